@@ -1,33 +1,342 @@
 #![no_std]
-use soroban_sdk::{contractimpl, contracttype, Address, BytesN, Env, IntoVal, RawVal};
+use soroban_sdk::{
+    contracterror, contractimpl, contracttype, serde::Serialize, Address, Bytes, BytesN, Env, String,
+    Symbol, Vec,
+};
 
 mod token {
     soroban_sdk::contractimport!(file = "./soroban_token_spec.wasm");
 }
 
+mod sbt {
+    soroban_sdk::contractimport!(file = "./soroban_sbt_contract.wasm");
+}
+
+mod nft {
+    soroban_sdk::contractimport!(file = "./nft/nft_soroban.wasm");
+}
+
+// The part of the auction contract's interface the campaign uses, declared
+// here as the auction wasm isn't part of this tree
+mod auction {
+    use soroban_sdk::{contractclient, contracttype, Address, Env};
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    #[contracttype]
+    pub enum AuctionState {
+        None,
+        Open,
+        Expired,
+        Settled,
+    }
+
+    #[derive(Clone, Debug)]
+    #[contracttype]
+    pub struct AuctionStatus {
+        pub state: AuctionState,
+        pub time_remaining: u64,
+        pub highest_bid: i128,
+        pub bid_count: u32,
+    }
+
+    #[contractclient(name = "Client")]
+    pub trait Auction {
+        fn auction_nft(env: Env, from: Address, token_id: u128, price: i128, expiration_date: u64);
+
+        fn sell_auctioned_nft(env: Env, owner: Address, token_id: u128, xlm_address: Address);
+
+        fn delist_auctioned_nft(env: Env, from: Address, token_id: u128, xlm_address: Address);
+
+        fn auction_status(env: Env, token_id: u128) -> AuctionStatus;
+    }
+}
+
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum CrowdfundError {
+    NotInitialized = 1,
+    AlreadyInitialized = 2,
+    InvalidAmount = 3,
+    SaleNotRunning = 4,
+    SaleStillRunning = 5,
+    RecipientCannotDeposit = 6,
+    RecipientCannotSponsor = 7,
+    RecipientCannotWithdraw = 8,
+    OnlyRecipientCanWithdraw = 9,
+    MemoTooLong = 10,
+    DepositNotFound = 11,
+    AlreadyCommitted = 12,
+    NoMatchingPool = 13,
+    AlreadyClaimed = 14,
+    BadgesNotConfigured = 15,
+    NotEligible = 16,
+    DisputesNotConfigured = 17,
+    DisputeWindowOpen = 18,
+    DisputeWindowClosed = 19,
+    WithdrawalFrozen = 20,
+    NoDispute = 21,
+    NoClaimableBalance = 22,
+    ClaimExpired = 23,
+    ClaimNotExpired = 24,
+    DonationNotFound = 25,
+    AlreadyListed = 26,
+    DonationNotListed = 27,
+    VestingNotConfigured = 28,
+    VestingHalted = 29,
+    AlreadyVoted = 30,
+    InvalidSplit = 31,
+    NotSplitRecipient = 32,
+}
+
 #[derive(Clone)]
 #[contracttype]
 pub enum DataKey {
+    Anonymous(BytesN<32>),
+    AnonymousRaised,
+    Arbiter,
+    BackerCount,
+    BadgeClaimed(Address),
+    Claimable(Address),
+    Badges,
+    ClosedAt,
     Deadline,
+    DeadlineMode,
+    Decimals,
+    Deposit(u32),
+    DepositCount,
+    Dispute,
+    Donated,
+    Donation(u32),
+    DonationCount,
+    DisputeConfig,
+    DisputeResolved,
+    FeeClaimed,
+    GoalReached,
+    Grace,
+    HaltRefunded(Address),
+    HaltVote(Address),
+    HaltVotes,
+    HaltedVested,
+    Matched,
+    ProtocolFee,
+    Raised,
     Recipient,
+    RecipientWithdrawn,
+    Refunded,
+    Sponsor(Address),
+    Sponsors,
+    Splits,
+    SplitWithdrawn(Address),
     Started,
     Target,
     Token,
     User(Address),
+    UserDeposit(Address, u32),
+    UserDepositCount(Address),
+    Vesting,
+    VestingWithdrawn,
+    WeightSnapshot,
+    WeightSnapshotted(Address),
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct DepositRecord {
+    pub user: Address,
+    pub amount: i128,
+    pub memo: Bytes,
+    pub timestamp: u64,
+    pub ledger: u32,
+}
+
+// Deposit receipt carrying the state after the deposit, user_total is the
+// backer's cumulative deposit and raised the campaign's new raised amount
+#[derive(Clone)]
+#[contracttype]
+pub struct DepositEvent {
+    pub user: Address,
+    pub amount: i128,
+    pub memo: Bytes,
+    pub user_total: i128,
+    pub raised: i128,
+}
+
+const MAX_MEMO_LEN: u32 = 64;
+
+// Rough ledger close time, to compare a timestamp with a ledger deadline
+const SECONDS_PER_LEDGER: u64 = 5;
+
+// Most deposit records returned by one deposits_of page
+const MAX_DEPOSIT_PAGE: u32 = 50;
+
+// Running hash over the voting weight of every backer with an address, in
+// order of their first deposit. Each step hashes the previous hash, the
+// serialized backer address and the weight as 16 big-endian bytes. The
+// snapshot is final once processed reaches the deposit count.
+#[derive(Clone)]
+#[contracttype]
+pub struct WeightSnapshot {
+    pub processed: u32,
+    pub backers: u32,
+    pub total_weight: i128,
+    pub hash: BytesN<32>,
+}
+
+// Deposit recorded under the backer's commitment, the sha256 of a secret
+// salt followed by the serialized refund address, instead of their address
+#[derive(Clone)]
+#[contracttype]
+pub struct AnonymousDepositEvent {
+    pub commitment: BytesN<32>,
+    pub amount: i128,
+    pub raised: i128,
+}
+
+// NFT donated to the campaign, auctioned through auction with the proceeds
+// credited to the raised amount once settled
+#[derive(Clone)]
+#[contracttype]
+pub struct DonationRecord {
+    pub donor: Address,
+    pub nft_contract: BytesN<32>,
+    pub token_id: u128,
+    pub auction: Option<BytesN<32>>,
+    pub settled: bool,
+}
+
+// Funds of a successful campaign vest to the recipient linearly over
+// duration after the deadline, with nothing vested before cliff. Both are in
+// the unit of the deadline.
+#[derive(Clone)]
+#[contracttype]
+pub struct VestingConfig {
+    pub cliff: u64,
+    pub duration: u64,
+}
+
+// Campaigns at min_bps of the target or more at the deadline keep running
+// for duration more, in the unit of the deadline, to close the gap
+#[derive(Clone)]
+#[contracttype]
+pub struct GraceConfig {
+    pub min_bps: u32,
+    pub duration: u64,
+}
+
+// Share of the payout owed to one of several recipients of a team campaign
+#[derive(Clone)]
+#[contracttype]
+pub struct PayoutSplit {
+    pub recipient: Address,
+    pub share_bps: u32,
+}
+
+// Share of the deposited value whose backers have to vote to halt vesting
+const HALT_SUPERMAJORITY_BPS: i128 = 6_667;
+
+// Decimals the display getters normalize amounts to, those of native XLM
+const DISPLAY_DECIMALS: u32 = 7;
+
+// Refund of an expired campaign swept out of the deposit ledger. Like a
+// Stellar claimable balance it stays with the contract until the backer
+// claims it or it expires, when it is returned to the backer; the backer's
+// money never goes to the recipient.
+#[derive(Clone)]
+#[contracttype]
+pub struct ClaimableBalance {
+    pub amount: i128,
+    pub expires_at: u64,
+}
+
+// How long a swept refund stays claimable, a year
+const CLAIM_EXPIRY: u64 = 365 * 24 * 60 * 60;
+
+// Backers who deposited at least min_amount qualify for the badge at uri
+#[derive(Clone)]
+#[contracttype]
+pub struct BadgeTier {
+    pub min_amount: i128,
+    pub uri: String,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct BadgeConfig {
+    pub sbt_contract: BytesN<32>,
+    pub tiers: Vec<BadgeTier>,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct MatchPool {
+    pub ratio_bps: u32,
+    pub cap: i128,
+    pub matched: i128,
+    pub refunded: bool,
+}
+
+// After a successful deadline the recipient has to wait out window, during
+// which a backer holding at least threshold_bps of the deposits can freeze
+// the withdrawal until the arbiter resolves the dispute
+#[derive(Clone)]
+#[contracttype]
+pub struct DisputeConfig {
+    pub arbiter: Address,
+    pub window: u64,
+    pub threshold_bps: u32,
+}
+
+// Share of a successful campaign owed to the protocol, claimed by collector
+#[derive(Clone)]
+#[contracttype]
+pub struct ProtocolFee {
+    pub collector: Address,
+    pub fee_bps: u32,
+    pub claimed: bool,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct ProgressEvent {
+    pub raised: i128,
+    pub target: i128,
+    pub pct_bps: u32,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct GoalReachedEvent {
+    pub raised: i128,
+    pub target: i128,
+    pub deposit_id: u32,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
-#[repr(u32)]
+#[contracttype]
 pub enum State {
-    Running = 0,
-    Success = 1,
-    Expired = 2,
+    Running,
+    Success,
+    Expired,
 }
 
-impl IntoVal<Env, RawVal> for State {
-    fn into_val(&self, env: &Env) -> RawVal {
-        (*self as u32).into_val(env)
-    }
+// Unit the deadline and start of a campaign are expressed in
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[contracttype]
+pub enum DeadlineMode {
+    Timestamp,
+    LedgerSequence,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct CampaignStatus {
+    pub state: State,
+    pub raised: i128,
+    pub target: i128,
+    pub deadline: u64,
+    pub started: u64,
+    pub backer_count: u32,
 }
 
 // Retrieves the current timestamp from the blockchain ledger.
@@ -35,43 +344,105 @@ fn get_ledger_timestamp(e: &Env) -> u64 {
     e.ledger().timestamp()
 }
 
+// Retrieves the unit this campaign measures its deadline in
+fn get_deadline_mode(e: &Env) -> DeadlineMode {
+    e.storage()
+        .get(&DataKey::DeadlineMode)
+        .unwrap_or(Ok(DeadlineMode::Timestamp))
+        .unwrap()
+}
+
+// Retrieves the current ledger time in the unit of the campaign deadline
+fn get_current_time(e: &Env) -> u64 {
+    match get_deadline_mode(e) {
+        DeadlineMode::Timestamp => get_ledger_timestamp(e),
+        DeadlineMode::LedgerSequence => e.ledger().sequence() as u64,
+    }
+}
+
+// Retrieves the deadline as a timestamp, estimated for a ledger deadline
+fn get_deadline_timestamp(e: &Env) -> Result<u64, CrowdfundError> {
+    let deadline = get_deadline(e)?;
+    match get_deadline_mode(e) {
+        DeadlineMode::Timestamp => Ok(deadline),
+        DeadlineMode::LedgerSequence => {
+            let ledgers_left = deadline.saturating_sub(e.ledger().sequence() as u64);
+            Ok(get_ledger_timestamp(e).saturating_add(ledgers_left * SECONDS_PER_LEDGER))
+        }
+    }
+}
+
 // Retrieves the address of the recipient
-fn get_recipient(e: &Env) -> Address {
+fn get_recipient(e: &Env) -> Result<Address, CrowdfundError> {
     e.storage()
         .get(&DataKey::Recipient)
-        .expect("not initialized")
-        .unwrap()
+        .ok_or(CrowdfundError::NotInitialized)
+        .map(|value| value.unwrap())
 }
 
 // Retrieves the timestamp of the deadline
-fn get_deadline(e: &Env) -> u64 {
+fn get_deadline(e: &Env) -> Result<u64, CrowdfundError> {
     e.storage()
         .get(&DataKey::Deadline)
-        .expect("not initialized")
-        .unwrap()
+        .ok_or(CrowdfundError::NotInitialized)
+        .map(|value| value.unwrap())
 }
 
 // Retrieves the timestamp of when this fund started
-fn get_started(e: &Env) -> u64 {
+fn get_started(e: &Env) -> Result<u64, CrowdfundError> {
     e.storage()
         .get(&DataKey::Started)
-        .expect("not initialized")
-        .unwrap()
+        .ok_or(CrowdfundError::NotInitialized)
+        .map(|value| value.unwrap())
 }
 
 // Retrieves the total amount to complete this fund
-fn get_target_amount(e: &Env) -> i128 {
+fn get_target_amount(e: &Env) -> Result<i128, CrowdfundError> {
     e.storage()
         .get(&DataKey::Target)
-        .expect("not initialized")
-        .unwrap()
+        .ok_or(CrowdfundError::NotInitialized)
+        .map(|value| value.unwrap())
 }
 
 // Retrieves the token that is being used for this fund
-fn get_token(e: &Env) -> BytesN<32> {
+fn get_token(e: &Env) -> Result<BytesN<32>, CrowdfundError> {
     e.storage()
         .get(&DataKey::Token)
-        .expect("not initialized")
+        .ok_or(CrowdfundError::NotInitialized)
+        .map(|value| value.unwrap())
+}
+
+// Retrieves the decimals of the fund's token recorded at initialization,
+// campaigns initialized before they were recorded assume display decimals
+fn get_decimals(e: &Env) -> u32 {
+    e.storage()
+        .get(&DataKey::Decimals)
+        .unwrap_or(Ok(DISPLAY_DECIMALS))
+        .unwrap()
+}
+
+// Rescales a token amount to DISPLAY_DECIMALS, truncating extra precision
+fn to_display(e: &Env, amount: i128) -> Result<i128, CrowdfundError> {
+    let decimals = get_decimals(e);
+
+    if decimals >= DISPLAY_DECIMALS {
+        let divisor = 10i128
+            .checked_pow(decimals - DISPLAY_DECIMALS)
+            .ok_or(CrowdfundError::InvalidAmount)?;
+        Ok(amount / divisor)
+    } else {
+        10i128
+            .checked_pow(DISPLAY_DECIMALS - decimals)
+            .and_then(|factor| amount.checked_mul(factor))
+            .ok_or(CrowdfundError::InvalidAmount)
+    }
+}
+
+// Retrieves the number of deposits recorded for a specific user
+fn get_user_deposit_count(e: &Env, user: &Address) -> u32 {
+    e.storage()
+        .get(&DataKey::UserDepositCount(user.clone()))
+        .unwrap_or(Ok(0))
         .unwrap()
 }
 
@@ -83,27 +454,315 @@ fn get_user_deposited(e: &Env, user: &Address) -> i128 {
         .unwrap()
 }
 
-// Retrieves the current balance by contract id
-fn get_balance(e: &Env, contract_id: &BytesN<32>) -> i128 {
-    let client = token::Client::new(e, contract_id);
-    client.balance(&e.current_contract_address())
+// Retrieves the number of distinct users that have deposited
+fn get_backer_count(e: &Env) -> u32 {
+    e.storage()
+        .get(&DataKey::BackerCount)
+        .unwrap_or(Ok(0))
+        .unwrap()
+}
+
+// Retrieves the sponsors that have committed a matching pool
+fn get_sponsors(e: &Env) -> Vec<Address> {
+    e.storage()
+        .get(&DataKey::Sponsors)
+        .unwrap_or(Ok(Vec::new(e)))
+        .unwrap()
+}
+
+// Retrieves the matching pool committed by a specific sponsor
+fn get_match_pool(e: &Env, sponsor: &Address) -> Option<MatchPool> {
+    e.storage()
+        .get(&DataKey::Sponsor(sponsor.clone()))
+        .map(|pool| pool.unwrap())
+}
+
+// Retrieves the total of all recorded deposits
+fn get_deposited(e: &Env) -> i128 {
+    e.storage()
+        .get(&DataKey::Raised)
+        .unwrap_or(Ok(0))
+        .unwrap()
+}
+
+// Retrieves the total of all accrued sponsor matches
+fn get_matched(e: &Env) -> i128 {
+    e.storage()
+        .get(&DataKey::Matched)
+        .unwrap_or(Ok(0))
+        .unwrap()
+}
+
+// Retrieves the amount raised for the recipient, deposits plus accrued matches.
+// Derived from internal accounting only, so stray transfers to the contract
+// don't count towards the target.
+fn get_raised(e: &Env) -> i128 {
+    get_deposited(e) + get_matched(e) + get_donated(e)
+}
+
+// Retrieves the total proceeds of settled NFT donation auctions
+fn get_donated(e: &Env) -> i128 {
+    e.storage()
+        .get(&DataKey::Donated)
+        .unwrap_or(Ok(0))
+        .unwrap()
+}
+
+// Retrieves a donated NFT
+fn get_donation(e: &Env, donation_id: u32) -> Result<DonationRecord, CrowdfundError> {
+    e.storage()
+        .get(&DataKey::Donation(donation_id))
+        .ok_or(CrowdfundError::DonationNotFound)
+        .map(|record| record.unwrap())
+}
+
+// Retrieves the campaign's own balance of its token
+fn get_token_balance(e: &Env) -> Result<i128, CrowdfundError> {
+    let client = token::Client::new(e, &get_token(e)?);
+    Ok(client.balance(&e.current_contract_address()))
+}
+
+// Accrues match liabilities of every sponsor pool for a new deposit
+fn accrue_matches(e: &Env, amount: i128) {
+    let mut total_matched = get_matched(e);
+
+    for sponsor in get_sponsors(e).iter() {
+        let sponsor = sponsor.unwrap();
+        let mut pool = get_match_pool(e, &sponsor).unwrap();
+        let mut matched = amount * pool.ratio_bps as i128 / 10_000;
+
+        if matched > pool.cap - pool.matched {
+            matched = pool.cap - pool.matched;
+        }
+        if matched == 0 {
+            continue;
+        }
+
+        pool.matched += matched;
+        total_matched += matched;
+        e.storage().set(&DataKey::Sponsor(sponsor), &pool);
+    }
+
+    e.storage().set(&DataKey::Matched, &total_matched);
+}
+
+// Retrieves the dispute settings of the campaign, if any
+fn get_dispute_config(e: &Env) -> Option<DisputeConfig> {
+    e.storage()
+        .get(&DataKey::DisputeConfig)
+        .map(|config| config.unwrap())
+}
+
+// Retrieves the protocol fee settings of the campaign, if any
+fn get_protocol_fee(e: &Env) -> Option<ProtocolFee> {
+    e.storage()
+        .get(&DataKey::ProtocolFee)
+        .map(|fee| fee.unwrap())
+}
+
+// Retrieves the amount of the raised funds owed to the protocol
+fn get_protocol_fee_amount(e: &Env) -> i128 {
+    match get_protocol_fee(e) {
+        Some(fee) => get_raised(e) * fee.fee_bps as i128 / 10_000,
+        None => 0,
+    }
+}
+
+// Retrieves the amount of the protocol fee transferred to the collector so far
+fn get_fee_claimed(e: &Env) -> i128 {
+    e.storage()
+        .get(&DataKey::FeeClaimed)
+        .unwrap_or(Ok(0))
+        .unwrap()
+}
+
+// Retrieves the amount paid out to the recipient of a successful campaign
+fn get_payout_total(e: &Env) -> i128 {
+    get_raised(e) - get_protocol_fee_amount(e)
+}
+
+// Retrieves the vesting schedule of the campaign, if any
+fn get_vesting(e: &Env) -> Option<VestingConfig> {
+    e.storage()
+        .get(&DataKey::Vesting)
+        .map(|config| config.unwrap())
+}
+
+// Retrieves the amount the recipient has withdrawn so far
+fn get_vesting_withdrawn(e: &Env) -> i128 {
+    e.storage()
+        .get(&DataKey::VestingWithdrawn)
+        .unwrap_or(Ok(0))
+        .unwrap()
+}
+
+// Retrieves the amount vested to the recipient, frozen once backers halt vesting
+fn get_vested(e: &Env) -> Result<i128, CrowdfundError> {
+    if let Some(vested) = e.storage().get(&DataKey::HaltedVested) {
+        return Ok(vested.unwrap());
+    }
+
+    let total = get_payout_total(e);
+    let config = match get_vesting(e) {
+        Some(config) => config,
+        None => return Ok(total),
+    };
+
+    let elapsed = get_current_time(e).saturating_sub(get_closed_at(e)?);
+    if elapsed < config.cliff {
+        return Ok(0);
+    }
+    if elapsed >= config.duration {
+        return Ok(total);
+    }
+    Ok(total * elapsed as i128 / config.duration as i128)
+}
+
+// Retrieves the payout splits of the campaign, empty when the recipient is paid alone
+fn get_splits(e: &Env) -> Vec<PayoutSplit> {
+    e.storage()
+        .get(&DataKey::Splits)
+        .unwrap_or(Ok(Vec::new(e)))
+        .unwrap()
+}
+
+// Retrieves the amount a split recipient has withdrawn so far
+fn get_split_withdrawn(e: &Env, recipient: &Address) -> i128 {
+    e.storage()
+        .get(&DataKey::SplitWithdrawn(recipient.clone()))
+        .unwrap_or(Ok(0))
+        .unwrap()
+}
+
+// Retrieves the amount vested to a split recipient and not yet withdrawn
+fn get_split_available(e: &Env, recipient: &Address) -> Result<i128, CrowdfundError> {
+    let vested = get_vested(e)?;
+    for split in get_splits(e).iter() {
+        let split = split.unwrap();
+        if split.recipient == *recipient {
+            let entitled = vested * split.share_bps as i128 / 10_000;
+            return Ok(entitled - get_split_withdrawn(e, recipient));
+        }
+    }
+    Err(CrowdfundError::NotSplitRecipient)
 }
 
 // Retrieves the current state of the crowd funding contract based on its stored values
-fn get_state(e: &Env) -> State {
-    let deadline = get_deadline(e);
-    let target_amount = get_target_amount(e);
-    let token_id = get_token(e);
-    let token_balance = get_balance(e, &token_id);
-    let current_timestamp = get_ledger_timestamp(e);
-
-    if current_timestamp < deadline {
-        return State::Running;
+fn get_state(e: &Env) -> Result<State, CrowdfundError> {
+    let deadline = get_deadline(e)?;
+    let target_amount = get_target_amount(e)?;
+    let raised = get_raised(e);
+    let current_time = get_current_time(e);
+
+    if current_time < deadline || in_grace_period(e, deadline, raised, target_amount) {
+        return Ok(State::Running);
+    };
+    // A dispute resolved in favour of the backers refunds the campaign
+    if e.storage().has(&DataKey::Refunded) {
+        return Ok(State::Expired);
     };
-    if token_balance >= target_amount {
-        return State::Success;
+    if raised >= target_amount {
+        return Ok(State::Success);
     };
-    State::Expired
+    Ok(State::Expired)
+}
+
+// Whether a campaign past its deadline is still closing the gap to its
+// target under the grace rule
+fn in_grace_period(e: &Env, deadline: u64, raised: i128, target_amount: i128) -> bool {
+    let grace = match get_grace(e) {
+        Some(grace) => grace,
+        None => return false,
+    };
+
+    raised < target_amount
+        && raised.saturating_mul(10_000) >= target_amount.saturating_mul(grace.min_bps as i128)
+        && get_current_time(e) < deadline.saturating_add(grace.duration)
+}
+
+// Retrieves when a successful campaign closed, the deadline unless it
+// reached its target during the grace period
+fn get_closed_at(e: &Env) -> Result<u64, CrowdfundError> {
+    match e.storage().get(&DataKey::ClosedAt) {
+        Some(closed_at) => Ok(closed_at.unwrap()),
+        None => get_deadline(e),
+    }
+}
+
+// Records the close of a campaign that just reached its target in grace
+fn record_grace_close(e: &Env) -> Result<(), CrowdfundError> {
+    let now = get_current_time(e);
+    if now >= get_deadline(e)? && get_raised(e) >= get_target_amount(e)? {
+        e.storage().set(&DataKey::ClosedAt, &now);
+    }
+    Ok(())
+}
+
+// Retrieves the grace rule of the campaign, if any
+fn get_grace(e: &Env) -> Option<GraceConfig> {
+    e.storage()
+        .get(&DataKey::Grace)
+        .map(|config| config.unwrap())
+}
+
+// Retrieves the voting weight snapshot built so far
+fn get_weight_snapshot(e: &Env) -> WeightSnapshot {
+    e.storage()
+        .get(&DataKey::WeightSnapshot)
+        .unwrap_or(Ok(WeightSnapshot {
+            processed: 0,
+            backers: 0,
+            total_weight: 0,
+            hash: BytesN::from_array(e, &[0; 32]),
+        }))
+        .unwrap()
+}
+
+// Retrieves the amount deposited under an anonymous commitment
+fn get_anonymous_deposited(e: &Env, commitment: &BytesN<32>) -> i128 {
+    e.storage()
+        .get(&DataKey::Anonymous(commitment.clone()))
+        .unwrap_or(Ok(0))
+        .unwrap()
+}
+
+// Reopens a completed payout after late proceeds raised the payout total,
+// so the recipient and collector can withdraw what they are now owed
+fn reopen_payout(e: &Env) {
+    e.storage().remove(&DataKey::RecipientWithdrawn);
+    if let Some(mut fee) = get_protocol_fee(e) {
+        fee.claimed = false;
+        e.storage().set(&DataKey::ProtocolFee, &fee);
+    }
+}
+
+// Fails while a dispute is pending or can still be raised, since either may
+// end in refunding the campaign
+fn check_dispute_settled(e: &Env) -> Result<(), CrowdfundError> {
+    if e.storage().has(&DataKey::Dispute) {
+        return Err(CrowdfundError::WithdrawalFrozen);
+    }
+    if let Some(config) = get_dispute_config(e) {
+        if !e.storage().has(&DataKey::DisputeResolved)
+            && get_current_time(e) < get_closed_at(e)? + config.window
+        {
+            return Err(CrowdfundError::DisputeWindowOpen);
+        }
+    }
+    Ok(())
+}
+
+// Retrieves the total of all anonymous deposits
+fn get_anonymous_raised(e: &Env) -> i128 {
+    e.storage()
+        .get(&DataKey::AnonymousRaised)
+        .unwrap_or(Ok(0))
+        .unwrap()
+}
+
+// Deposits of backers with an address, the weight behind halt votes and refunds
+fn get_named_deposited(e: &Env) -> i128 {
+    get_deposited(e) - get_anonymous_raised(e)
 }
 
 // Updates the amount of tokens depositied by a specific user
@@ -111,11 +770,110 @@ fn set_user_deposited(e: &Env, user: &Address, amount: &i128) {
     e.storage().set(&DataKey::User(user.clone()), amount);
 }
 
+// Retrieves the number of deposits recorded so far
+fn get_deposit_count(e: &Env) -> u32 {
+    e.storage()
+        .get(&DataKey::DepositCount)
+        .unwrap_or(Ok(0))
+        .unwrap()
+}
+
+// Records a deposit, accrues sponsor matches and pulls the tokens from the user
+fn make_deposit(e: &Env, user: &Address, amount: i128, memo: Bytes) -> Result<(), CrowdfundError> {
+    if amount <= 0 {
+        return Err(CrowdfundError::InvalidAmount);
+    }
+    if get_state(e)? != State::Running {
+        return Err(CrowdfundError::SaleNotRunning);
+    }
+    if *user == get_recipient(e)? {
+        return Err(CrowdfundError::RecipientCannotDeposit);
+    }
+
+    let balance = get_user_deposited(e, user);
+    if balance == 0 {
+        e.storage()
+            .set(&DataKey::BackerCount, &(get_backer_count(e) + 1));
+    }
+    set_user_deposited(e, user, &(balance + amount));
+    e.storage()
+        .set(&DataKey::Raised, &(get_deposited(e) + amount));
+    accrue_matches(e, amount);
+    record_grace_close(e)?;
+
+    let deposit_id = get_deposit_count(e) + 1;
+    let record = DepositRecord {
+        user: user.clone(),
+        amount,
+        memo,
+        timestamp: e.ledger().timestamp(),
+        ledger: e.ledger().sequence(),
+    };
+    e.storage().set(&DataKey::Deposit(deposit_id), &record);
+    e.storage().set(&DataKey::DepositCount, &deposit_id);
+
+    // Per-backer index into the deposit ledger for audit exports
+    let user_count = get_user_deposit_count(e, user);
+    e.storage()
+        .set(&DataKey::UserDeposit(user.clone(), user_count), &deposit_id);
+    e.storage()
+        .set(&DataKey::UserDepositCount(user.clone()), &(user_count + 1));
+
+    let client = token::Client::new(e, &get_token(e)?);
+    client.xfer(user, &e.current_contract_address(), &amount);
+
+    let receipt = DepositEvent {
+        user: record.user,
+        amount,
+        memo: record.memo,
+        user_total: balance + amount,
+        raised: get_raised(e),
+    };
+    e.events()
+        .publish((Symbol::short("deposit"), deposit_id), receipt);
+    publish_progress(e, deposit_id)
+}
+
+// Publishes the funding progress after a deposit, and the goal reached
+// event the first time the target is crossed
+fn publish_progress(e: &Env, deposit_id: u32) -> Result<(), CrowdfundError> {
+    let raised = get_raised(e);
+    let target = get_target_amount(e)?;
+    let pct_bps = if target <= 0 {
+        10_000
+    } else {
+        (raised * 10_000 / target).min(u32::MAX as i128) as u32
+    };
+
+    e.events().publish(
+        (Symbol::short("progress"),),
+        ProgressEvent {
+            raised,
+            target,
+            pct_bps,
+        },
+    );
+
+    if raised >= target && !e.storage().has(&DataKey::GoalReached) {
+        e.storage().set(&DataKey::GoalReached, &true);
+        e.events().publish(
+            (Symbol::short("goal"),),
+            GoalReachedEvent {
+                raised,
+                target,
+                deposit_id,
+            },
+        );
+    }
+    Ok(())
+}
+
 // Transfer tokens from the contract to the recipient
-fn transfer(e: &Env, to: &Address, amount: &i128) {
-    let token_contract_id = &get_token(e);
+fn transfer(e: &Env, to: &Address, amount: &i128) -> Result<(), CrowdfundError> {
+    let token_contract_id = &get_token(e)?;
     let client = token::Client::new(e, token_contract_id);
     client.xfer(&e.current_contract_address(), to, amount);
+    Ok(())
 }
 
 struct Crowdfund;
@@ -129,98 +887,1001 @@ impl Crowdfund {
         deadline: u64,
         target_amount: i128,
         token: BytesN<32>,
-    ) {
-        assert!(!e.storage().has(&DataKey::Recipient), "already initialized");
+    ) -> Result<(), CrowdfundError> {
+        Self::initialize_with_mode(
+            e,
+            recipient,
+            deadline,
+            target_amount,
+            token,
+            DeadlineMode::Timestamp,
+        )
+    }
+
+    // Initialize a campaign whose deadline is either a timestamp or a ledger sequence
+    pub fn initialize_with_mode(
+        e: Env,
+        recipient: Address,
+        deadline: u64,
+        target_amount: i128,
+        token: BytesN<32>,
+        deadline_mode: DeadlineMode,
+    ) -> Result<(), CrowdfundError> {
+        if e.storage().has(&DataKey::Recipient) {
+            return Err(CrowdfundError::AlreadyInitialized);
+        }
 
         e.storage().set(&DataKey::Recipient, &recipient);
+        e.storage().set(&DataKey::DeadlineMode, &deadline_mode);
         e.storage()
-            .set(&DataKey::Started, &get_ledger_timestamp(&e));
+            .set(&DataKey::Started, &get_current_time(&e));
         e.storage().set(&DataKey::Deadline, &deadline);
         e.storage().set(&DataKey::Target, &target_amount);
         e.storage().set(&DataKey::Token, &token);
+
+        let client = token::Client::new(&e, &token);
+        e.storage().set(&DataKey::Decimals, &client.decimals());
+        Ok(())
+    }
+
+    // Initialize a campaign that owes fee_bps of a successful raise to the
+    // collector, usually the factory that deployed it, which also names the
+    // arbiter of disputes. The grace rule, if any, is fixed here so backers
+    // know it when they deposit
+    #[allow(clippy::too_many_arguments)]
+    pub fn initialize_with_fee(
+        e: Env,
+        recipient: Address,
+        deadline: u64,
+        target_amount: i128,
+        token: BytesN<32>,
+        deadline_mode: DeadlineMode,
+        collector: Address,
+        fee_bps: u32,
+        arbiter: Address,
+        grace: Option<GraceConfig>,
+    ) -> Result<(), CrowdfundError> {
+        if fee_bps > 10_000 {
+            return Err(CrowdfundError::InvalidAmount);
+        }
+        if let Some(grace) = &grace {
+            if grace.min_bps == 0 || grace.min_bps >= 10_000 || grace.duration == 0 {
+                return Err(CrowdfundError::InvalidAmount);
+            }
+        }
+
+        Self::initialize_with_mode(
+            e.clone(),
+            recipient,
+            deadline,
+            target_amount,
+            token,
+            deadline_mode,
+        )?;
+
+        let fee = ProtocolFee {
+            collector,
+            fee_bps,
+            claimed: false,
+        };
+        e.storage().set(&DataKey::ProtocolFee, &fee);
+        e.storage().set(&DataKey::Arbiter, &arbiter);
+        if let Some(grace) = grace {
+            e.storage().set(&DataKey::Grace, &grace);
+        }
+        Ok(())
+    }
+
+    pub fn protocol_fee(e: Env) -> Option<ProtocolFee> {
+        get_protocol_fee(&e)
+    }
+
+    // Transfer the protocol's share of a successful campaign to the collector
+    pub fn claim_protocol_fee(e: Env) -> Result<i128, CrowdfundError> {
+        let mut fee = get_protocol_fee(&e).ok_or(CrowdfundError::NotEligible)?;
+        fee.collector.require_auth();
+
+        match get_state(&e)? {
+            State::Running => return Err(CrowdfundError::SaleStillRunning),
+            State::Expired => return Err(CrowdfundError::NotEligible),
+            State::Success => {}
+        }
+        if fee.claimed {
+            return Err(CrowdfundError::AlreadyClaimed);
+        }
+        check_dispute_settled(&e)?;
+
+        let amount = get_protocol_fee_amount(&e) - get_fee_claimed(&e);
+        fee.claimed = true;
+        e.storage().set(&DataKey::ProtocolFee, &fee);
+        e.storage().set(&DataKey::FeeClaimed, &(get_fee_claimed(&e) + amount));
+        transfer(&e, &fee.collector, &amount)?;
+        Ok(amount)
     }
 
-    pub fn recipient(e: Env) -> Address {
+    pub fn recipient(e: Env) -> Result<Address, CrowdfundError> {
         get_recipient(&e)
     }
 
-    pub fn deadline(e: Env) -> u64 {
+    pub fn deadline(e: Env) -> Result<u64, CrowdfundError> {
         get_deadline(&e)
     }
 
-    pub fn started(e: Env) -> u64 {
+    pub fn started(e: Env) -> Result<u64, CrowdfundError> {
         get_started(&e)
     }
 
-    pub fn state(e: Env) -> u32 {
-        get_state(&e) as u32
+    pub fn deadline_mode(e: Env) -> DeadlineMode {
+        get_deadline_mode(&e)
+    }
+
+    pub fn state(e: Env) -> Result<State, CrowdfundError> {
+        get_state(&e)
+    }
+
+    // Whether the campaign has ended and no dispute can still refund it
+    pub fn outcome_final(e: Env) -> Result<bool, CrowdfundError> {
+        match get_state(&e)? {
+            State::Running => Ok(false),
+            State::Expired => Ok(true),
+            State::Success => Ok(check_dispute_settled(&e).is_ok()),
+        }
+    }
+
+    // Returns every value a frontend needs to render the campaign in one call
+    pub fn get_status(e: Env) -> Result<CampaignStatus, CrowdfundError> {
+        Ok(CampaignStatus {
+            state: get_state(&e)?,
+            raised: get_raised(&e),
+            target: get_target_amount(&e)?,
+            deadline: get_deadline(&e)?,
+            started: get_started(&e)?,
+            backer_count: get_backer_count(&e),
+        })
     }
 
-    pub fn target(e: Env) -> i128 {
+    pub fn target(e: Env) -> Result<i128, CrowdfundError> {
         get_target_amount(&e)
     }
 
-    pub fn token(e: Env) -> BytesN<32> {
+    pub fn token(e: Env) -> Result<BytesN<32>, CrowdfundError> {
         get_token(&e)
     }
 
-    pub fn balance(e: Env, user: Address) -> i128 {
-        let recipient = get_recipient(&e);
-        if get_state(&e) == State::Success {
-            if user != recipient {
-                return 0;
+    // Amount raised for the recipient from recorded deposits and sponsor matches
+    pub fn raised(e: Env) -> i128 {
+        get_raised(&e)
+    }
+
+    pub fn decimals(e: Env) -> u32 {
+        get_decimals(&e)
+    }
+
+    // Target and raised amounts normalized to DISPLAY_DECIMALS, so campaigns
+    // in tokens with different decimals render on the same scale
+    pub fn target_display(e: Env) -> Result<i128, CrowdfundError> {
+        to_display(&e, get_target_amount(&e)?)
+    }
+
+    pub fn raised_display(e: Env) -> Result<i128, CrowdfundError> {
+        to_display(&e, get_raised(&e))
+    }
+
+    pub fn balance(e: Env, user: Address) -> Result<i128, CrowdfundError> {
+        let recipient = get_recipient(&e)?;
+        if get_state(&e)? == State::Success {
+            if !get_splits(&e).is_empty() {
+                return Ok(get_split_available(&e, &user).unwrap_or(0));
+            }
+            if user != recipient || e.storage().has(&DataKey::RecipientWithdrawn) {
+                return Ok(0);
             };
-            return get_balance(&e, &get_token(&e));
+            return Ok(get_vested(&e)? - get_vesting_withdrawn(&e));
         };
 
-        get_user_deposited(&e, &user)
+        Ok(get_user_deposited(&e, &user))
     }
 
     // Deposit tokens into the contract and record the sender
-    pub fn deposit(e: Env, user: Address, amount: i128) {
+    pub fn deposit(e: Env, user: Address, amount: i128) -> Result<(), CrowdfundError> {
+        user.require_auth();
+        make_deposit(&e, &user, amount, Bytes::new(&e))
+    }
+
+    // Deposit tokens with a short memo or designation code for reconciliation
+    pub fn deposit_with_memo(
+        e: Env,
+        user: Address,
+        amount: i128,
+        memo: Bytes,
+    ) -> Result<(), CrowdfundError> {
         user.require_auth();
-        assert!(amount > 0, "amount must be positive");
-        assert!(get_state(&e) == State::Running, "sale is not running");
+        if memo.len() > MAX_MEMO_LEN {
+            return Err(CrowdfundError::MemoTooLong);
+        }
+        make_deposit(&e, &user, amount, memo)
+    }
+
+    // Deposit keyed by a commitment rather than the payer's address. The
+    // backer keeps the salt to claim a refund, to the address the commitment
+    // binds, if the campaign fails; anonymous deposits carry no dispute or
+    // halt voting rights and no share of a halt refund.
+    pub fn deposit_anonymous(
+        e: Env,
+        payer: Address,
+        commitment: BytesN<32>,
+        amount: i128,
+    ) -> Result<(), CrowdfundError> {
+        payer.require_auth();
+        if amount <= 0 {
+            return Err(CrowdfundError::InvalidAmount);
+        }
+        if get_state(&e)? != State::Running {
+            return Err(CrowdfundError::SaleNotRunning);
+        }
+        if payer == get_recipient(&e)? {
+            return Err(CrowdfundError::RecipientCannotDeposit);
+        }
+
+        let balance = get_anonymous_deposited(&e, &commitment);
+        if balance == 0 {
+            e.storage()
+                .set(&DataKey::BackerCount, &(get_backer_count(&e) + 1));
+        }
+        e.storage()
+            .set(&DataKey::Anonymous(commitment.clone()), &(balance + amount));
+        e.storage()
+            .set(&DataKey::Raised, &(get_deposited(&e) + amount));
+        e.storage()
+            .set(&DataKey::AnonymousRaised, &(get_anonymous_raised(&e) + amount));
+        accrue_matches(&e, amount);
+        record_grace_close(&e)?;
+
+        let client = token::Client::new(&e, &get_token(&e)?);
+        client.xfer(&payer, &e.current_contract_address(), &amount);
+
+        e.events().publish(
+            (Symbol::short("deposit"), Symbol::short("anonymous")),
+            AnonymousDepositEvent {
+                commitment,
+                amount,
+                raised: get_raised(&e),
+            },
+        );
+        // Anonymous deposits have no deposit record, so no deposit id
+        publish_progress(&e, 0)
+    }
+
+    pub fn anonymous_balance(e: Env, commitment: BytesN<32>) -> i128 {
+        get_anonymous_deposited(&e, &commitment)
+    }
+
+    // Refunds an anonymous deposit of a failed campaign to the address its
+    // commitment binds, so a revealed salt can't be replayed to another address
+    pub fn withdraw_anonymous(e: Env, salt: BytesN<32>, to: Address) -> Result<(), CrowdfundError> {
+        match get_state(&e)? {
+            State::Running => return Err(CrowdfundError::SaleStillRunning),
+            State::Success => return Err(CrowdfundError::NotEligible),
+            State::Expired => {}
+        }
+
+        let mut preimage = Bytes::from_array(&e, &salt.to_array());
+        preimage.append(&to.serialize(&e));
+        let commitment = e.crypto().sha256(&preimage);
+        let balance = get_anonymous_deposited(&e, &commitment);
+        if balance == 0 {
+            return Err(CrowdfundError::NoClaimableBalance);
+        }
+
+        e.storage().set(&DataKey::Anonymous(commitment), &0_i128);
+        transfer(&e, &to, &balance)
+    }
+
+    // Voting weight of a backer in a successful campaign, their confirmed
+    // deposits as of the deadline
+    pub fn voting_weight(e: Env, user: Address) -> Result<i128, CrowdfundError> {
+        if get_state(&e)? != State::Success {
+            return Err(CrowdfundError::NotEligible);
+        }
+        Ok(get_user_deposited(&e, &user))
+    }
+
+    // Extends the weight snapshot by up to limit deposit records. Anyone can
+    // call it once the campaign succeeded, until every record is processed
+    pub fn snapshot_weights(e: Env, limit: u32) -> Result<WeightSnapshot, CrowdfundError> {
+        if get_state(&e)? != State::Success {
+            return Err(CrowdfundError::NotEligible);
+        }
+
+        let mut snapshot = get_weight_snapshot(&e);
+        let end = snapshot.processed.saturating_add(limit).min(get_deposit_count(&e));
+
+        for deposit_id in (snapshot.processed + 1)..=end {
+            let record: DepositRecord = e.storage().get(&DataKey::Deposit(deposit_id)).unwrap().unwrap();
+            let key = DataKey::WeightSnapshotted(record.user.clone());
+            if e.storage().has(&key) {
+                continue;
+            }
+
+            let weight = get_user_deposited(&e, &record.user);
+            let mut preimage = Bytes::from_array(&e, &snapshot.hash.to_array());
+            preimage.append(&record.user.serialize(&e));
+            preimage.append(&Bytes::from_array(&e, &weight.to_be_bytes()));
+
+            snapshot.hash = e.crypto().sha256(&preimage);
+            snapshot.backers += 1;
+            snapshot.total_weight += weight;
+            e.storage().set(&key, &true);
+        }
+
+        snapshot.processed = end;
+        e.storage().set(&DataKey::WeightSnapshot, &snapshot);
+        Ok(snapshot)
+    }
+
+    pub fn weight_snapshot(e: Env) -> WeightSnapshot {
+        get_weight_snapshot(&e)
+    }
+
+    pub fn deposit_count(e: Env) -> u32 {
+        get_deposit_count(&e)
+    }
+
+    pub fn get_deposit(e: Env, deposit_id: u32) -> Result<DepositRecord, CrowdfundError> {
+        e.storage()
+            .get(&DataKey::Deposit(deposit_id))
+            .ok_or(CrowdfundError::DepositNotFound)
+            .map(|record| record.unwrap())
+    }
+
+    pub fn user_deposit_count(e: Env, user: Address) -> u32 {
+        get_user_deposit_count(&e, &user)
+    }
+
+    // Individual deposits of a backer in deposit order, for source-of-funds
+    // review. Pages hold at most MAX_DEPOSIT_PAGE records.
+    pub fn deposits_of(
+        e: Env,
+        user: Address,
+        start: u32,
+        limit: u32,
+    ) -> Result<Vec<DepositRecord>, CrowdfundError> {
+        let end = start
+            .saturating_add(limit.min(MAX_DEPOSIT_PAGE))
+            .min(get_user_deposit_count(&e, &user));
+        let mut records = Vec::new(&e);
+
+        for index in start..end {
+            let deposit_id: u32 = e
+                .storage()
+                .get(&DataKey::UserDeposit(user.clone(), index))
+                .unwrap()
+                .unwrap();
+            records.push_back(Self::get_deposit(e.clone(), deposit_id)?);
+        }
+
+        Ok(records)
+    }
+
+    // Commit a matching pool that matches each deposit at ratio_bps up to cap
+    pub fn commit_match(
+        e: Env,
+        sponsor: Address,
+        ratio_bps: u32,
+        cap: i128,
+    ) -> Result<(), CrowdfundError> {
+        sponsor.require_auth();
+        if cap <= 0 || ratio_bps == 0 {
+            return Err(CrowdfundError::InvalidAmount);
+        }
+        if get_state(&e)? != State::Running {
+            return Err(CrowdfundError::SaleNotRunning);
+        }
+        if sponsor == get_recipient(&e)? {
+            return Err(CrowdfundError::RecipientCannotSponsor);
+        }
+        if get_match_pool(&e, &sponsor).is_some() {
+            return Err(CrowdfundError::AlreadyCommitted);
+        }
+
+        let pool = MatchPool {
+            ratio_bps,
+            cap,
+            matched: 0,
+            refunded: false,
+        };
+        let mut sponsors = get_sponsors(&e);
+        sponsors.push_back(sponsor.clone());
+
+        e.storage().set(&DataKey::Sponsor(sponsor.clone()), &pool);
+        e.storage().set(&DataKey::Sponsors, &sponsors);
+
+        let client = token::Client::new(&e, &get_token(&e)?);
+        client.xfer(&sponsor, &e.current_contract_address(), &cap);
+        Ok(())
+    }
+
+    pub fn match_pool(e: Env, sponsor: Address) -> Result<MatchPool, CrowdfundError> {
+        get_match_pool(&e, &sponsor).ok_or(CrowdfundError::NoMatchingPool)
+    }
+
+    // Refund a sponsor once funding ended
+    // If funding was met, only the unmatched remainder is returned
+    // If funding was not met, the full pool is returned
+    pub fn refund_sponsor(e: Env, sponsor: Address) -> Result<(), CrowdfundError> {
+        let mut pool = get_match_pool(&e, &sponsor).ok_or(CrowdfundError::NoMatchingPool)?;
+        if pool.refunded {
+            return Err(CrowdfundError::AlreadyClaimed);
+        }
+
+        let refund = match get_state(&e)? {
+            State::Running => return Err(CrowdfundError::SaleStillRunning),
+            State::Success => pool.cap - pool.matched,
+            State::Expired => pool.cap,
+        };
+
+        pool.refunded = true;
+        e.storage().set(&DataKey::Sponsor(sponsor.clone()), &pool);
+        transfer(&e, &sponsor, &refund)
+    }
+
+    // Configure the SBT contract and the tiers of the backer badges, sorted
+    // by ascending min_amount
+    pub fn set_badges(
+        e: Env,
+        sbt_contract: BytesN<32>,
+        tiers: Vec<BadgeTier>,
+    ) -> Result<(), CrowdfundError> {
+        get_recipient(&e)?.require_auth();
+
+        let mut previous = 0;
+        for tier in tiers.iter() {
+            let tier = tier.unwrap();
+            if tier.min_amount <= previous {
+                return Err(CrowdfundError::InvalidAmount);
+            }
+            previous = tier.min_amount;
+        }
+
+        let config = BadgeConfig {
+            sbt_contract,
+            tiers,
+        };
+        e.storage().set(&DataKey::Badges, &config);
+        Ok(())
+    }
+
+    // Configure the dispute window, once and before the deadline. The arbiter
+    // is the one named at initialization, never chosen by the recipient
+    pub fn set_dispute_config(e: Env, window: u64, threshold_bps: u32) -> Result<(), CrowdfundError> {
+        get_recipient(&e)?.require_auth();
+        let arbiter: Address = e
+            .storage()
+            .get(&DataKey::Arbiter)
+            .ok_or(CrowdfundError::DisputesNotConfigured)?
+            .unwrap();
+        if threshold_bps == 0 || threshold_bps > 10_000 {
+            return Err(CrowdfundError::InvalidAmount);
+        }
+        if get_state(&e)? != State::Running {
+            return Err(CrowdfundError::SaleNotRunning);
+        }
+        if get_dispute_config(&e).is_some() {
+            return Err(CrowdfundError::AlreadyInitialized);
+        }
+
+        let config = DisputeConfig {
+            arbiter,
+            window,
+            threshold_bps,
+        };
+        e.storage().set(&DataKey::DisputeConfig, &config);
+        Ok(())
+    }
+
+    pub fn dispute_config(e: Env) -> Result<DisputeConfig, CrowdfundError> {
+        get_dispute_config(&e).ok_or(CrowdfundError::DisputesNotConfigured)
+    }
+
+    // Returns the backer whose dispute currently freezes the withdrawal
+    pub fn dispute(e: Env) -> Option<Address> {
+        e.storage().get(&DataKey::Dispute).map(|backer| backer.unwrap())
+    }
+
+    // Freeze the recipient withdrawal during the dispute window
+    pub fn raise_dispute(e: Env, backer: Address) -> Result<(), CrowdfundError> {
+        backer.require_auth();
+        let config = get_dispute_config(&e).ok_or(CrowdfundError::DisputesNotConfigured)?;
+
+        match get_state(&e)? {
+            State::Running => return Err(CrowdfundError::SaleStillRunning),
+            State::Expired => return Err(CrowdfundError::SaleNotRunning),
+            State::Success => {}
+        }
+        if e.storage().has(&DataKey::Dispute) {
+            return Err(CrowdfundError::WithdrawalFrozen);
+        }
+        if e.storage().has(&DataKey::DisputeResolved)
+            || e.storage().has(&DataKey::RecipientWithdrawn)
+            || get_current_time(&e) >= get_closed_at(&e)? + config.window
+        {
+            return Err(CrowdfundError::DisputeWindowClosed);
+        }
+
+        let share = get_user_deposited(&e, &backer) * 10_000;
+        if share == 0 || share < get_named_deposited(&e) * config.threshold_bps as i128 {
+            return Err(CrowdfundError::NotEligible);
+        }
+
+        e.storage().set(&DataKey::Dispute, &backer);
+        e.events()
+            .publish((Symbol::short("dispute"), Symbol::short("raised")), backer);
+        Ok(())
+    }
+
+    // Resolve a pending dispute, either releasing the funds to the recipient
+    // or refunding the campaign so backers and sponsors can withdraw
+    pub fn resolve_dispute(e: Env, refund: bool) -> Result<(), CrowdfundError> {
+        let config = get_dispute_config(&e).ok_or(CrowdfundError::DisputesNotConfigured)?;
+        config.arbiter.require_auth();
+        if !e.storage().has(&DataKey::Dispute) {
+            return Err(CrowdfundError::NoDispute);
+        }
+
+        e.storage().remove(&DataKey::Dispute);
+        e.storage().set(&DataKey::DisputeResolved, &true);
+        if refund {
+            e.storage().set(&DataKey::Refunded, &true);
+        }
+
+        e.events()
+            .publish((Symbol::short("dispute"), Symbol::short("resolved")), refund);
+        Ok(())
+    }
+
+    // Mint a soulbound backer badge for the highest tier the backer's deposits reach
+    pub fn claim_badge(e: Env, backer: Address) -> Result<u128, CrowdfundError> {
+        backer.require_auth();
+        if get_state(&e)? != State::Success {
+            return Err(CrowdfundError::SaleStillRunning);
+        }
+        if e.storage().has(&DataKey::BadgeClaimed(backer.clone())) {
+            return Err(CrowdfundError::AlreadyClaimed);
+        }
+
+        let config: BadgeConfig = e
+            .storage()
+            .get(&DataKey::Badges)
+            .ok_or(CrowdfundError::BadgesNotConfigured)?
+            .unwrap();
+        let deposited = get_user_deposited(&e, &backer);
+
+        let mut badge_uri = None;
+        for tier in config.tiers.iter() {
+            let tier = tier.unwrap();
+            if deposited >= tier.min_amount {
+                badge_uri = Some(tier.uri);
+            }
+        }
+        let badge_uri = badge_uri.ok_or(CrowdfundError::NotEligible)?;
+
+        e.storage().set(&DataKey::BadgeClaimed(backer.clone()), &true);
+
+        let client = sbt::Client::new(&e, &config.sbt_contract);
+        Ok(client.mint_sbt(&backer, &badge_uri))
+    }
+
+    // Donate an NFT to be auctioned for the campaign
+    pub fn donate_nft(
+        e: Env,
+        donor: Address,
+        nft_contract: BytesN<32>,
+        token_id: u128,
+    ) -> Result<u32, CrowdfundError> {
+        donor.require_auth();
+        if get_state(&e)? != State::Running {
+            return Err(CrowdfundError::SaleNotRunning);
+        }
+
+        let client = nft::Client::new(&e, &nft_contract);
+        client.transfer_from(&donor, &e.current_contract_address(), &token_id);
+
+        let donation_id = e
+            .storage()
+            .get(&DataKey::DonationCount)
+            .unwrap_or(Ok(0))
+            .unwrap()
+            + 1;
+        let record = DonationRecord {
+            donor,
+            nft_contract,
+            token_id,
+            auction: None,
+            settled: false,
+        };
+        e.storage().set(&DataKey::Donation(donation_id), &record);
+        e.storage().set(&DataKey::DonationCount, &donation_id);
+
+        e.events()
+            .publish((Symbol::short("donation"), donation_id), record);
+        Ok(donation_id)
+    }
+
+    pub fn get_donation(e: Env, donation_id: u32) -> Result<DonationRecord, CrowdfundError> {
+        get_donation(&e, donation_id)
+    }
+
+    // List a donated NFT in an auction contract for its collection. The
+    // auction has to end before the campaign deadline.
+    pub fn auction_donation(
+        e: Env,
+        donation_id: u32,
+        auction_contract: BytesN<32>,
+        start_price: i128,
+        expiration_date: u64,
+    ) -> Result<(), CrowdfundError> {
+        get_recipient(&e)?.require_auth();
+        let mut record = get_donation(&e, donation_id)?;
+        if record.auction.is_some() {
+            return Err(CrowdfundError::AlreadyListed);
+        }
+        if get_state(&e)? != State::Running {
+            return Err(CrowdfundError::SaleNotRunning);
+        }
+        if expiration_date >= get_deadline_timestamp(&e)? {
+            return Err(CrowdfundError::InvalidAmount);
+        }
+
+        let client = auction::Client::new(&e, &auction_contract);
+        client.auction_nft(
+            &e.current_contract_address(),
+            &record.token_id,
+            &start_price,
+            &expiration_date,
+        );
+
+        record.auction = Some(auction_contract);
+        e.storage().set(&DataKey::Donation(donation_id), &record);
+        Ok(())
+    }
+
+    // Settle the auction of a donated NFT and credit what the campaign
+    // received, net of marketplace fees, towards the raised amount. Proceeds
+    // settled after a successful close are paid out like the rest, through
+    // withdraw and the protocol fee, while a failed campaign returns them to
+    // the donor
+    pub fn settle_donation(e: Env, donation_id: u32) -> Result<i128, CrowdfundError> {
+        let mut record = get_donation(&e, donation_id)?;
+        let auction_contract = record.auction.clone().ok_or(CrowdfundError::DonationNotListed)?;
+        if record.settled {
+            return Err(CrowdfundError::AlreadyClaimed);
+        }
+
+        let before = get_token_balance(&e)?;
+        let client = auction::Client::new(&e, &auction_contract);
+        client.sell_auctioned_nft(
+            &e.current_contract_address(),
+            &record.token_id,
+            &Address::from_contract_id(&e, &get_token(&e)?),
+        );
+        let proceeds = get_token_balance(&e)? - before;
+
+        record.settled = true;
+        e.storage().set(&DataKey::Donation(donation_id), &record);
+        match get_state(&e)? {
+            State::Expired => transfer(&e, &record.donor, &proceeds)?,
+            state => {
+                e.storage()
+                    .set(&DataKey::Donated, &(get_donated(&e) + proceeds));
+                if state == State::Success && proceeds > 0 {
+                    reopen_payout(&e);
+                }
+            }
+        }
+        Ok(proceeds)
+    }
+
+    // Return a donated NFT that can no longer raise anything to its donor,
+    // once the campaign has closed, delisting it if its auction drew no bids
+    pub fn return_donation(e: Env, donation_id: u32) -> Result<(), CrowdfundError> {
+        let mut record = get_donation(&e, donation_id)?;
+        record.donor.require_auth();
+        if record.settled {
+            return Err(CrowdfundError::AlreadyClaimed);
+        }
+        if get_state(&e)? == State::Running {
+            return Err(CrowdfundError::SaleStillRunning);
+        }
+
+        if let Some(auction_contract) = record.auction.clone() {
+            let client = auction::Client::new(&e, &auction_contract);
+            // An auction with bids is settled instead, its proceeds going
+            // where the campaign's outcome sends them
+            if client.auction_status(&record.token_id).bid_count > 0 {
+                return Err(CrowdfundError::NotEligible);
+            }
+            client.delist_auctioned_nft(
+                &e.current_contract_address(),
+                &record.token_id,
+                &Address::from_contract_id(&e, &get_token(&e)?),
+            );
+        }
+
+        record.settled = true;
+        e.storage().set(&DataKey::Donation(donation_id), &record);
+
+        let client = nft::Client::new(&e, &record.nft_contract);
+        client.transfer_from(&e.current_contract_address(), &record.donor, &record.token_id);
+
+        e.events()
+            .publish((Symbol::short("donation"), Symbol::short("returned")), donation_id);
+        Ok(())
+    }
+
+    // Vest the funds of a successful campaign instead of paying them out at
+    // once, configured once and before the deadline
+    pub fn set_vesting(e: Env, cliff: u64, duration: u64) -> Result<(), CrowdfundError> {
+        get_recipient(&e)?.require_auth();
+        if duration == 0 || cliff > duration {
+            return Err(CrowdfundError::InvalidAmount);
+        }
+        if get_state(&e)? != State::Running {
+            return Err(CrowdfundError::SaleNotRunning);
+        }
+        if get_vesting(&e).is_some() {
+            return Err(CrowdfundError::AlreadyInitialized);
+        }
+
+        e.storage().set(&DataKey::Vesting, &VestingConfig { cliff, duration });
+        Ok(())
+    }
+
+    pub fn grace_period(e: Env) -> Option<GraceConfig> {
+        get_grace(&e)
+    }
+
+    // True while the campaign runs past its deadline under the grace rule
+    pub fn in_grace(e: Env) -> Result<bool, CrowdfundError> {
+        let deadline = get_deadline(&e)?;
+        Ok(get_current_time(&e) >= deadline
+            && in_grace_period(&e, deadline, get_raised(&e), get_target_amount(&e)?))
+    }
+
+    // Split the payout between several recipients by share in bps, which have
+    // to add up to 100%. Each recipient then withdraws their own share.
+    pub fn set_splits(e: Env, splits: Vec<PayoutSplit>) -> Result<(), CrowdfundError> {
+        get_recipient(&e)?.require_auth();
+        if get_state(&e)? != State::Running {
+            return Err(CrowdfundError::SaleNotRunning);
+        }
 
-        let recipient = get_recipient(&e);
-        assert!(user != recipient, "recipient may not deposit");
+        let mut total: u32 = 0;
+        for (i, split) in splits.iter().enumerate() {
+            let split = split.unwrap();
+            if split.share_bps == 0 {
+                return Err(CrowdfundError::InvalidSplit);
+            }
+            for other in splits.iter().skip(i + 1) {
+                if other.unwrap().recipient == split.recipient {
+                    return Err(CrowdfundError::InvalidSplit);
+                }
+            }
+            total = total.saturating_add(split.share_bps);
+        }
+        if total != 10_000 {
+            return Err(CrowdfundError::InvalidSplit);
+        }
 
-        let balance = get_user_deposited(&e, &user);
-        set_user_deposited(&e, &user, &(balance + amount));
+        e.storage().set(&DataKey::Splits, &splits);
+        Ok(())
+    }
+
+    pub fn splits(e: Env) -> Vec<PayoutSplit> {
+        get_splits(&e)
+    }
+
+    pub fn split_withdrawn(e: Env, recipient: Address) -> i128 {
+        get_split_withdrawn(&e, &recipient)
+    }
+
+    pub fn vesting(e: Env) -> Result<VestingConfig, CrowdfundError> {
+        get_vesting(&e).ok_or(CrowdfundError::VestingNotConfigured)
+    }
 
-        let client = token::Client::new(&e, &get_token(&e));
-        client.xfer(&user, &e.current_contract_address(), &amount);
+    pub fn vested(e: Env) -> Result<i128, CrowdfundError> {
+        get_vested(&e)
+    }
+
+    // Vote with the backer's deposited value to halt the remaining vesting.
+    // Once a supermajority voted, what had not vested yet is refunded.
+    pub fn vote_halt(e: Env, backer: Address) -> Result<(), CrowdfundError> {
+        backer.require_auth();
+        get_vesting(&e).ok_or(CrowdfundError::VestingNotConfigured)?;
+
+        if get_state(&e)? != State::Success {
+            return Err(CrowdfundError::SaleStillRunning);
+        }
+        if e.storage().has(&DataKey::HaltedVested) {
+            return Err(CrowdfundError::VestingHalted);
+        }
+        if e.storage().has(&DataKey::HaltVote(backer.clone())) {
+            return Err(CrowdfundError::AlreadyVoted);
+        }
+
+        let weight = get_user_deposited(&e, &backer);
+        if weight == 0 {
+            return Err(CrowdfundError::NotEligible);
+        }
+
+        let votes: i128 = e
+            .storage()
+            .get(&DataKey::HaltVotes)
+            .unwrap_or(Ok(0))
+            .unwrap()
+            + weight;
+        e.storage().set(&DataKey::HaltVote(backer.clone()), &true);
+        e.storage().set(&DataKey::HaltVotes, &votes);
+
+        if votes * 10_000 >= get_named_deposited(&e) * HALT_SUPERMAJORITY_BPS {
+            e.storage().set(&DataKey::HaltedVested, &get_vested(&e)?);
+            e.events()
+                .publish((Symbol::short("vesting"), Symbol::short("halted")), votes);
+        }
+        Ok(())
+    }
+
+    // Refund the backer's pro-rata share of what had not vested when vesting was halted
+    pub fn claim_halt_refund(e: Env, backer: Address) -> Result<i128, CrowdfundError> {
+        backer.require_auth();
+        let vested: i128 = e
+            .storage()
+            .get(&DataKey::HaltedVested)
+            .ok_or(CrowdfundError::NotEligible)?
+            .unwrap();
+        if e.storage().has(&DataKey::HaltRefunded(backer.clone())) {
+            return Err(CrowdfundError::AlreadyClaimed);
+        }
+
+        let remainder = get_payout_total(&e) - vested;
+        let refund = remainder * get_user_deposited(&e, &backer) / get_named_deposited(&e);
+        if refund == 0 {
+            return Err(CrowdfundError::NotEligible);
+        }
+
+        e.storage().set(&DataKey::HaltRefunded(backer.clone()), &true);
+        transfer(&e, &backer, &refund)?;
+        Ok(refund)
+    }
+
+    // Convert the unclaimed refunds of backers of deposits start..start + limit
+    // into claimable balances, returning how many were swept
+    pub fn sweep_refunds(e: Env, start: u32, limit: u32) -> Result<u32, CrowdfundError> {
+        get_recipient(&e)?.require_auth();
+        if get_state(&e)? != State::Expired {
+            return Err(CrowdfundError::SaleStillRunning);
+        }
+
+        let end = start.saturating_add(limit).min(get_deposit_count(&e));
+        let mut swept = 0;
+
+        for deposit_id in (start + 1)..=end {
+            let record: DepositRecord = e.storage().get(&DataKey::Deposit(deposit_id)).unwrap().unwrap();
+            let amount = get_user_deposited(&e, &record.user);
+            if amount == 0 {
+                continue;
+            }
+
+            set_user_deposited(&e, &record.user, &0);
+            e.storage().set(
+                &DataKey::Claimable(record.user),
+                &ClaimableBalance {
+                    amount,
+                    expires_at: get_current_time(&e) + CLAIM_EXPIRY,
+                },
+            );
+            swept += 1;
+        }
+        Ok(swept)
+    }
+
+    pub fn claimable_balance(e: Env, backer: Address) -> Result<ClaimableBalance, CrowdfundError> {
+        e.storage()
+            .get(&DataKey::Claimable(backer))
+            .ok_or(CrowdfundError::NoClaimableBalance)
+            .map(|balance| balance.unwrap())
+    }
+
+    // Claim a swept refund before it expires
+    pub fn claim_balance(e: Env, backer: Address) -> Result<(), CrowdfundError> {
+        backer.require_auth();
+        let balance = Self::claimable_balance(e.clone(), backer.clone())?;
+        if get_current_time(&e) >= balance.expires_at {
+            return Err(CrowdfundError::ClaimExpired);
+        }
+
+        e.storage().remove(&DataKey::Claimable(backer.clone()));
+        transfer(&e, &backer, &balance.amount)
+    }
+
+    // Return an expired swept refund to its backer, callable by anyone since
+    // the funds can only go to the backer
+    pub fn return_expired_balance(e: Env, backer: Address) -> Result<(), CrowdfundError> {
+        let balance = Self::claimable_balance(e.clone(), backer.clone())?;
+        if get_current_time(&e) < balance.expires_at {
+            return Err(CrowdfundError::ClaimNotExpired);
+        }
+
+        e.storage().remove(&DataKey::Claimable(backer.clone()));
+        transfer(&e, &backer, &balance.amount)
     }
 
     // Withdraw funded tokens depending on current state
     // If funding was met within the alloted timeline, the receiver is allowed to withdraw
     // If funding was not met wthin the alloted timeline, the sender can withdraw their fundings
-    pub fn withdraw(e: Env, to: Address) {
-        let state = get_state(&e);
-        let recipient = get_recipient(&e);
+    pub fn withdraw(e: Env, to: Address) -> Result<(), CrowdfundError> {
+        let state = get_state(&e)?;
+        let recipient = get_recipient(&e)?;
 
         match state {
-            State::Running => {
-                panic!("funding is still running")
-            }
+            State::Running => Err(CrowdfundError::SaleStillRunning),
             State::Success => {
-                assert!(
-                    to == recipient,
-                    "fund was successful, only the recipient may withdraw"
-                );
-                let token = get_token(&e);
-                transfer(&e, &recipient, &get_balance(&e, &token));
+                let splits = get_splits(&e);
+                if splits.is_empty() && to != recipient {
+                    return Err(CrowdfundError::OnlyRecipientCanWithdraw);
+                }
+                if e.storage().has(&DataKey::RecipientWithdrawn) {
+                    return Err(CrowdfundError::AlreadyClaimed);
+                }
+                check_dispute_settled(&e)?;
+                let amount = if splits.is_empty() {
+                    get_vested(&e)? - get_vesting_withdrawn(&e)
+                } else {
+                    get_split_available(&e, &to)?
+                };
+                if amount <= 0 {
+                    return Err(CrowdfundError::NotEligible);
+                }
+
+                let withdrawn = get_vesting_withdrawn(&e) + amount;
+                e.storage().set(&DataKey::VestingWithdrawn, &withdrawn);
+                if !splits.is_empty() {
+                    let split_withdrawn = get_split_withdrawn(&e, &to) + amount;
+                    e.storage()
+                        .set(&DataKey::SplitWithdrawn(to.clone()), &split_withdrawn);
+                }
+
+                // Split shares round down, so with splits the payout is done
+                // once every recipient has nothing left to withdraw
+                let halted = e.storage().has(&DataKey::HaltedVested);
+                let done = if splits.is_empty() {
+                    halted || withdrawn == get_payout_total(&e)
+                } else {
+                    let mut done = halted || get_vested(&e)? == get_payout_total(&e);
+                    for split in splits.iter() {
+                        done = done && get_split_available(&e, &split.unwrap().recipient)? <= 0;
+                    }
+                    done
+                };
+                if done {
+                    e.storage().set(&DataKey::RecipientWithdrawn, &true);
+                }
+                transfer(&e, &to, &amount)
             }
             State::Expired => {
-                assert!(
-                    to != recipient,
-                    "fund has expired, the recipient may not withdraw"
-                );
-                // Withdraw full amount
+                if to == recipient {
+                    return Err(CrowdfundError::RecipientCannotWithdraw);
+                }
+                // Withdraw full amount, or the claimable balance it was swept into
                 let balance = get_user_deposited(&e, &to);
+                if balance == 0 {
+                    if e.storage().has(&DataKey::Claimable(to.clone())) {
+                        return Self::claim_balance(e, to);
+                    }
+                    return Err(CrowdfundError::NotEligible);
+                }
                 set_user_deposited(&e, &to, &0);
-                transfer(&e, &to, &balance);
+                transfer(&e, &to, &balance)
             }
-        };
+        }
     }
 }
\ No newline at end of file
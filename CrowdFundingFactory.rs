@@ -0,0 +1,376 @@
+#![no_std]
+use soroban_sdk::{contractimpl, contracttype, Address, BytesN, Env, Symbol, Vec};
+
+mod crowdfund {
+    soroban_sdk::contractimport!(file = "./soroban_crowdfund_contract.wasm");
+}
+
+mod token {
+    soroban_sdk::contractimport!(file = "./soroban_token_spec.wasm");
+}
+
+// Campaigns a single campaigns call reads, each costing a cross-contract call
+const MAX_CAMPAIGN_SCAN: u32 = 50;
+
+#[derive(Clone)]
+#[contracttype]
+pub enum DataKey {
+    Admin,
+    Campaign(u32),
+    CampaignCount,
+    Category(Symbol),
+    FeeBps,
+    Recorded(u32),
+    Stats,
+    TotalRaised(BytesN<32>),
+    Treasury(BytesN<32>),
+    WasmHash,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct CampaignInfo {
+    pub contract_id: BytesN<32>,
+    pub category: Symbol,
+    pub creator: Address,
+}
+
+// One page of a category scan, see campaigns
+#[derive(Clone)]
+#[contracttype]
+pub struct CampaignPage {
+    pub campaigns: Vec<CampaignInfo>,
+    pub next: Option<u32>,
+}
+
+// Settings of a cloned campaign that replace the template's; fields left
+// as None are copied from the template. Without a deadline the clone runs
+// for as long as the template did, counted from its creation
+#[derive(Clone)]
+#[contracttype]
+pub struct CampaignOverrides {
+    pub recipient: Option<Address>,
+    pub deadline: Option<u64>,
+    pub target_amount: Option<i128>,
+    pub token: Option<BytesN<32>>,
+}
+
+// Protocol-wide totals over every campaign whose outcome has been recorded.
+// Amounts raised are in the campaign's token, so they are kept per token
+#[derive(Clone, Default)]
+#[contracttype]
+pub struct ProtocolStats {
+    pub succeeded: u32,
+    pub failed: u32,
+}
+
+// Retrieves the hash of the crowdfund contract wasm deployed for each campaign
+fn get_wasm_hash(e: &Env) -> BytesN<32> {
+    e.storage()
+        .get(&DataKey::WasmHash)
+        .expect("not initialized")
+        .unwrap()
+}
+
+// Retrieves the number of campaigns created through the factory
+fn get_campaign_count(e: &Env) -> u32 {
+    e.storage()
+        .get(&DataKey::CampaignCount)
+        .unwrap_or(Ok(0))
+        .unwrap()
+}
+
+// Retrieves the registry entry of a campaign
+fn get_campaign(e: &Env, campaign_id: u32) -> CampaignInfo {
+    e.storage()
+        .get(&DataKey::Campaign(campaign_id))
+        .expect("campaign not found")
+        .unwrap()
+}
+
+// Retrieves the ids of every campaign registered under a category
+fn get_category(e: &Env, category: &Symbol) -> Vec<u32> {
+    e.storage()
+        .get(&DataKey::Category(category.clone()))
+        .unwrap_or(Ok(Vec::new(e)))
+        .unwrap()
+}
+
+// Retrieves the protocol fee charged on campaigns created from now on
+fn get_fee_bps(e: &Env) -> u32 {
+    e.storage()
+        .get(&DataKey::FeeBps)
+        .unwrap_or(Ok(0))
+        .unwrap()
+}
+
+// Retrieves the protocol-wide statistics
+fn get_stats(e: &Env) -> ProtocolStats {
+    e.storage()
+        .get(&DataKey::Stats)
+        .unwrap_or(Ok(ProtocolStats::default()))
+        .unwrap()
+}
+
+// Retrieves the total raised in a token by successful campaigns
+fn get_total_raised(e: &Env, token: &BytesN<32>) -> i128 {
+    e.storage()
+        .get(&DataKey::TotalRaised(token.clone()))
+        .unwrap_or(Ok(0))
+        .unwrap()
+}
+
+// Retrieves the protocol fees accrued in a token and not yet claimed
+fn get_treasury(e: &Env, token: &BytesN<32>) -> i128 {
+    e.storage()
+        .get(&DataKey::Treasury(token.clone()))
+        .unwrap_or(Ok(0))
+        .unwrap()
+}
+
+// Registers a freshly deployed campaign under its category and returns its id
+fn register_campaign(e: &Env, contract_id: BytesN<32>, category: Symbol, creator: Address) -> u32 {
+    let campaign_id = get_campaign_count(e) + 1;
+    let info = CampaignInfo {
+        contract_id,
+        category: category.clone(),
+        creator,
+    };
+    let mut ids = get_category(e, &category);
+    ids.push_back(campaign_id);
+
+    e.storage().set(&DataKey::Campaign(campaign_id), &info);
+    e.storage().set(&DataKey::CampaignCount, &campaign_id);
+    e.storage().set(&DataKey::Category(category), &ids);
+
+    campaign_id
+}
+
+// Retrieves the admin of the factory
+fn get_admin(e: &Env) -> Address {
+    e.storage()
+        .get(&DataKey::Admin)
+        .expect("not initialized")
+        .unwrap()
+}
+
+struct CrowdfundFactory;
+
+#[contractimpl]
+#[allow(clippy::needless_pass_by_value)]
+impl CrowdfundFactory {
+    pub fn initialize(e: Env, admin: Address, wasm_hash: BytesN<32>) {
+        assert!(!e.storage().has(&DataKey::Admin), "already initialized");
+
+        e.storage().set(&DataKey::Admin, &admin);
+        e.storage().set(&DataKey::WasmHash, &wasm_hash);
+    }
+
+    // Deploy and initialize a new campaign and register it under its category
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_campaign(
+        e: Env,
+        creator: Address,
+        category: Symbol,
+        salt: BytesN<32>,
+        recipient: Address,
+        deadline: u64,
+        target_amount: i128,
+        token: BytesN<32>,
+        grace: Option<crowdfund::GraceConfig>,
+    ) -> u32 {
+        creator.require_auth();
+
+        let contract_id = e
+            .deployer()
+            .with_current_contract(&salt)
+            .deploy(&get_wasm_hash(&e));
+        let client = crowdfund::Client::new(&e, &contract_id);
+        client.initialize_with_fee(
+            &recipient,
+            &deadline,
+            &target_amount,
+            &token,
+            &crowdfund::DeadlineMode::Timestamp,
+            &e.current_contract_address(),
+            &get_fee_bps(&e),
+            &get_admin(&e),
+            &grace,
+        );
+
+        register_campaign(&e, contract_id, category, creator)
+    }
+
+    // Deploy a new campaign for the creator of a prior one, copying the
+    // template's token, protocol fee, deadline mode, grace rule and category
+    // except where overridden
+    pub fn clone_campaign(
+        e: Env,
+        template_id: u32,
+        salt: BytesN<32>,
+        overrides: CampaignOverrides,
+    ) -> u32 {
+        let template = get_campaign(&e, template_id);
+        template.creator.require_auth();
+
+        let source = crowdfund::Client::new(&e, &template.contract_id);
+        let mode = source.deadline_mode();
+        let deadline = match overrides.deadline {
+            Some(deadline) => deadline,
+            None => {
+                let now = match mode {
+                    crowdfund::DeadlineMode::Timestamp => e.ledger().timestamp(),
+                    crowdfund::DeadlineMode::LedgerSequence => e.ledger().sequence() as u64,
+                };
+                now + (source.deadline() - source.started())
+            }
+        };
+        let fee_bps = match source.protocol_fee() {
+            Some(fee) => fee.fee_bps,
+            None => 0,
+        };
+
+        let contract_id = e
+            .deployer()
+            .with_current_contract(&salt)
+            .deploy(&get_wasm_hash(&e));
+        let client = crowdfund::Client::new(&e, &contract_id);
+        client.initialize_with_fee(
+            &overrides.recipient.unwrap_or_else(|| source.recipient()),
+            &deadline,
+            &overrides.target_amount.unwrap_or_else(|| source.target()),
+            &overrides.token.unwrap_or_else(|| source.token()),
+            &mode,
+            &e.current_contract_address(),
+            &fee_bps,
+            &get_admin(&e),
+            &source.grace_period(),
+        );
+
+        register_campaign(&e, contract_id, template.category, template.creator)
+    }
+
+    pub fn campaign_count(e: Env) -> u32 {
+        get_campaign_count(&e)
+    }
+
+    pub fn campaign(e: Env, campaign_id: u32) -> CampaignInfo {
+        get_campaign(&e, campaign_id)
+    }
+
+    // Amount a campaign raised according to its own deposit records
+    pub fn raised(e: Env, campaign_id: u32) -> i128 {
+        let info = get_campaign(&e, campaign_id);
+        let client = crowdfund::Client::new(&e, &info.contract_id);
+        client.raised()
+    }
+
+    // Protocol fee in bps owed by campaigns created after the change
+    pub fn set_protocol_fee(e: Env, fee_bps: u32) {
+        get_admin(&e).require_auth();
+        assert!(fee_bps <= 10_000, "fee can not exceed 100%");
+
+        e.storage().set(&DataKey::FeeBps, &fee_bps);
+    }
+
+    pub fn protocol_fee(e: Env) -> u32 {
+        get_fee_bps(&e)
+    }
+
+    pub fn stats(e: Env) -> ProtocolStats {
+        get_stats(&e)
+    }
+
+    pub fn total_raised(e: Env, token: BytesN<32>) -> i128 {
+        get_total_raised(&e, &token)
+    }
+
+    pub fn protocol_fees(e: Env, token: BytesN<32>) -> i128 {
+        get_treasury(&e, &token)
+    }
+
+    // Count a finished campaign into the protocol stats and, if it
+    // succeeded, accrue its protocol fee to the treasury. A successful
+    // campaign is only final once no dispute can still refund it
+    pub fn record_outcome(e: Env, campaign_id: u32) {
+        assert!(
+            !e.storage().has(&DataKey::Recorded(campaign_id)),
+            "outcome already recorded"
+        );
+
+        let info = get_campaign(&e, campaign_id);
+        let client = crowdfund::Client::new(&e, &info.contract_id);
+        let mut stats = get_stats(&e);
+
+        match client.state() {
+            crowdfund::State::Running => panic!("campaign still running"),
+            crowdfund::State::Expired => stats.failed += 1,
+            crowdfund::State::Success => {
+                assert!(client.outcome_final(), "campaign outcome not final");
+                stats.succeeded += 1;
+                let token = client.token();
+                e.storage().set(
+                    &DataKey::TotalRaised(token.clone()),
+                    &(get_total_raised(&e, &token) + client.raised()),
+                );
+
+                if client.protocol_fee().is_some() {
+                    let fee = client.claim_protocol_fee();
+                    e.storage()
+                        .set(&DataKey::Treasury(token.clone()), &(get_treasury(&e, &token) + fee));
+                }
+            }
+        }
+
+        e.storage().set(&DataKey::Recorded(campaign_id), &true);
+        e.storage().set(&DataKey::Stats, &stats);
+    }
+
+    // Transfer the protocol fees accrued in a token to the admin
+    pub fn claim_protocol_fees(e: Env, admin: Address, token: BytesN<32>) -> i128 {
+        admin.require_auth();
+        assert!(admin == get_admin(&e), "only admin can claim protocol fees");
+
+        let amount = get_treasury(&e, &token);
+        e.storage().set(&DataKey::Treasury(token.clone()), &0_i128);
+
+        let client = token::Client::new(&e, &token);
+        client.xfer(&e.current_contract_address(), &admin, &amount);
+
+        amount
+    }
+
+    // Enumerate the campaigns of a category that are in the given state,
+    // scanning at most MAX_CAMPAIGN_SCAN entries of the category from start
+    // and returning at most limit matches. The page's next is where the
+    // following call should start, None once the category is exhausted
+    pub fn campaigns(
+        e: Env,
+        category: Symbol,
+        state: crowdfund::State,
+        start: u32,
+        limit: u32,
+    ) -> CampaignPage {
+        let ids = get_category(&e, &category);
+        let mut campaigns = Vec::new(&e);
+        let mut next = start;
+
+        for campaign_id in ids.iter().skip(start as usize).take(MAX_CAMPAIGN_SCAN as usize) {
+            if campaigns.len() >= limit {
+                break;
+            }
+            next += 1;
+
+            let info = get_campaign(&e, campaign_id.unwrap());
+            let client = crowdfund::Client::new(&e, &info.contract_id);
+            if client.state() == state {
+                campaigns.push_back(info);
+            }
+        }
+
+        CampaignPage {
+            campaigns,
+            next: if next < ids.len() { Some(next) } else { None },
+        }
+    }
+}
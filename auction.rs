@@ -1,16 +1,65 @@
 #![no_std]
 use soroban_sdk::{
-    contract, contractimpl, contracttype, symbol_short, token, Address, Env, Symbol, Vec,
+    contract, contractclient, contracterror, contractimpl, contracttype, panic_with_error, symbol_short,
+    events::Topics, token, Address, Bytes, BytesN, Env, IntoVal, String, Symbol, Val, Vec,
 };
 
 mod nft_contract {
     soroban_sdk::contractimport!(file = "nft/nft_soroban.wasm");
 }
 
-const SELLEVENT: Symbol = symbol_short!("SELLEVENT");
+mod nft_marketplace;
+mod registry_interface;
+mod token_id;
+
+use nft_marketplace::NFTMarketplaceClient;
+use registry_interface::{AuctionItem, ListingRegistryClient, SaleItem};
+use token_id::TokenId;
+
+// Raised instead of wrapping when bid or settlement arithmetic overflows
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum AuctionError {
+    Overflow = 1,
+}
+
+// Interface a registered settlement hook contract must implement
+#[contractclient(name = "SettlementHookClient")]
+pub trait SettlementHook {
+    fn on_settlement(env: Env, token_id: u128, winner: Address, price: i128, payment_token: Address);
+}
+
+const SALE_ACTION: Symbol = symbol_short!("sale");
 const AUCEVENT: Symbol = symbol_short!("AUCEVENT");
 const BIDEVENT: Symbol = symbol_short!("BIDEVENT");
 const DLEVENT: Symbol = symbol_short!("DLEVENT");
+const INSTEVENT: Symbol = symbol_short!("INSTEVENT");
+const REVEVENT: Symbol = symbol_short!("REVEVENT");
+const MIGEVENT: Symbol = symbol_short!("MIGEVENT");
+const BLOCKEVENT: Symbol = symbol_short!("BLKEVENT");
+const BONDEVENT: Symbol = symbol_short!("BONDEVENT");
+const FEEEVENT: Symbol = symbol_short!("FEEEVENT");
+const SALEEVENT: Symbol = symbol_short!("SALEEVENT");
+
+const DAY_IN_SECONDS: u64 = 86400;
+// Time after expiry a seller has to reveal a committed reserve
+const RESERVE_REVEAL_WINDOW: u64 = DAY_IN_SECONDS;
+const MAX_BPS: u32 = 10_000;
+
+// Bumped whenever the topics or data layout of a published event changes,
+// 2 since event data carries the event nonce
+const EVENT_SCHEMA_VERSION: u32 = 2;
+
+const ROYALTY_LEG: Symbol = symbol_short!("royalty");
+const FEE_LEG: Symbol = symbol_short!("fee");
+const REFERRAL_LEG: Symbol = symbol_short!("referral");
+const SELLER_LEG: Symbol = symbol_short!("seller");
+const KEEPER_LEG: Symbol = symbol_short!("keeper");
+const SETTLEEVENT: Symbol = symbol_short!("SETTLE");
+const MAX_EXPIRY_QUERY_DAYS: u64 = 90;
+// Outbid escrows returned per settlement or continue_refunds call
+const REFUND_BATCH_SIZE: u32 = 10;
 
 #[derive(Clone)]
 #[contracttype]
@@ -25,8 +74,10 @@ pub struct AuctionEvent {
 #[contracttype]
 pub struct SellEvent {
     token_id: u128,
+    seller: Address,
     buyer: Address,
     price: i128,
+    payment_token: Address,
 }
 
 #[derive(Clone)]
@@ -42,6 +93,118 @@ pub struct BidEvent {
     token_id: u128,
     user: Address,
     bid_price: i128,
+    bid_count: u32,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct OutbidEvent {
+    token_id: u128,
+    outbid_user: Address,
+    previous_price: i128,
+    new_price: i128,
+    new_user: Address,
+    bid_count: u32,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct InstallmentEvent {
+    payout_id: u64,
+    seller: Address,
+    amount: i128,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct ReverseEvent {
+    payout_id: u64,
+    buyer: Address,
+    amount: i128,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct MigrateEvent {
+    token_id: u128,
+    payment_token: Address,
+    refunded: i128,
+}
+
+// Published next to the regular bid event, whose user is the principal
+#[derive(Clone)]
+#[contracttype]
+pub struct AgentBidEvent {
+    token_id: u128,
+    agent: Address,
+    principal: Address,
+    bid_price: i128,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct AgentMandateEvent {
+    principal: Address,
+    agent: Address,
+    max_price: Option<i128>,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct RemapEvent {
+    token_id: u128,
+    old_collection: Address,
+    new_collection: Address,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct RefundEvent {
+    token_id: u128,
+    user: Address,
+    amount: i128,
+    remaining: u32,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct BlockEvent {
+    token_id: u128,
+    owner: Address,
+    user: Address,
+}
+
+// One transfer of a settlement, kind is one of the *_LEG symbols
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct PaymentLeg {
+    kind: Symbol,
+    recipient: Address,
+    amount: i128,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct SettlementEvent {
+    token_id: u128,
+    payment_token: Address,
+    price: i128,
+    legs: Vec<PaymentLeg>,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct CollectionFeeEvent {
+    collection: Address,
+    fee_bps: Option<u32>,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct BondEvent {
+    token_id: u128,
+    to: Address,
+    amount: i128,
 }
 
 #[derive(Clone)]
@@ -49,6 +212,189 @@ pub struct BidEvent {
 pub enum DataKey {
     Admin,
     NFTAddress,
+    InstallmentConfig,
+    PayoutCount,
+    Payout(u64),
+    SettlementHook,
+    ExpiryBucket(u64),
+    Nonce(Address),
+    PaymentToken,
+    AuctionToken(u128),
+    Fee,
+    FeeTiers,
+    Volume(Address),
+    Settled(u128),
+    BlockedBidder(u128, Address),
+    ListingRegistry,
+    BondAmount,
+    Bond(u128),
+    CollectionFee(Address),
+    FeeCollections,
+    Royalty,
+    ReferralBps,
+    Referrer(u128),
+    SaleCount,
+    Sale(u64),
+    SaleLots(u64),
+    Lot(u128),
+    KeeperBounty,
+    BidFee,
+    AuctionBidFees(u128),
+    BidderFees(u128, Address),
+    FeePool(Address),
+    LedgerBids(Address),
+    SettlementDelay,
+    EventNonce,
+    ReserveCommitment(u128),
+    ReserveRevealed(u128),
+    IncrementSchedule,
+    AuctionIncrements(u128),
+    AuctionCollection(u128),
+    Agent(Address),
+    AgentMandate(Address, Address),
+    PendingRefunds(u128),
+}
+
+// Flat anti-spam fee charged per bid, held until the auction closes and
+// then moved to the fee pool, except the winner's fees when refund_winner
+// is set. max_bids_per_ledger caps the bids of one address per ledger, 0
+// leaves them unlimited.
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct BidFeeConfig {
+    fee: i128,
+    refund_winner: bool,
+    max_bids_per_ledger: u32,
+}
+
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct LedgerBids {
+    sequence: u32,
+    count: u32,
+}
+
+// Bounty paid out of the marketplace fee to whoever settles an expired
+// auction, ramping from 0 at expiry up to max_bps of the price after ramp
+// seconds and never above cap
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct KeeperBounty {
+    max_bps: u32,
+    cap: i128,
+    ramp: u64,
+}
+
+// Named auction-house sale grouping auctions into numbered lots. Bidding on
+// its lots opens at start.
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct AuctionSale {
+    sale_id: u64,
+    title: String,
+    start: u64,
+    end: u64,
+}
+
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct Lot {
+    sale_id: u64,
+    lot_number: u32,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct LotEvent {
+    sale_id: u64,
+    lot_number: u32,
+    token_id: u128,
+}
+
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct RoyaltyConfig {
+    recipient: Address,
+    bps: u32,
+}
+
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct SellerBond {
+    seller: Address,
+    payment_token: Address,
+    amount: i128,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[contracttype]
+pub enum AuctionState {
+    None,
+    Open,
+    Expired,
+    Settled,
+}
+
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct AuctionStatus {
+    state: AuctionState,
+    time_remaining: u64,
+    highest_bid: i128,
+    bid_count: u32,
+}
+
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct FeeConfig {
+    fee_bps: u32,
+    shares: Vec<FeeShare>,
+}
+
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct FeeShare {
+    recipient: Address,
+    bps: u32,
+}
+
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct FeeTier {
+    min_volume: i128,
+    rebate_bps: u32,
+}
+
+// Once the highest bid reaches min_price the next bid must beat it by at
+// least increment_bps of it, e.g. +5% from 0 and +2% from 100 XLM
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct IncrementStep {
+    min_price: i128,
+    increment_bps: u32,
+}
+
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct InstallmentConfig {
+    threshold: i128,
+    installments: u32,
+    interval: u64,
+}
+
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct InstallmentPayout {
+    token_id: u128,
+    seller: Address,
+    buyer: Address,
+    payment_token: Address,
+    total: i128,
+    installments: u32,
+    paid: u32,
+    start: u64,
+    interval: u64,
+    reversed: bool,
 }
 
 #[contracttype]
@@ -59,7 +405,7 @@ pub struct AuctionNFT {
     start_price: i128,
     expiration_date: u64,
     bidders: Vec<Bidder>,
-    highest_bidder: HighestBidder,
+    highest_bidder: Option<HighestBidder>,
 }
 
 #[derive(Clone, Debug)]
@@ -67,13 +413,29 @@ pub struct AuctionNFT {
 struct Bidder {
     user: Address,
     price: i128,
+    escrowed: bool,
+    agent: Option<Address>,
+}
+
+// Escrow of an outbid bid, kept in the token it was paid in since a
+// migration can change the auction's payment token before it is returned
+#[derive(Clone, Debug)]
+#[contracttype]
+struct PendingRefund {
+    user: Address,
+    amount: i128,
+    payment_token: Address,
 }
 
+// Escrowed bids were transferred to the contract when placed, the others
+// are backed by an allowance and only collected at settlement
 #[derive(Clone, Debug)]
 #[contracttype]
 struct HighestBidder {
     user: Address,
     price: i128,
+    escrowed: bool,
+    agent: Option<Address>,
 }
 
 #[contract]
@@ -95,19 +457,51 @@ impl NFTAuctionStorefront {
     pub fn auction_nft(env: Env, from: Address, token_id: u128, price: i128, expiration_date: u64) {
         from.require_auth();
 
+        Self::create_auction(env, from, token_id, price, expiration_date);
+    }
+
+    // Lets a relayer submit an auction the seller authorized off-chain. The
+    // signed arguments include the seller's next nonce so it can't be replayed.
+    pub fn auction_nft_relayed(
+        env: Env,
+        from: Address,
+        token_id: u128,
+        price: i128,
+        expiration_date: u64,
+        nonce: u64,
+    ) {
+        from.require_auth_for_args((token_id, price, expiration_date, nonce).into_val(&env));
+
+        Self::consume_nonce(env.clone(), from.clone(), nonce);
+        Self::create_auction(env, from, token_id, price, expiration_date);
+    }
+
+    pub fn get_nonce(env: Env, account: Address) -> u64 {
+        env.storage().instance().get(&DataKey::Nonce(account)).unwrap_or(0)
+    }
+
+    fn consume_nonce(env: Env, account: Address, nonce: u64) {
+        if nonce != Self::get_nonce(env.clone(), account.clone()) {
+            panic!("Invalid nonce")
+        }
+
+        env.storage().instance().set(&DataKey::Nonce(account), &(nonce + 1));
+    }
+
+    fn create_auction(env: Env, from: Address, token_id: u128, price: i128, expiration_date: u64) {
+        TokenId::from_u128(token_id);
+
         let nft_client = Self::get_nft_client(env.clone());
 
         if nft_client.has_nft_owner(&from.clone(), &token_id) {
             panic!("Invalid Sender")
         } else if from == env.current_contract_address() {
             panic!("Sender can not be contract address")
-        } else if token_id == 0 {
-            panic!("Token ID can not be zero")
         }
 
-        let auction_nft = Self::get_auctioned_nft(env.clone(), token_id);
-
-        if auction_nft.owner == from {
+        // An earlier auction of the token, possibly by a previous owner, holds
+        // escrow and a bond until it is settled or invalidated
+        if Self::get_auctioned_nft(env.clone(), token_id).token_id != 0 {
             panic!("NFT Auctioned Already")
         }
 
@@ -123,16 +517,20 @@ impl NFTAuctionStorefront {
             start_price: price,
             expiration_date,
             bidders: Vec::new(&env),
-            highest_bidder: HighestBidder {
-                user: env.current_contract_address(),
-                price: 0,
-            },
+            highest_bidder: None,
         };
 
+        Self::lock_bond(env.clone(), from.clone(), token_id);
+
         env.storage().instance().set(&token_id, &auction_listing); // store auction listing at token_id
+        Self::lock_listing(env.clone(), token_id);
+        env.storage().instance().remove(&DataKey::Settled(token_id));
+        if let Some(payment_token) = Self::get_payment_token(env.clone()) {
+            env.storage().instance().set(&DataKey::AuctionToken(token_id), &payment_token);
+        }
+        Self::add_to_expiry_bucket(env.clone(), token_id, expiration_date);
 
-        env.events()
-            .publish((AUCEVENT, symbol_short!("auctioned")), auction_event);
+        Self::publish_event(env.clone(), (AUCEVENT, symbol_short!("auctioned")), auction_event);
     }
 
     pub fn get_auctioned_nft(env: Env, token_id: u128) -> AuctionNFT {
@@ -142,22 +540,154 @@ impl NFTAuctionStorefront {
             start_price: 0,
             expiration_date: 0,
             bidders: Vec::new(&env),
-            highest_bidder: HighestBidder {
-                user: env.current_contract_address(),
-                price: 0,
-            },
+            highest_bidder: None,
         });
 
         return auction_nft;
     }
 
+    // Auction of the token in the shape the listing registry's get_item returns
+    pub fn get_item(env: Env, token_id: u128) -> SaleItem {
+        let auction_nft = Self::get_auctioned_nft(env.clone(), token_id);
+
+        if auction_nft.token_id == 0 {
+            return SaleItem::None;
+        }
+
+        SaleItem::Auction(AuctionItem {
+            marketplace: env.current_contract_address(),
+            owner: auction_nft.owner,
+            start_price: auction_nft.start_price,
+            expiration_date: auction_nft.expiration_date,
+            highest_bid: auction_nft.highest_bidder.as_ref().map_or(0, |bid| bid.price),
+            bid_count: auction_nft.bidders.len(),
+        })
+    }
+
+    // Derives the auction state from the ledger clock so clients don't have to
+    pub fn auction_status(env: Env, token_id: u128) -> AuctionStatus {
+        let auction_nft = Self::get_auctioned_nft(env.clone(), token_id);
+        let now = env.ledger().timestamp();
+
+        if auction_nft.token_id == 0 {
+            let state = if env.storage().instance().has(&DataKey::Settled(token_id)) {
+                AuctionState::Settled
+            } else {
+                AuctionState::None
+            };
+
+            return AuctionStatus {
+                state,
+                time_remaining: 0,
+                highest_bid: 0,
+                bid_count: 0,
+            };
+        }
+
+        let (state, time_remaining) = if now > auction_nft.expiration_date {
+            (AuctionState::Expired, 0)
+        } else {
+            (AuctionState::Open, auction_nft.expiration_date - now)
+        };
+
+        AuctionStatus {
+            state,
+            time_remaining,
+            highest_bid: auction_nft.highest_bidder.as_ref().map_or(0, |bid| bid.price),
+            bid_count: auction_nft.bidders.len(),
+        }
+    }
+
     pub fn bid_nft(env: Env, user: Address, token_id: u128, bid_price: i128, xlm_address: Address) {
         user.require_auth();
 
+        Self::place_bid(env, user, token_id, bid_price, xlm_address, true, None);
+    }
+
+    // Bids without transferring the funds upfront. The bidder approves the
+    // contract for the bid amount, which is only collected if they win, so
+    // losing bidders never have capital locked.
+    pub fn bid_nft_with_allowance(env: Env, user: Address, token_id: u128, bid_price: i128, xlm_address: Address) {
+        user.require_auth();
+
+        if !Self::can_pay(env.clone(), user.clone(), bid_price, xlm_address.clone()) {
+            panic!("Insufficient balance or allowance")
+        }
+
+        Self::place_bid(env, user, token_id, bid_price, xlm_address, false, None);
+    }
+
+    // Registered agents, e.g. auction houses bidding for phone clients
+    pub fn set_agent(env: Env, admin: Address, agent: Address, allowed: bool) {
+        Self::check_admin(env.clone(), admin);
+
+        if allowed {
+            env.storage().instance().set(&DataKey::Agent(agent), &true);
+        } else {
+            env.storage().instance().remove(&DataKey::Agent(agent));
+        }
+    }
+
+    pub fn is_agent(env: Env, agent: Address) -> bool {
+        env.storage().instance().has(&DataKey::Agent(agent))
+    }
+
+    // Standing authorization for an agent to bid up to max_price for the
+    // principal, None revokes it
+    pub fn authorize_agent(env: Env, principal: Address, agent: Address, max_price: Option<i128>) {
+        principal.require_auth();
+
+        let key = DataKey::AgentMandate(principal.clone(), agent.clone());
+        match max_price {
+            Some(max_price) => {
+                if max_price <= 0 {
+                    panic!("Max price must be positive")
+                }
+                env.storage().persistent().set(&key, &max_price);
+            }
+            None => env.storage().persistent().remove(&key),
+        }
+
+        let mandate_event = AgentMandateEvent { principal, agent, max_price };
+        Self::publish_event(env.clone(), (BIDEVENT, symbol_short!("mandate")), mandate_event);
+    }
+
+    pub fn agent_mandate(env: Env, principal: Address, agent: Address) -> Option<i128> {
+        env.storage().persistent().get(&DataKey::AgentMandate(principal, agent))
+    }
+
+    // Bids for the principal under their mandate. The bid is backed by the
+    // principal's allowance like bid_nft_with_allowance, and the agent pays
+    // any bid fee.
+    pub fn bid_as_agent(env: Env, agent: Address, principal: Address, token_id: u128, bid_price: i128, xlm_address: Address) {
+        agent.require_auth();
+
+        let max_price = Self::agent_mandate(env.clone(), principal.clone(), agent.clone());
+
+        if !Self::is_agent(env.clone(), agent.clone()) {
+            panic!("Agent not registered")
+        } else if max_price.map_or(true, |max_price| bid_price > max_price) {
+            panic!("Bid exceeds the agent mandate")
+        } else if !Self::can_pay(env.clone(), principal.clone(), bid_price, xlm_address.clone()) {
+            panic!("Insufficient balance or allowance")
+        }
+
+        Self::place_bid(env, principal, token_id, bid_price, xlm_address, false, Some(agent));
+    }
+
+    fn place_bid(
+        env: Env,
+        user: Address,
+        token_id: u128,
+        bid_price: i128,
+        xlm_address: Address,
+        escrowed: bool,
+        agent: Option<Address>,
+    ) {
+        TokenId::from_u128(token_id);
+
         if user == env.current_contract_address() {
             panic!("Sender can not be contract address")
-        } else if token_id == 0 {
-            panic!("Token ID can not be zero")
         }
 
         let mut auction_nft = Self::get_auctioned_nft(env.clone(), token_id);
@@ -166,120 +696,1761 @@ impl NFTAuctionStorefront {
             panic!("Owner NFT can not be bidder")
         } else if auction_nft.token_id == 0 {
             panic!("NFT not auctioned yet")
+        } else if Self::is_bidder_blocked(env.clone(), token_id, user.clone()) {
+            panic!("Bidder is blocked by the seller")
         }
 
         if env.ledger().timestamp() > auction_nft.expiration_date {
             panic!("The auction has expired");
         }
 
+        if let Some(lot) = Self::get_lot(env.clone(), token_id) {
+            if env.ledger().timestamp() < Self::get_sale(env.clone(), lot.sale_id).start {
+                panic!("The sale has not started yet")
+            }
+        }
+
+        Self::check_payment_token(env.clone(), token_id, xlm_address.clone());
+
         let previous_bid = auction_nft.highest_bidder.clone();
 
-        if bid_price <= previous_bid.price {
+        if bid_price <= previous_bid.as_ref().map_or(0, |bid| bid.price) {
             panic!("bid price must be greater than highest bid")
+        } else if bid_price < Self::min_next_bid(env.clone(), token_id) {
+            panic!("bid price is below the minimum increment")
         }
 
         auction_nft.bidders.push_front(Bidder {
             user: user.clone(),
             price: bid_price,
+            escrowed,
+            agent: agent.clone(),
         });
 
-        auction_nft.highest_bidder = HighestBidder {
+        auction_nft.highest_bidder = Some(HighestBidder {
             user: user.clone(),
             price: bid_price,
-        };
+            escrowed,
+            agent: agent.clone(),
+        });
 
         env.storage().instance().set(&token_id, &auction_nft);
+        Self::report_offer(env.clone(), token_id, Some(bid_price));
 
-        // Refund the previous highest bidder
-        if previous_bid.user != env.current_contract_address() {
-            let client = token::Client::new(&env.clone(), &xlm_address);
-            client.transfer(&env.current_contract_address(), &previous_bid.user, &previous_bid.price);
+        // The previous highest bid stays escrowed until the auction closes,
+        // so a bidder that can't receive funds can't block new bids
+        if let Some(previous_bid) = &previous_bid {
+            Self::queue_refund(env.clone(), token_id, previous_bid, xlm_address.clone());
         }
 
         // Transfer XLM to contract address
-        let client = token::Client::new(&env.clone(), &xlm_address);
-        client.transfer(&user, &env.current_contract_address(), &bid_price);
+        if escrowed {
+            let client = token::Client::new(&env.clone(), &xlm_address);
+            client.transfer(&user, &env.current_contract_address(), &bid_price);
+        }
+
+        let fee_payer = agent.clone().unwrap_or(user.clone());
+        Self::charge_bid_fee(env.clone(), fee_payer, token_id, xlm_address.clone());
+
+        let bid_count = auction_nft.bidders.len();
+
+        // Lets notification services alert the displaced bidder directly
+        if let Some(previous_bid) = previous_bid {
+            let outbid_event = OutbidEvent {
+                token_id,
+                outbid_user: previous_bid.user,
+                previous_price: previous_bid.price,
+                new_price: bid_price,
+                new_user: user.clone(),
+                bid_count,
+            };
+            Self::publish_event(env.clone(), (BIDEVENT, symbol_short!("outbid")), outbid_event);
+        }
 
+        if let Some(agent) = agent {
+            let agent_event = AgentBidEvent {
+                token_id,
+                agent,
+                principal: user.clone(),
+                bid_price,
+            };
+            Self::publish_event(env.clone(), (BIDEVENT, symbol_short!("agent")), agent_event);
+        }
 
         let bid_event = BidEvent {
             token_id,
             user,
             bid_price,
+            bid_count,
         };
-        env.events().publish((BIDEVENT, symbol_short!("bid")), bid_event)
+        Self::publish_event(env.clone(), (BIDEVENT, symbol_short!("bid")), bid_event)
     }
 
-    pub fn sell_auctioned_nft(env: Env, owner: Address, token_id: u128, xlm_address: Address) {
-        let nft_client = Self::get_nft_client(env.clone());
-
-        if nft_client.has_nft_owner(&owner.clone(), &token_id) {
-            panic!("Invalid Sender")
-        } else if owner == env.current_contract_address() {
-            panic!("Sender can not be contract address")
-        } else if token_id == 0 {
-            panic!("Token ID can not be zero")
-        }
+    pub fn block_bidder(env: Env, owner: Address, token_id: u128, user: Address) {
+        owner.require_auth();
 
         let auction_nft = Self::get_auctioned_nft(env.clone(), token_id);
 
         if auction_nft.token_id == 0 {
             panic!("NFT not auctioned yet")
+        } else if auction_nft.owner != owner {
+            panic!("Only the owner can block bidders")
+        } else if auction_nft.highest_bidder.as_ref().map_or(false, |bid| bid.user == user) {
+            panic!("Highest bidder can not be blocked")
         }
 
-        if env.ledger().timestamp() < auction_nft.expiration_date {
-            panic!("Auction has not expired yet")
-        }
+        // The seller is stored so blocks don't carry over to a later auction by a new owner
+        env.storage()
+            .persistent()
+            .set(&DataKey::BlockedBidder(token_id, user.clone()), &owner);
 
-        env.storage().instance().remove(&token_id);
+        let block_event = BlockEvent { token_id, owner, user };
+        Self::publish_event(env.clone(), (BLOCKEVENT, symbol_short!("blocked")), block_event)
+    }
 
-        let highest_bid = auction_nft.highest_bidder.clone();
+    pub fn unblock_bidder(env: Env, owner: Address, token_id: u128, user: Address) {
+        owner.require_auth();
 
-        let client = token::Client::new(&env.clone(), &xlm_address);
-        client.transfer(&env.current_contract_address(), &owner, &highest_bid.price);
+        let auction_nft = Self::get_auctioned_nft(env.clone(), token_id);
 
-        nft_client.transfer_from(&owner, &highest_bid.user, &token_id);
+        if auction_nft.owner != owner {
+            panic!("Only the owner can unblock bidders")
+        }
 
-        let sell_event = SellEvent {
-            token_id,
-            buyer: highest_bid.user,
-            price: highest_bid.price,
-        };
+        env.storage()
+            .persistent()
+            .remove(&DataKey::BlockedBidder(token_id, user.clone()));
 
-        env.events().publish((SELLEVENT, symbol_short!("sell")), sell_event)
+        let block_event = BlockEvent { token_id, owner, user };
+        Self::publish_event(env.clone(), (BLOCKEVENT, symbol_short!("unblocked")), block_event)
     }
 
-    pub fn delist_auctioned_nft(env: Env, from: Address, token_id: u128, xlm_address: Address) {
-        from.require_auth();
-
-        let admin = Self::read_administrator(env.clone());
-        let auctioned_nft = Self::get_auctioned_nft(env.clone(), token_id);
+    pub fn is_bidder_blocked(env: Env, token_id: u128, user: Address) -> bool {
+        let blocked_by: Option<Address> = env
+            .storage()
+            .persistent()
+            .get(&DataKey::BlockedBidder(token_id, user));
 
-        if auctioned_nft.token_id == 0 {
-            panic!("NFT not auctioned");
+        match blocked_by {
+            Some(owner) => Self::get_auctioned_nft(env, token_id).owner == owner,
+            None => false,
         }
+    }
 
-        if from != auctioned_nft.owner && from != admin {
-            panic!("Only the owner or admin can delist the auctioned NFT");
-        }
+    pub fn sell_auctioned_nft(env: Env, owner: Address, token_id: u128, xlm_address: Address) {
+        Self::settle_auction(env, owner, token_id, xlm_address, None);
+    }
 
-        env.storage().instance().remove(&token_id);
+    // Settles an expired auction on the seller's behalf, paying the keeper
+    // the bounty for the time the auction sat unsettled
+    pub fn settle_as_keeper(env: Env, keeper: Address, token_id: u128, xlm_address: Address) {
+        keeper.require_auth();
 
-        let highest_bidder = auctioned_nft.highest_bidder.clone();
+        let owner = Self::get_auctioned_nft(env.clone(), token_id).owner;
 
-        if highest_bidder.user != auctioned_nft.owner && highest_bidder.price != 0 {
-            // Refund the previous bidder
-            if highest_bidder.user != env.current_contract_address() {
-                let client = token::Client::new(&env.clone(), &xlm_address);
-                client.transfer(&env.current_contract_address(), &highest_bidder.user, &highest_bidder.price);
-            }
-        }
+        Self::settle_auction(env, owner, token_id, xlm_address, Some(keeper));
+    }
+
+    fn settle_auction(env: Env, owner: Address, token_id: u128, xlm_address: Address, keeper: Option<Address>) {
+        TokenId::from_u128(token_id);
+        let nft_client = Self::get_auction_nft_client(env.clone(), token_id);
+
+        if nft_client.has_nft_owner(&owner.clone(), &token_id) {
+            panic!("Invalid Sender")
+        } else if owner == env.current_contract_address() {
+            panic!("Sender can not be contract address")
+        }
+
+        let mut auction_nft = Self::get_auctioned_nft(env.clone(), token_id);
+
+        // An auction made by an earlier holder can only be invalidated
+        if auction_nft.token_id == 0 {
+            panic!("NFT not auctioned yet")
+        } else if auction_nft.owner != owner {
+            panic!("Auction was made by a previous owner")
+        }
+
+        if env.ledger().timestamp() < auction_nft.expiration_date {
+            panic!("Auction has not expired yet")
+        }
+
+        let settles_at = Self::settles_at(env.clone(), token_id);
+
+        if env.ledger().timestamp() < settles_at {
+            panic!("Auction is still under review")
+        }
+
+        // An unrevealed reserve holds settlement until the reveal window
+        // closes, after which the highest bid wins regardless
+        if env.storage().instance().has(&DataKey::ReserveCommitment(token_id))
+            && !env.storage().instance().has(&DataKey::ReserveRevealed(token_id))
+            && env.ledger().timestamp() < auction_nft.expiration_date + RESERVE_REVEAL_WINDOW
+        {
+            panic!("Reserve reveal window open")
+        }
+
+        Self::check_payment_token(env.clone(), token_id, xlm_address.clone());
+
+        let revealed: Option<i128> = env.storage().instance().get(&DataKey::ReserveRevealed(token_id));
+        let highest_price = auction_nft.highest_bidder.as_ref().map_or(0, |bid| bid.price);
+
+        if revealed.map_or(false, |reserve| highest_price < reserve) {
+            return Self::close_below_reserve(env, &auction_nft, owner, xlm_address);
+        }
+
+        // Every bidder defaulted, the auction closes unsold and the seller
+        // keeps the bond
+        let highest_bid = match Self::collect_winning_bid(env.clone(), &auction_nft, xlm_address.clone()) {
+            Some(bid) => bid,
+            None => {
+                Self::remove_auction(env.clone(), token_id, auction_nft.expiration_date);
+                Self::close_bid_fees(env.clone(), &auction_nft, xlm_address.clone(), false);
+                Self::release_bond(env.clone(), token_id, owner.clone());
+                Self::process_refunds(env.clone(), token_id);
+
+                let delist_event = DelistEvent { token_id, owner };
+                return Self::publish_event(env.clone(), (DLEVENT, symbol_short!("unsold")), delist_event);
+            }
+        };
+        auction_nft.highest_bidder = Some(highest_bid.clone());
+
+        let keeper = keeper.map(|keeper| (keeper, settles_at));
+        let legs = Self::split_payment(
+            env.clone(),
+            nft_client.address.clone(),
+            token_id,
+            owner.clone(),
+            highest_bid.price,
+            keeper,
+        );
+
+        Self::remove_auction(env.clone(), token_id, auction_nft.expiration_date);
+        Self::close_bid_fees(env.clone(), &auction_nft, xlm_address.clone(), true);
+        env.storage().instance().set(&DataKey::Settled(token_id), &true);
+
+        // Honest settlement returns the listing bond to the seller
+        Self::release_bond(env.clone(), token_id, owner.clone());
+        Self::process_refunds(env.clone(), token_id);
+
+        let client = token::Client::new(&env.clone(), &xlm_address);
+        let mut seller_amount = 0;
+        let marketplace_client = NFTMarketplaceClient::new(&env, &nft_client.address);
+        let reports_royalties =
+            nft_marketplace::is_registered(&env, &nft_client.address, &env.current_contract_address());
+
+        for leg in legs.iter() {
+            if leg.kind == SELLER_LEG {
+                seller_amount = leg.amount;
+            } else if leg.amount > 0 {
+                client.transfer(&env.current_contract_address(), &leg.recipient, &leg.amount);
+
+                if leg.kind == ROYALTY_LEG && reports_royalties {
+                    marketplace_client.record_royalty(
+                        &env.current_contract_address(),
+                        &token_id,
+                        &leg.recipient,
+                        &xlm_address,
+                        &leg.amount,
+                    );
+                }
+            }
+        }
+
+        let volume = Self::settled_volume(env.clone(), owner.clone());
+        let volume = Self::checked(&env, volume.checked_add(highest_bid.price));
+        env.storage().persistent().set(&DataKey::Volume(owner.clone()), &volume);
+
+        // High-value sales are paid out in installments held in escrow
+        let config: Option<InstallmentConfig> = env.storage().instance().get(&DataKey::InstallmentConfig);
+
+        match config {
+            Some(config) if highest_bid.price >= config.threshold => {
+                let payout_id: u64 = env.storage().instance().get(&DataKey::PayoutCount).unwrap_or(0) + 1;
+                let payout = InstallmentPayout {
+                    token_id,
+                    seller: owner.clone(),
+                    buyer: highest_bid.user.clone(),
+                    payment_token: xlm_address.clone(),
+                    total: seller_amount,
+                    installments: config.installments,
+                    paid: 0,
+                    start: env.ledger().timestamp(),
+                    interval: config.interval,
+                    reversed: false,
+                };
+
+                env.storage().instance().set(&DataKey::Payout(payout_id), &payout);
+                env.storage().instance().set(&DataKey::PayoutCount, &payout_id);
+            }
+            _ => {
+                client.transfer(&env.current_contract_address(), &owner, &seller_amount);
+            }
+        }
+
+        let settlement_event = SettlementEvent {
+            token_id,
+            payment_token: xlm_address.clone(),
+            price: highest_bid.price,
+            legs,
+        };
+        Self::publish_event(env.clone(), (SETTLEEVENT, symbol_short!("settled")), settlement_event);
+
+        Self::transfer_sold_nft(env.clone(), &nft_client, &owner, &highest_bid.user, token_id);
+
+        if let Some(hook) = Self::get_settlement_hook(env.clone()) {
+            let hook_client = SettlementHookClient::new(&env, &hook);
+            hook_client.on_settlement(&token_id, &highest_bid.user, &highest_bid.price, &xlm_address);
+        }
+
+        // Sale events follow the wallet convention of (action, schema version,
+        // asset contract, token id) topics with amounts and addresses as data
+        let sell_event = SellEvent {
+            token_id,
+            seller: owner,
+            buyer: highest_bid.user,
+            price: highest_bid.price,
+            payment_token: xlm_address,
+        };
+
+        Self::publish_event(
+            env.clone(),
+            (SALE_ACTION, EVENT_SCHEMA_VERSION, nft_client.address.clone(), token_id),
+            sell_event,
+        )
+    }
+
+    pub fn delist_auctioned_nft(env: Env, from: Address, token_id: u128, xlm_address: Address) {
+        from.require_auth();
+
+        let admin = Self::read_administrator(env.clone());
+        let auctioned_nft = Self::get_auctioned_nft(env.clone(), token_id);
+
+        if auctioned_nft.token_id == 0 {
+            panic!("NFT not auctioned");
+        }
+
+        if from != auctioned_nft.owner && from != admin {
+            panic!("Only the owner or admin can delist the auctioned NFT");
+        }
+
+        Self::check_payment_token(env.clone(), token_id, xlm_address.clone());
+
+        Self::remove_auction(env.clone(), token_id, auctioned_nft.expiration_date);
+        Self::close_bid_fees(env.clone(), &auctioned_nft, xlm_address.clone(), false);
+
+        // Refund the previous bidder
+        if let Some(highest_bidder) = &auctioned_nft.highest_bidder {
+            Self::refund_bid(env.clone(), highest_bidder, xlm_address.clone());
+        }
+        Self::process_refunds(env.clone(), token_id);
+
+        // A seller who delists after bids forfeits the bond to the highest
+        // bidder, unless that bidder's allowance bid can no longer be paid
+        match auctioned_nft.highest_bidder.clone() {
+            Some(highest_bidder)
+                if from == auctioned_nft.owner
+                    && (highest_bidder.escrowed
+                        || Self::can_pay(env.clone(), highest_bidder.user.clone(), highest_bidder.price, xlm_address.clone())) =>
+            {
+                Self::release_bond(env.clone(), token_id, highest_bidder.user);
+            }
+            _ => Self::release_bond(env.clone(), token_id, auctioned_nft.owner),
+        }
+
+        let delist_event = DelistEvent {
+            token_id,
+            owner: from,
+        };
+
+        Self::publish_event(env.clone(), (DLEVENT, symbol_short!("delisted")), delist_event)
+    }
+
+    // Cancels an auction whose seller no longer holds the NFT. Anyone can call
+    // it; the highest bid is refunded and the seller's bond goes to the bidder.
+    pub fn invalidate_auction(env: Env, token_id: u128, xlm_address: Address) {
+        let nft_client = Self::get_auction_nft_client(env.clone(), token_id);
+        let auctioned_nft = Self::get_auctioned_nft(env.clone(), token_id);
+
+        if auctioned_nft.token_id == 0 {
+            panic!("NFT not auctioned");
+        } else if !nft_client.has_nft_owner(&auctioned_nft.owner, &token_id) {
+            panic!("Seller still owns the NFT")
+        }
+
+        Self::check_payment_token(env.clone(), token_id, xlm_address.clone());
+
+        Self::remove_auction(env.clone(), token_id, auctioned_nft.expiration_date);
+        Self::close_bid_fees(env.clone(), &auctioned_nft, xlm_address.clone(), false);
+
+        if let Some(highest_bidder) = auctioned_nft.highest_bidder.clone() {
+            Self::refund_bid(env.clone(), &highest_bidder, xlm_address.clone());
+
+            Self::release_bond(env.clone(), token_id, highest_bidder.user);
+        } else {
+            Self::release_bond(env.clone(), token_id, auctioned_nft.owner.clone());
+        }
+        Self::process_refunds(env.clone(), token_id);
+
+        let delist_event = DelistEvent {
+            token_id,
+            owner: auctioned_nft.owner,
+        };
+
+        Self::publish_event(env.clone(), (DLEVENT, symbol_short!("invalid")), delist_event)
+    }
+
+    // Bond sellers lock in the payment token when creating an auction
+    pub fn set_bond_amount(env: Env, admin: Address, amount: i128) {
+        Self::check_admin(env.clone(), admin);
+
+        if amount < 0 {
+            panic!("Bond can not be negative")
+        }
+
+        env.storage().instance().set(&DataKey::BondAmount, &amount);
+    }
+
+    pub fn get_bond_amount(env: Env) -> i128 {
+        env.storage().instance().get(&DataKey::BondAmount).unwrap_or(0)
+    }
+
+    pub fn get_bond(env: Env, token_id: u128) -> Option<SellerBond> {
+        env.storage().persistent().get(&DataKey::Bond(token_id))
+    }
+
+    pub fn create_sale(env: Env, admin: Address, title: String, start: u64, end: u64) -> u64 {
+        Self::check_admin(env.clone(), admin);
+
+        if end <= start {
+            panic!("Invalid time range")
+        }
+
+        let sale_id: u64 = env.storage().instance().get(&DataKey::SaleCount).unwrap_or(0) + 1;
+        let sale = AuctionSale {
+            sale_id,
+            title,
+            start,
+            end,
+        };
+
+        env.storage().persistent().set(&DataKey::Sale(sale_id), &sale);
+        env.storage().instance().set(&DataKey::SaleCount, &sale_id);
+        Self::publish_event(env.clone(), (SALEEVENT, symbol_short!("created")), sale);
+
+        sale_id
+    }
+
+    pub fn get_sale(env: Env, sale_id: u64) -> AuctionSale {
+        env.storage()
+            .persistent()
+            .get(&DataKey::Sale(sale_id))
+            .expect("Sale not found")
+    }
+
+    // Adds an open auction to the sale as its next lot
+    pub fn add_lot(env: Env, admin: Address, sale_id: u64, token_id: u128) -> u32 {
+        Self::check_admin(env.clone(), admin);
+
+        Self::get_sale(env.clone(), sale_id);
+
+        if Self::get_auctioned_nft(env.clone(), token_id).token_id == 0 {
+            panic!("NFT not auctioned")
+        } else if Self::get_lot(env.clone(), token_id).is_some() {
+            panic!("NFT already in a sale")
+        }
+
+        let mut lots = Self::sale_lots(env.clone(), sale_id);
+        lots.push_back(token_id);
+
+        let lot_number = lots.len();
+        let lot = Lot { sale_id, lot_number };
+
+        env.storage().persistent().set(&DataKey::SaleLots(sale_id), &lots);
+        env.storage().instance().set(&DataKey::Lot(token_id), &lot);
+
+        let lot_event = LotEvent {
+            sale_id,
+            lot_number,
+            token_id,
+        };
+        Self::publish_event(env.clone(), (SALEEVENT, symbol_short!("lot")), lot_event);
+
+        lot_number
+    }
+
+    // Token ids of the sale in lot order, lot n at index n - 1
+    pub fn sale_lots(env: Env, sale_id: u64) -> Vec<u128> {
+        env.storage()
+            .persistent()
+            .get(&DataKey::SaleLots(sale_id))
+            .unwrap_or(Vec::new(&env))
+    }
+
+    pub fn get_lot(env: Env, token_id: u128) -> Option<Lot> {
+        env.storage().instance().get(&DataKey::Lot(token_id))
+    }
+
+    // Returns the auctions expiring in [start, end], read from the daily buckets
+    pub fn auctions_expiring_between(env: Env, start: u64, end: u64) -> Vec<u128> {
+        if end < start {
+            panic!("Invalid time range")
+        } else if end / DAY_IN_SECONDS - start / DAY_IN_SECONDS >= MAX_EXPIRY_QUERY_DAYS {
+            panic!("Time range too large")
+        }
+
+        let mut token_ids = Vec::new(&env);
+
+        for day in (start / DAY_IN_SECONDS)..=(end / DAY_IN_SECONDS) {
+            let bucket: Vec<u128> = env
+                .storage()
+                .instance()
+                .get(&DataKey::ExpiryBucket(day))
+                .unwrap_or(Vec::new(&env));
+
+            for token_id in bucket.iter() {
+                let auction_nft = Self::get_auctioned_nft(env.clone(), token_id);
+
+                if auction_nft.expiration_date >= start && auction_nft.expiration_date <= end {
+                    token_ids.push_back(token_id);
+                }
+            }
+        }
+
+        token_ids
+    }
+
+    pub fn set_payment_token(env: Env, admin: Address, payment_token: Address) {
+        Self::check_admin(env.clone(), admin);
+
+        env.storage().instance().set(&DataKey::PaymentToken, &payment_token);
+    }
+
+    pub fn get_payment_token(env: Env) -> Option<Address> {
+        env.storage().instance().get(&DataKey::PaymentToken)
+    }
+
+    pub fn get_auction_token(env: Env, token_id: u128) -> Option<Address> {
+        env.storage().instance().get(&DataKey::AuctionToken(token_id))
+    }
+
+    // Moves auctions created before a payment token was configured onto the
+    // stored token. A bid locked in a different legacy token is refunded so
+    // the bidder can bid again in the stored token.
+    pub fn migrate_auction_tokens(env: Env, admin: Address, token_ids: Vec<u128>, legacy_token: Address) {
+        Self::check_admin(env.clone(), admin);
+
+        let payment_token = Self::get_payment_token(env.clone()).expect("Payment token not configured");
+
+        for token_id in token_ids.iter() {
+            let mut auction_nft = Self::get_auctioned_nft(env.clone(), token_id);
+
+            if auction_nft.token_id == 0 {
+                panic!("NFT not auctioned yet")
+            } else if Self::get_auction_token(env.clone(), token_id).is_some() {
+                panic!("Auction already migrated")
+            }
+
+            let mut refunded = 0;
+
+            match auction_nft.highest_bidder.clone() {
+                Some(highest_bid) if legacy_token != payment_token => {
+                    // Allowance bids were given in the legacy token and are simply dropped
+                    if highest_bid.escrowed {
+                        Self::refund_bid(env.clone(), &highest_bid, legacy_token.clone());
+                        refunded = highest_bid.price;
+                    }
+
+                    // Bid fees were paid in the legacy token too, and the bid
+                    // list goes so no legacy allowance bid can be collected
+                    Self::close_bid_fees(env.clone(), &auction_nft, legacy_token.clone(), false);
+
+                    auction_nft.highest_bidder = None;
+                    auction_nft.bidders = Vec::new(&env);
+                    env.storage().instance().set(&token_id, &auction_nft);
+                }
+                _ => {}
+            }
+
+            env.storage().instance().set(&DataKey::AuctionToken(token_id), &payment_token);
+
+            let migrate_event = MigrateEvent {
+                token_id,
+                payment_token: payment_token.clone(),
+                refunded,
+            };
+            Self::publish_event(env.clone(), (MIGEVENT, symbol_short!("migrated")), migrate_event);
+        }
+    }
+
+    // Disaster recovery after the NFT contract was replaced: re-points open
+    // auctions from old to new, keeping their bids, bonds and settings. The
+    // listing registry lock moves along with them.
+    pub fn remap_nft_contract(env: Env, admin: Address, old: Address, new: Address, token_ids: Vec<u128>) {
+        Self::check_admin(env.clone(), admin);
+
+        if old == new {
+            panic!("Collections must differ")
+        }
+
+        for token_id in token_ids.iter() {
+            if Self::get_auctioned_nft(env.clone(), token_id).token_id == 0 {
+                panic!("NFT not auctioned yet")
+            } else if Self::get_auction_collection(env.clone(), token_id) != old {
+                panic!("Auction is not on the old collection")
+            }
+
+            Self::unlock_listing(env.clone(), token_id);
+
+            if new == Self::get_nft_client(env.clone()).address {
+                env.storage().instance().remove(&DataKey::AuctionCollection(token_id));
+            } else {
+                env.storage().instance().set(&DataKey::AuctionCollection(token_id), &new);
+            }
+
+            Self::lock_listing(env.clone(), token_id);
+
+            let remap_event = RemapEvent {
+                token_id,
+                old_collection: old.clone(),
+                new_collection: new.clone(),
+            };
+            Self::publish_event(env.clone(), (MIGEVENT, symbol_short!("remapped")), remap_event);
+        }
+    }
+
+    // NFT contract an auction's token lives in, the configured one unless the
+    // auction was remapped
+    pub fn get_auction_collection(env: Env, token_id: u128) -> Address {
+        env.storage()
+            .instance()
+            .get(&DataKey::AuctionCollection(token_id))
+            .unwrap_or(Self::get_nft_client(env.clone()).address)
+    }
+
+    // The fee is split between the shares, whose bps must add up to fee_bps
+    pub fn set_fee(env: Env, admin: Address, fee_bps: u32, shares: Vec<FeeShare>) {
+        Self::check_admin(env.clone(), admin);
+
+        if fee_bps > MAX_BPS {
+            panic!("Fee can not exceed 100%")
+        } else if shares.is_empty() {
+            panic!("Fee needs at least one recipient")
+        }
+
+        let mut total_bps: u32 = 0;
+        for share in shares.iter() {
+            total_bps = Self::checked(&env, total_bps.checked_add(share.bps));
+        }
+
+        if total_bps != fee_bps {
+            panic!("Fee shares must sum to the total fee")
+        }
+
+        let max_fee = fee_bps.max(Self::max_collection_fee(env.clone()));
+        Self::check_sale_bps(env.clone(), Self::royalty_bps(env.clone()), max_fee, Self::referral_bps(env.clone()));
+
+        env.storage().instance().set(&DataKey::Fee, &FeeConfig { fee_bps, shares });
+    }
+
+    pub fn get_fee(env: Env) -> Option<FeeConfig> {
+        env.storage().instance().get(&DataKey::Fee)
+    }
+
+    // Overrides the global fee_bps for one NFT contract, None restores the
+    // global fee. The override is still split between the global fee shares.
+    pub fn set_collection_fee(env: Env, admin: Address, collection: Address, fee_bps: Option<u32>) {
+        Self::check_admin(env.clone(), admin);
+
+        match fee_bps {
+            Some(fee_bps) => {
+                if fee_bps > MAX_BPS {
+                    panic!("Fee can not exceed 100%")
+                } else if Self::get_fee(env.clone()).is_none() {
+                    panic!("Fee recipients not configured")
+                }
+                Self::check_sale_bps(env.clone(), Self::royalty_bps(env.clone()), fee_bps, Self::referral_bps(env.clone()));
+
+                let mut collections = Self::fee_collections(env.clone());
+                if !collections.contains(&collection) {
+                    collections.push_back(collection.clone());
+                    env.storage().instance().set(&DataKey::FeeCollections, &collections);
+                }
+                env.storage().instance().set(&DataKey::CollectionFee(collection.clone()), &fee_bps);
+            }
+            None => {
+                let mut collections = Self::fee_collections(env.clone());
+                if let Some(index) = collections.first_index_of(&collection) {
+                    collections.remove(index);
+                    env.storage().instance().set(&DataKey::FeeCollections, &collections);
+                }
+                env.storage().instance().remove(&DataKey::CollectionFee(collection.clone()));
+            }
+        }
+
+        let fee_event = CollectionFeeEvent { collection, fee_bps };
+        Self::publish_event(env.clone(), (FEEEVENT, symbol_short!("override")), fee_event);
+    }
+
+    pub fn get_collection_fee(env: Env, collection: Address) -> Option<u32> {
+        env.storage().instance().get(&DataKey::CollectionFee(collection))
+    }
+
+    // Fee in bps charged when settling a sale of the collection, before rebates
+    pub fn effective_fee(env: Env, collection: Address) -> u32 {
+        let fee_config = match Self::get_fee(env.clone()) {
+            Some(fee_config) => fee_config,
+            None => return 0,
+        };
+
+        Self::get_collection_fee(env, collection).unwrap_or(fee_config.fee_bps)
+    }
+
+    pub fn set_royalty(env: Env, admin: Address, recipient: Address, bps: u32) {
+        Self::check_admin(env.clone(), admin);
+
+        if bps > MAX_BPS {
+            panic!("Royalty can not exceed 100%")
+        }
+        Self::check_sale_bps(env.clone(), bps, Self::max_fee_bps(env.clone()), Self::referral_bps(env.clone()));
+
+        env.storage().instance().set(&DataKey::Royalty, &RoyaltyConfig { recipient, bps });
+    }
+
+    pub fn get_royalty(env: Env) -> Option<RoyaltyConfig> {
+        env.storage().instance().get(&DataKey::Royalty)
+    }
+
+    // Share of the sale price paid to the referrer the seller designated
+    pub fn set_referral_bps(env: Env, admin: Address, bps: u32) {
+        Self::check_admin(env.clone(), admin);
+
+        if bps > MAX_BPS {
+            panic!("Referral can not exceed 100%")
+        }
+        Self::check_sale_bps(env.clone(), Self::royalty_bps(env.clone()), Self::max_fee_bps(env.clone()), bps);
+
+        env.storage().instance().set(&DataKey::ReferralBps, &bps);
+    }
+
+    // Royalty, fee and referral are all taken out of the sale price, so with
+    // the highest fee any collection can be charged they can't exceed it
+    fn check_sale_bps(env: Env, royalty_bps: u32, fee_bps: u32, referral_bps: u32) {
+        let total = Self::checked(&env, royalty_bps.checked_add(fee_bps));
+        let total = Self::checked(&env, total.checked_add(referral_bps));
+
+        if total > MAX_BPS {
+            panic!("Royalty, fee and referral can not exceed 100%")
+        }
+    }
+
+    fn royalty_bps(env: Env) -> u32 {
+        Self::get_royalty(env).map_or(0, |royalty| royalty.bps)
+    }
+
+    fn referral_bps(env: Env) -> u32 {
+        env.storage().instance().get(&DataKey::ReferralBps).unwrap_or(0)
+    }
+
+    // Collections with a fee override, so the highest fee can be found
+    fn fee_collections(env: Env) -> Vec<Address> {
+        env.storage().instance().get(&DataKey::FeeCollections).unwrap_or(Vec::new(&env))
+    }
+
+    fn max_collection_fee(env: Env) -> u32 {
+        let mut max_fee = 0;
+        for collection in Self::fee_collections(env.clone()).iter() {
+            max_fee = max_fee.max(Self::get_collection_fee(env.clone(), collection).unwrap_or(0));
+        }
+        max_fee
+    }
+
+    fn max_fee_bps(env: Env) -> u32 {
+        let fee_bps = Self::get_fee(env.clone()).map_or(0, |fee_config| fee_config.fee_bps);
+        fee_bps.max(Self::max_collection_fee(env))
+    }
+
+    pub fn set_referrer(env: Env, owner: Address, token_id: u128, referrer: Address) {
+        owner.require_auth();
+
+        if Self::get_auctioned_nft(env.clone(), token_id).owner != owner {
+            panic!("Only the auction owner can set the referrer")
+        }
+
+        env.storage().instance().set(&DataKey::Referrer(token_id), &referrer);
+    }
+
+    pub fn get_referrer(env: Env, token_id: u128) -> Option<Address> {
+        env.storage().instance().get(&DataKey::Referrer(token_id))
+    }
+
+    pub fn set_keeper_bounty(env: Env, admin: Address, max_bps: u32, cap: i128, ramp: u64) {
+        Self::check_admin(env.clone(), admin);
+
+        if max_bps > MAX_BPS {
+            panic!("Bounty can not exceed 100%")
+        } else if cap < 0 {
+            panic!("Bounty cap can not be negative")
+        }
+
+        env.storage().instance().set(&DataKey::KeeperBounty, &KeeperBounty { max_bps, cap, ramp });
+    }
+
+    pub fn get_keeper_bounty(env: Env) -> Option<KeeperBounty> {
+        env.storage().instance().get(&DataKey::KeeperBounty)
+    }
+
+    // Bounty a keeper settling the auction now would receive, before it is
+    // limited to the marketplace fee
+    pub fn keeper_bounty(env: Env, token_id: u128) -> i128 {
+        let auction_nft = Self::get_auctioned_nft(env.clone(), token_id);
+
+        if auction_nft.token_id == 0 {
+            panic!("NFT not auctioned");
+        }
+
+        let settles_at = Self::settles_at(env.clone(), token_id);
+
+        let price = auction_nft.highest_bidder.map_or(0, |bid| bid.price);
+
+        Self::bounty_for(env, price, settles_at)
+    }
+
+    // Review period after expiry during which the admin can void a sale
+    // before anyone can settle it
+    pub fn set_settlement_delay(env: Env, admin: Address, delay: u64) {
+        Self::check_admin(env.clone(), admin);
+
+        env.storage().instance().set(&DataKey::SettlementDelay, &delay);
+    }
+
+    pub fn get_settlement_delay(env: Env) -> u64 {
+        env.storage().instance().get(&DataKey::SettlementDelay).unwrap_or(0)
+    }
+
+    // First timestamp at which the auction can be settled
+    pub fn settles_at(env: Env, token_id: u128) -> u64 {
+        let auction_nft = Self::get_auctioned_nft(env.clone(), token_id);
+
+        if auction_nft.token_id == 0 {
+            panic!("NFT not auctioned");
+        }
+
+        let delay = Self::get_settlement_delay(env.clone());
+
+        Self::checked(&env, auction_nft.expiration_date.checked_add(delay))
+    }
+
+    // Cancels an expired auction flagged for fraud during its review period.
+    // The highest bid is refunded, the NFT stays with the seller and the
+    // bond is returned to them.
+    pub fn void_auction(env: Env, admin: Address, token_id: u128, xlm_address: Address) {
+        Self::check_admin(env.clone(), admin.clone());
+
+        let auctioned_nft = Self::get_auctioned_nft(env.clone(), token_id);
+
+        if auctioned_nft.token_id == 0 {
+            panic!("NFT not auctioned");
+        } else if env.ledger().timestamp() < auctioned_nft.expiration_date {
+            panic!("Auction has not expired yet")
+        } else if env.ledger().timestamp() >= Self::settles_at(env.clone(), token_id) {
+            panic!("Review period is over")
+        }
+
+        Self::check_payment_token(env.clone(), token_id, xlm_address.clone());
+
+        Self::remove_auction(env.clone(), token_id, auctioned_nft.expiration_date);
+        Self::close_bid_fees(env.clone(), &auctioned_nft, xlm_address.clone(), false);
+
+        if let Some(highest_bidder) = &auctioned_nft.highest_bidder {
+            Self::refund_bid(env.clone(), highest_bidder, xlm_address.clone());
+        }
+
+        Self::release_bond(env.clone(), token_id, auctioned_nft.owner.clone());
+        Self::process_refunds(env.clone(), token_id);
+
+        let delist_event = DelistEvent {
+            token_id,
+            owner: auctioned_nft.owner,
+        };
+
+        Self::publish_event(env.clone(), (DLEVENT, symbol_short!("voided")), delist_event)
+    }
+
+    // Returns the next batch of outbid escrows left over when the auction
+    // closed with more than REFUND_BATCH_SIZE of them. Anyone can call it.
+    pub fn continue_refunds(env: Env, token_id: u128) -> u32 {
+        if Self::pending_refunds(env.clone(), token_id) == 0 {
+            panic!("No pending refunds")
+        }
+
+        Self::process_refunds(env, token_id)
+    }
+
+    pub fn pending_refunds(env: Env, token_id: u128) -> u32 {
+        let refunds: Vec<PendingRefund> = env
+            .storage()
+            .persistent()
+            .get(&DataKey::PendingRefunds(token_id))
+            .unwrap_or(Vec::new(&env));
+
+        refunds.len()
+    }
+
+    // Commits to a hidden reserve price before bidding starts, as sha256 of
+    // the reserve as 16 big-endian bytes followed by a 32 byte salt
+    pub fn commit_reserve(env: Env, owner: Address, token_id: u128, commitment: BytesN<32>) {
+        owner.require_auth();
+
+        let auction_nft = Self::get_auctioned_nft(env.clone(), token_id);
+
+        if auction_nft.token_id == 0 {
+            panic!("NFT not auctioned");
+        } else if auction_nft.owner != owner {
+            panic!("Only the owner can commit a reserve")
+        } else if auction_nft.highest_bidder.is_some() {
+            panic!("Auction already has bids")
+        }
+
+        env.storage().instance().set(&DataKey::ReserveCommitment(token_id), &commitment);
+    }
+
+    pub fn get_reserve_commitment(env: Env, token_id: u128) -> Option<BytesN<32>> {
+        env.storage().instance().get(&DataKey::ReserveCommitment(token_id))
+    }
+
+    // Reveals the reserve once the auction has its first bid. After expiry a
+    // highest bid short of it ends the auction unsold, refunding the bidders
+    // and the bond; revealed while bidding is open, settlement does the same.
+    pub fn reveal_reserve(env: Env, owner: Address, token_id: u128, reserve: i128, salt: BytesN<32>, xlm_address: Address) {
+        owner.require_auth();
+
+        let auction_nft = Self::get_auctioned_nft(env.clone(), token_id);
+        let commitment = Self::get_reserve_commitment(env.clone(), token_id).expect("No reserve committed");
+
+        if auction_nft.owner != owner {
+            panic!("Only the owner can reveal the reserve")
+        } else if env.ledger().timestamp() < auction_nft.expiration_date && auction_nft.highest_bidder.is_none() {
+            panic!("Auction has no bids yet")
+        } else if env.ledger().timestamp() >= auction_nft.expiration_date + RESERVE_REVEAL_WINDOW {
+            panic!("Reveal window is over")
+        } else if env.storage().instance().has(&DataKey::ReserveRevealed(token_id)) {
+            panic!("Reserve already revealed")
+        }
+
+        let mut preimage = Bytes::from_array(&env, &reserve.to_be_bytes());
+        preimage.append(&Bytes::from_array(&env, &salt.to_array()));
+
+        if env.crypto().sha256(&preimage) != commitment {
+            panic!("Reserve does not match commitment")
+        }
+
+        let highest_price = auction_nft.highest_bidder.as_ref().map_or(0, |bid| bid.price);
+
+        if highest_price >= reserve || env.ledger().timestamp() < auction_nft.expiration_date {
+            env.storage().instance().set(&DataKey::ReserveRevealed(token_id), &reserve);
+            return;
+        }
+
+        Self::check_payment_token(env.clone(), token_id, xlm_address.clone());
+        Self::close_below_reserve(env, &auction_nft, owner, xlm_address);
+    }
+
+    pub fn set_bid_fee(env: Env, admin: Address, fee: i128, refund_winner: bool, max_bids_per_ledger: u32) {
+        Self::check_admin(env.clone(), admin);
+
+        if fee < 0 {
+            panic!("Bid fee can not be negative")
+        }
+
+        let config = BidFeeConfig {
+            fee,
+            refund_winner,
+            max_bids_per_ledger,
+        };
+        env.storage().instance().set(&DataKey::BidFee, &config);
+    }
+
+    pub fn get_bid_fee(env: Env) -> Option<BidFeeConfig> {
+        env.storage().instance().get(&DataKey::BidFee)
+    }
+
+    // Bid fees collected from closed auctions in a payment token
+    pub fn fee_pool(env: Env, payment_token: Address) -> i128 {
+        env.storage().persistent().get(&DataKey::FeePool(payment_token)).unwrap_or(0)
+    }
+
+    pub fn withdraw_fee_pool(env: Env, admin: Address, payment_token: Address, to: Address) -> i128 {
+        Self::check_admin(env.clone(), admin);
+
+        let amount = Self::fee_pool(env.clone(), payment_token.clone());
+
+        if amount > 0 {
+            env.storage().persistent().remove(&DataKey::FeePool(payment_token.clone()));
+
+            let client = token::Client::new(&env, &payment_token);
+            client.transfer(&env.current_contract_address(), &to, &amount);
+        }
+
+        amount
+    }
+
+    // Tiers map a seller's settled volume to a rebate on the marketplace fee
+    // and must be sorted by ascending volume
+    pub fn set_fee_tiers(env: Env, admin: Address, tiers: Vec<FeeTier>) {
+        Self::check_admin(env.clone(), admin);
+
+        let mut previous: Option<FeeTier> = None;
+        for tier in tiers.iter() {
+            if tier.rebate_bps > MAX_BPS {
+                panic!("Rebate can not exceed 100%")
+            }
+            if let Some(previous) = previous {
+                if tier.min_volume <= previous.min_volume {
+                    panic!("Fee tiers must be sorted by volume")
+                }
+            }
+            previous = Some(tier);
+        }
+
+        env.storage().instance().set(&DataKey::FeeTiers, &tiers);
+    }
+
+    // Global increment ladder for auctions without their own, must be sorted
+    // by ascending price. An empty ladder only requires outbidding.
+    pub fn set_increment_schedule(env: Env, admin: Address, steps: Vec<IncrementStep>) {
+        Self::check_admin(env.clone(), admin);
+        Self::check_increment_steps(&steps);
+
+        env.storage().instance().set(&DataKey::IncrementSchedule, &steps);
+    }
+
+    pub fn get_increment_schedule(env: Env) -> Vec<IncrementStep> {
+        env.storage().instance().get(&DataKey::IncrementSchedule).unwrap_or(Vec::new(&env))
+    }
+
+    // Overrides the global ladder for one auction before bidding starts,
+    // None falls back to the global ladder
+    pub fn set_auction_increments(env: Env, owner: Address, token_id: u128, steps: Option<Vec<IncrementStep>>) {
+        owner.require_auth();
+
+        let auction_nft = Self::get_auctioned_nft(env.clone(), token_id);
+
+        if auction_nft.token_id == 0 {
+            panic!("NFT not auctioned");
+        } else if auction_nft.owner != owner {
+            panic!("Only the owner can set increments")
+        } else if auction_nft.highest_bidder.is_some() {
+            panic!("Auction already has bids")
+        }
+
+        match steps {
+            Some(steps) => {
+                Self::check_increment_steps(&steps);
+                env.storage().instance().set(&DataKey::AuctionIncrements(token_id), &steps);
+            }
+            None => env.storage().instance().remove(&DataKey::AuctionIncrements(token_id)),
+        }
+    }
 
-        let delist_event = DelistEvent {
+    // Ladder applied to the auction, its own or the global one
+    pub fn get_increments(env: Env, token_id: u128) -> Vec<IncrementStep> {
+        env.storage()
+            .instance()
+            .get(&DataKey::AuctionIncrements(token_id))
+            .unwrap_or(Self::get_increment_schedule(env.clone()))
+    }
+
+    // Lowest price the next bid on the auction may offer
+    pub fn min_next_bid(env: Env, token_id: u128) -> i128 {
+        let auction_nft = Self::get_auctioned_nft(env.clone(), token_id);
+        let highest = match auction_nft.highest_bidder {
+            Some(bid) => bid.price,
+            None => return 1,
+        };
+
+        let mut increment_bps = 0;
+        for step in Self::get_increments(env.clone(), token_id).iter() {
+            if highest >= step.min_price {
+                increment_bps = step.increment_bps;
+            }
+        }
+
+        let increment = Self::bps_of(&env, highest, increment_bps).max(1);
+        Self::checked(&env, highest.checked_add(increment))
+    }
+
+    pub fn settled_volume(env: Env, account: Address) -> i128 {
+        env.storage().persistent().get(&DataKey::Volume(account)).unwrap_or(0)
+    }
+
+    // Returns the fee rebate in bps the account currently qualifies for
+    pub fn fee_tier(env: Env, account: Address) -> u32 {
+        let volume = Self::settled_volume(env.clone(), account);
+        let tiers: Vec<FeeTier> = env.storage().instance().get(&DataKey::FeeTiers).unwrap_or(Vec::new(&env));
+
+        let mut rebate_bps = 0;
+        for tier in tiers.iter() {
+            if volume >= tier.min_volume {
+                rebate_bps = tier.rebate_bps;
+            }
+        }
+
+        rebate_bps
+    }
+
+    pub fn set_settlement_hook(env: Env, admin: Address, hook: Address) {
+        Self::check_admin(env.clone(), admin);
+
+        env.storage().instance().set(&DataKey::SettlementHook, &hook);
+    }
+
+    pub fn clear_settlement_hook(env: Env, admin: Address) {
+        Self::check_admin(env.clone(), admin);
+
+        env.storage().instance().remove(&DataKey::SettlementHook);
+    }
+
+    pub fn get_settlement_hook(env: Env) -> Option<Address> {
+        env.storage().instance().get(&DataKey::SettlementHook)
+    }
+
+    pub fn set_installment_config(env: Env, admin: Address, threshold: i128, installments: u32, interval: u64) {
+        Self::check_admin(env.clone(), admin);
+
+        if installments < 2 {
+            panic!("Installments must be at least two")
+        } else if threshold <= 0 {
+            panic!("Threshold must be positive")
+        }
+
+        let config = InstallmentConfig {
+            threshold,
+            installments,
+            interval,
+        };
+
+        env.storage().instance().set(&DataKey::InstallmentConfig, &config);
+    }
+
+    pub fn clear_installment_config(env: Env, admin: Address) {
+        Self::check_admin(env.clone(), admin);
+
+        env.storage().instance().remove(&DataKey::InstallmentConfig);
+    }
+
+    pub fn get_installment_payout(env: Env, payout_id: u64) -> InstallmentPayout {
+        env.storage()
+            .instance()
+            .get(&DataKey::Payout(payout_id))
+            .expect("Payout not found")
+    }
+
+    // Pays out every tranche that has vested since the last claim. The first
+    // tranche vests at sale, each following one after another interval.
+    pub fn claim_installments(env: Env, seller: Address, payout_id: u64) -> i128 {
+        seller.require_auth();
+
+        let mut payout = Self::get_installment_payout(env.clone(), payout_id);
+
+        if payout.seller != seller {
+            panic!("Only the seller can claim installments")
+        }
+
+        let elapsed = env.ledger().timestamp() - payout.start;
+        let periods = if payout.interval == 0 { payout.installments as u64 } else { elapsed / payout.interval };
+
+        // Capped before narrowing so a long elapsed time can't wrap the count
+        let mut vested = if periods >= payout.installments as u64 {
+            payout.installments
+        } else {
+            periods as u32 + 1
+        };
+        if payout.reversed && vested == payout.installments {
+            vested -= 1;
+        }
+        if vested <= payout.paid {
+            panic!("No installment due")
+        }
+
+        let mut amount: i128 = 0;
+        for tranche in payout.paid..vested {
+            amount = Self::checked(&env, amount.checked_add(Self::installment_amount(&payout, tranche)));
+        }
+
+        payout.paid = vested;
+        env.storage().instance().set(&DataKey::Payout(payout_id), &payout);
+
+        let client = token::Client::new(&env, &payout.payment_token);
+        client.transfer(&env.current_contract_address(), &seller, &amount);
+
+        let installment_event = InstallmentEvent {
+            payout_id,
+            seller,
+            amount,
+        };
+        Self::publish_event(env.clone(), (INSTEVENT, symbol_short!("claimed")), installment_event);
+
+        amount
+    }
+
+    // Refunds the final tranche to the buyer during the fraud-review window
+    pub fn reverse_final_installment(env: Env, admin: Address, payout_id: u64) {
+        Self::check_admin(env.clone(), admin);
+
+        let mut payout = Self::get_installment_payout(env.clone(), payout_id);
+
+        if payout.reversed {
+            panic!("Final installment already reversed")
+        } else if payout.paid == payout.installments {
+            panic!("Final installment already paid")
+        }
+
+        let amount = Self::installment_amount(&payout, payout.installments - 1);
+
+        payout.reversed = true;
+        env.storage().instance().set(&DataKey::Payout(payout_id), &payout);
+
+        let client = token::Client::new(&env, &payout.payment_token);
+        client.transfer(&env.current_contract_address(), &payout.buyer, &amount);
+
+        let reverse_event = ReverseEvent {
+            payout_id,
+            buyer: payout.buyer,
+            amount,
+        };
+        Self::publish_event(env.clone(), (REVEVENT, symbol_short!("reversed")), reverse_event);
+    }
+
+    // Splits a sale price into royalty, fee, referral and seller legs. The
+    // seller leg receives whatever the other legs leave, so the legs always
+    // add up to the price.
+    fn split_payment(
+        env: Env,
+        collection: Address,
+        token_id: u128,
+        seller: Address,
+        price: i128,
+        keeper: Option<(Address, u64)>,
+    ) -> Vec<PaymentLeg> {
+        let mut legs = Vec::new(&env);
+        let mut remaining = price;
+
+        if let Some(royalty) = Self::get_royalty(env.clone()) {
+            let amount = Self::bps_of(&env, price, royalty.bps);
+            remaining = Self::take_leg(&env, &mut legs, ROYALTY_LEG, royalty.recipient, amount, remaining);
+        }
+
+        let mut fee = Self::settlement_fee(env.clone(), collection, seller.clone(), price);
+
+        // The keeper bounty is carved out of the fee before the shares
+        if let Some((keeper, settles_at)) = keeper {
+            let bounty = Self::bounty_for(env.clone(), price, settles_at).min(fee);
+            remaining = Self::take_leg(&env, &mut legs, KEEPER_LEG, keeper, bounty, remaining);
+            fee -= bounty;
+        }
+
+        if fee > 0 {
+            // Each share gets its part of the fee, the last one the rounding remainder
+            let fee_config = Self::get_fee(env.clone()).unwrap();
+            let last = fee_config.shares.len() - 1;
+            let mut paid = 0;
+
+            for (index, share) in fee_config.shares.iter().enumerate() {
+                let amount = if index as u32 == last {
+                    Self::checked(&env, fee.checked_sub(paid))
+                } else if fee_config.fee_bps == 0 {
+                    0
+                } else {
+                    Self::checked(&env, fee.checked_mul(share.bps as i128)) / fee_config.fee_bps as i128
+                };
+
+                remaining = Self::take_leg(&env, &mut legs, FEE_LEG, share.recipient, amount, remaining);
+                paid = Self::checked(&env, paid.checked_add(amount));
+            }
+        }
+
+        let referral_bps = Self::referral_bps(env.clone());
+        if let Some(referrer) = Self::get_referrer(env.clone(), token_id) {
+            let amount = Self::bps_of(&env, price, referral_bps);
+            remaining = Self::take_leg(&env, &mut legs, REFERRAL_LEG, referrer, amount, remaining);
+        }
+
+        legs.push_back(PaymentLeg {
+            kind: SELLER_LEG,
+            recipient: seller,
+            amount: remaining,
+        });
+
+        legs
+    }
+
+    fn bounty_for(env: Env, price: i128, settles_at: u64) -> i128 {
+        let config = match Self::get_keeper_bounty(env.clone()) {
+            Some(config) => config,
+            None => return 0,
+        };
+
+        let now = env.ledger().timestamp();
+        let delay = if now > settles_at { now - settles_at } else { 0 };
+        let bps = if config.ramp == 0 || delay >= config.ramp {
+            config.max_bps
+        } else {
+            (config.max_bps as u64 * delay / config.ramp) as u32
+        };
+
+        Self::bps_of(&env, price, bps).min(config.cap)
+    }
+
+    fn take_leg(
+        env: &Env,
+        legs: &mut Vec<PaymentLeg>,
+        kind: Symbol,
+        recipient: Address,
+        amount: i128,
+        remaining: i128,
+    ) -> i128 {
+        if amount == 0 {
+            return remaining;
+        }
+
+        let remaining = Self::checked(env, remaining.checked_sub(amount));
+        if remaining < 0 {
+            panic!("Settlement legs exceed the sale price")
+        }
+
+        legs.push_back(PaymentLeg { kind, recipient, amount });
+
+        remaining
+    }
+
+    fn check_increment_steps(steps: &Vec<IncrementStep>) {
+        let mut previous: Option<IncrementStep> = None;
+        for step in steps.iter() {
+            if step.increment_bps > MAX_BPS {
+                panic!("Increment can not exceed 100%")
+            }
+            if let Some(previous) = previous {
+                if step.min_price <= previous.min_price {
+                    panic!("Increment steps must be sorted by price")
+                }
+            }
+            previous = Some(step);
+        }
+    }
+
+    fn bps_of(env: &Env, amount: i128, bps: u32) -> i128 {
+        Self::checked(env, amount.checked_mul(bps as i128)) / MAX_BPS as i128
+    }
+
+    fn checked<T>(env: &Env, value: Option<T>) -> T {
+        match value {
+            Some(value) => value,
+            None => panic_with_error!(env, AuctionError::Overflow),
+        }
+    }
+
+    fn settlement_fee(env: Env, collection: Address, seller: Address, price: i128) -> i128 {
+        let fee_bps = Self::effective_fee(env.clone(), collection);
+        let fee = Self::bps_of(&env, price, fee_bps);
+        let rebate = Self::bps_of(&env, fee, Self::fee_tier(env.clone(), seller));
+
+        fee - rebate
+    }
+
+    fn remove_auction(env: Env, token_id: u128, expiration_date: u64) {
+        env.storage().instance().remove(&token_id);
+        env.storage().instance().remove(&DataKey::AuctionToken(token_id));
+        env.storage().instance().remove(&DataKey::Referrer(token_id));
+        env.storage().instance().remove(&DataKey::Lot(token_id));
+        env.storage().instance().remove(&DataKey::ReserveCommitment(token_id));
+        env.storage().instance().remove(&DataKey::ReserveRevealed(token_id));
+        env.storage().instance().remove(&DataKey::AuctionIncrements(token_id));
+        Self::unlock_listing(env.clone(), token_id);
+        Self::report_offer(env.clone(), token_id, None);
+        env.storage().instance().remove(&DataKey::AuctionCollection(token_id));
+        Self::remove_from_expiry_bucket(env, token_id, expiration_date);
+    }
+
+    // Feeds the NFT contract's highest offer view with the leading bid once
+    // the auction is registered as a marketplace there
+    fn report_offer(env: Env, token_id: u128, price: Option<i128>) {
+        let collection = Self::get_auction_collection(env.clone(), token_id);
+        let marketplace_client = NFTMarketplaceClient::new(&env, &collection);
+
+        if nft_marketplace::is_registered(&env, &collection, &env.current_contract_address()) {
+            marketplace_client.report_offer(&env.current_contract_address(), &token_id, &price);
+        }
+    }
+
+    // Returns an escrowed bid to its bidder, allowance bids hold no funds
+    fn refund_bid(env: Env, bid: &HighestBidder, xlm_address: Address) {
+        if bid.escrowed {
+            let client = token::Client::new(&env, &xlm_address);
+            client.transfer(&env.current_contract_address(), &bid.user, &bid.price);
+        }
+    }
+
+    // Losers' escrows are returned when the auction closes, a batch at a time
+    fn queue_refund(env: Env, token_id: u128, bid: &HighestBidder, xlm_address: Address) {
+        if !bid.escrowed {
+            return;
+        }
+
+        let key = DataKey::PendingRefunds(token_id);
+        let mut refunds: Vec<PendingRefund> = env.storage().persistent().get(&key).unwrap_or(Vec::new(&env));
+
+        refunds.push_back(PendingRefund {
+            user: bid.user.clone(),
+            amount: bid.price,
+            payment_token: xlm_address,
+        });
+        env.storage().persistent().set(&key, &refunds);
+    }
+
+    // Ends an auction whose highest bid fell short of the revealed reserve
+    fn close_below_reserve(env: Env, auction_nft: &AuctionNFT, owner: Address, xlm_address: Address) {
+        let token_id = auction_nft.token_id;
+
+        Self::remove_auction(env.clone(), token_id, auction_nft.expiration_date);
+        Self::close_bid_fees(env.clone(), auction_nft, xlm_address.clone(), false);
+
+        if let Some(highest_bidder) = &auction_nft.highest_bidder {
+            Self::refund_bid(env.clone(), highest_bidder, xlm_address.clone());
+        }
+
+        Self::release_bond(env.clone(), token_id, owner.clone());
+        Self::process_refunds(env.clone(), token_id);
+
+        let delist_event = DelistEvent { token_id, owner };
+
+        Self::publish_event(env.clone(), (DLEVENT, symbol_short!("reserve")), delist_event)
+    }
+
+    // Returns up to REFUND_BATCH_SIZE queued escrows and the number left
+    fn process_refunds(env: Env, token_id: u128) -> u32 {
+        let key = DataKey::PendingRefunds(token_id);
+        let mut refunds: Vec<PendingRefund> = env.storage().persistent().get(&key).unwrap_or(Vec::new(&env));
+        let mut paid = 0;
+
+        while paid < REFUND_BATCH_SIZE {
+            let refund = match refunds.pop_front() {
+                Some(refund) => refund,
+                None => break,
+            };
+
+            let client = token::Client::new(&env, &refund.payment_token);
+            client.transfer(&env.current_contract_address(), &refund.user, &refund.amount);
+            paid += 1;
+
+            let refund_event = RefundEvent {
+                token_id,
+                user: refund.user,
+                amount: refund.amount,
+                remaining: refunds.len(),
+            };
+            Self::publish_event(env.clone(), (BIDEVENT, symbol_short!("refunded")), refund_event);
+        }
+
+        if refunds.is_empty() {
+            env.storage().persistent().remove(&key);
+        } else {
+            env.storage().persistent().set(&key, &refunds);
+        }
+
+        refunds.len()
+    }
+
+    fn can_pay(env: Env, user: Address, amount: i128, xlm_address: Address) -> bool {
+        let client = token::Client::new(&env, &xlm_address);
+
+        client.balance(&user) >= amount && client.allowance(&user, &env.current_contract_address()) >= amount
+    }
+
+    // Winning bid of an expired auction, with its funds held by the contract.
+    // An allowance bid is re-validated and pulled from the bidder here; if the
+    // bidder can no longer pay, the next highest bid from another bidder is
+    // used instead: an allowance bid the bidder can still pay, or an outbid
+    // escrow still waiting in the refund queue. None when no bid is left.
+    fn collect_winning_bid(env: Env, auction_nft: &AuctionNFT, xlm_address: Address) -> Option<HighestBidder> {
+        let highest_bid = match auction_nft.highest_bidder.clone() {
+            Some(bid) => bid,
+            None => panic!("Auction has no bids"),
+        };
+
+        if highest_bid.escrowed {
+            return Some(highest_bid);
+        }
+
+        let client = token::Client::new(&env, &xlm_address);
+        let mut failed: Vec<Address> = Vec::new(&env);
+
+        // Bidders are stored newest first, so in descending price order
+        for bidder in auction_nft.bidders.iter() {
+            if failed.contains(&bidder.user)
+                || Self::is_bidder_blocked(env.clone(), auction_nft.token_id, bidder.user.clone())
+            {
+                continue;
+            }
+
+            let collected = if bidder.escrowed {
+                Self::take_queued_refund(env.clone(), auction_nft.token_id, &bidder, &xlm_address)
+            } else if Self::can_pay(env.clone(), bidder.user.clone(), bidder.price, xlm_address.clone()) {
+                client.transfer_from(
+                    &env.current_contract_address(),
+                    &bidder.user,
+                    &env.current_contract_address(),
+                    &bidder.price,
+                );
+                true
+            } else {
+                failed.push_back(bidder.user.clone());
+                false
+            };
+
+            if collected {
+                return Some(HighestBidder {
+                    user: bidder.user,
+                    price: bidder.price,
+                    escrowed: true,
+                    agent: bidder.agent,
+                });
+            }
+        }
+
+        None
+    }
+
+    // Takes an outbid escrow back out of the refund queue so it can win the
+    // auction, false when it was already refunded
+    fn take_queued_refund(env: Env, token_id: u128, bidder: &Bidder, xlm_address: &Address) -> bool {
+        let key = DataKey::PendingRefunds(token_id);
+        let mut refunds: Vec<PendingRefund> = env.storage().persistent().get(&key).unwrap_or(Vec::new(&env));
+
+        let index = refunds.iter().position(|refund| {
+            refund.user == bidder.user && refund.amount == bidder.price && refund.payment_token == *xlm_address
+        });
+
+        let index = match index {
+            Some(index) => index as u32,
+            None => return false,
+        };
+
+        refunds.remove(index);
+
+        if refunds.is_empty() {
+            env.storage().persistent().remove(&key);
+        } else {
+            env.storage().persistent().set(&key, &refunds);
+        }
+
+        true
+    }
+
+    fn charge_bid_fee(env: Env, user: Address, token_id: u128, xlm_address: Address) {
+        let config = match Self::get_bid_fee(env.clone()) {
+            Some(config) => config,
+            None => return,
+        };
+
+        if config.max_bids_per_ledger > 0 {
+            let sequence = env.ledger().sequence();
+            let key = DataKey::LedgerBids(user.clone());
+            let mut bids: LedgerBids = env.storage().temporary().get(&key).unwrap_or(LedgerBids { sequence, count: 0 });
+
+            // Counts restart with every ledger
+            if bids.sequence != sequence {
+                bids = LedgerBids { sequence, count: 0 };
+            }
+            if bids.count >= config.max_bids_per_ledger {
+                panic!("Bid limit per ledger reached")
+            }
+
+            bids.count += 1;
+            env.storage().temporary().set(&key, &bids);
+        }
+
+        if config.fee == 0 {
+            return;
+        }
+
+        let client = token::Client::new(&env, &xlm_address);
+        client.transfer(&user, &env.current_contract_address(), &config.fee);
+
+        let total: i128 = env.storage().instance().get(&DataKey::AuctionBidFees(token_id)).unwrap_or(0);
+        let paid: i128 = env.storage().instance().get(&DataKey::BidderFees(token_id, user.clone())).unwrap_or(0);
+
+        let total = Self::checked(&env, total.checked_add(config.fee));
+        let paid = Self::checked(&env, paid.checked_add(config.fee));
+
+        env.storage().instance().set(&DataKey::AuctionBidFees(token_id), &total);
+        env.storage().instance().set(&DataKey::BidderFees(token_id, user), &paid);
+    }
+
+    // Moves the bid fees of a closed auction to the fee pool, refunding the
+    // winner's own fees on settlement if configured
+    fn close_bid_fees(env: Env, auction_nft: &AuctionNFT, xlm_address: Address, settled: bool) {
+        let token_id = auction_nft.token_id;
+        let mut total: i128 = env.storage().instance().get(&DataKey::AuctionBidFees(token_id)).unwrap_or(0);
+
+        if total == 0 {
+            return;
+        }
+
+        let refund_winner = Self::get_bid_fee(env.clone()).map_or(false, |config| config.refund_winner);
+
+        // Fees are recorded under whoever paid them, the agent of an agent bid
+        let winner = match auction_nft.highest_bidder.clone() {
+            Some(highest_bid) if settled && refund_winner => Some(highest_bid.agent.unwrap_or(highest_bid.user)),
+            _ => None,
+        };
+
+        if let Some(winner) = winner {
+            let refund: i128 = env
+                .storage()
+                .instance()
+                .get(&DataKey::BidderFees(token_id, winner.clone()))
+                .unwrap_or(0);
+
+            if refund > 0 {
+                let client = token::Client::new(&env, &xlm_address);
+                client.transfer(&env.current_contract_address(), &winner, &refund);
+                total -= refund;
+            }
+        }
+
+        for bidder in auction_nft.bidders.iter() {
+            let fee_payer = bidder.agent.unwrap_or(bidder.user);
+            env.storage().instance().remove(&DataKey::BidderFees(token_id, fee_payer));
+        }
+        env.storage().instance().remove(&DataKey::AuctionBidFees(token_id));
+
+        let pool = Self::fee_pool(env.clone(), xlm_address.clone());
+        let pool = Self::checked(&env, pool.checked_add(total));
+        env.storage().persistent().set(&DataKey::FeePool(xlm_address), &pool);
+    }
+
+    fn lock_bond(env: Env, seller: Address, token_id: u128) {
+        let amount = Self::get_bond_amount(env.clone());
+
+        if amount == 0 {
+            return;
+        }
+
+        let payment_token = Self::get_payment_token(env.clone()).expect("Payment token not configured");
+        let client = token::Client::new(&env, &payment_token);
+        client.transfer(&seller, &env.current_contract_address(), &amount);
+
+        let bond = SellerBond {
+            seller,
+            payment_token,
+            amount,
+        };
+        env.storage().persistent().set(&DataKey::Bond(token_id), &bond);
+    }
+
+    fn release_bond(env: Env, token_id: u128, to: Address) {
+        let bond = match Self::get_bond(env.clone(), token_id) {
+            Some(bond) => bond,
+            None => return,
+        };
+
+        env.storage().persistent().remove(&DataKey::Bond(token_id));
+
+        let client = token::Client::new(&env, &bond.payment_token);
+        client.transfer(&env.current_contract_address(), &to, &bond.amount);
+
+        let bond_event = BondEvent {
             token_id,
-            owner: from,
+            to,
+            amount: bond.amount,
         };
+        Self::publish_event(env.clone(), (BONDEVENT, symbol_short!("released")), bond_event);
+    }
+
+    fn check_payment_token(env: Env, token_id: u128, xlm_address: Address) {
+        if let Some(payment_token) = Self::get_auction_token(env, token_id) {
+            if payment_token != xlm_address {
+                panic!("Invalid payment token")
+            }
+        }
+    }
+
+    fn add_to_expiry_bucket(env: Env, token_id: u128, expiration_date: u64) {
+        let key = DataKey::ExpiryBucket(expiration_date / DAY_IN_SECONDS);
+        let mut bucket: Vec<u128> = env.storage().instance().get(&key).unwrap_or(Vec::new(&env));
+
+        bucket.push_back(token_id);
+        env.storage().instance().set(&key, &bucket);
+    }
+
+    fn remove_from_expiry_bucket(env: Env, token_id: u128, expiration_date: u64) {
+        let key = DataKey::ExpiryBucket(expiration_date / DAY_IN_SECONDS);
+        let mut bucket: Vec<u128> = env.storage().instance().get(&key).unwrap_or(Vec::new(&env));
+
+        if let Some(index) = bucket.first_index_of(token_id) {
+            bucket.remove(index);
+        }
+
+        if bucket.is_empty() {
+            env.storage().instance().remove(&key);
+        } else {
+            env.storage().instance().set(&key, &bucket);
+        }
+    }
+
+    fn installment_amount(payout: &InstallmentPayout, tranche: u32) -> i128 {
+        let share = payout.total / payout.installments as i128;
+
+        // The final tranche absorbs the rounding remainder
+        if tranche == payout.installments - 1 {
+            payout.total - share * (payout.installments as i128 - 1)
+        } else {
+            share
+        }
+    }
+
+    pub fn set_listing_registry(env: Env, admin: Address, registry: Address) {
+        Self::check_admin(env.clone(), admin);
 
-        env.events().publish((DLEVENT, symbol_short!("delisted")), delist_event)
+        env.storage().instance().set(&DataKey::ListingRegistry, &registry);
+    }
+
+    fn lock_listing(env: Env, token_id: u128) {
+        if let Some(registry) = env.storage().instance().get::<DataKey, Address>(&DataKey::ListingRegistry) {
+            let registry_client = ListingRegistryClient::new(&env, &registry);
+            let collection = Self::get_auction_collection(env.clone(), token_id);
+            registry_client.lock(&env.current_contract_address(), &collection, &token_id);
+        }
+    }
+
+    fn unlock_listing(env: Env, token_id: u128) {
+        if let Some(registry) = env.storage().instance().get::<DataKey, Address>(&DataKey::ListingRegistry) {
+            let registry_client = ListingRegistryClient::new(&env, &registry);
+            let collection = Self::get_auction_collection(env.clone(), token_id);
+            registry_client.unlock(&env.current_contract_address(), &collection, &token_id);
+        }
+    }
+
+    // Auction events publish (event_nonce, payload) as their data, the nonce
+    // counting every event of the contract so indexers can spot gaps
+    fn publish_event<T: Topics, D: IntoVal<Env, Val>>(env: Env, topics: T, data: D) {
+        let event_nonce = Self::event_nonce(env.clone()) + 1;
+
+        env.storage().instance().set(&DataKey::EventNonce, &event_nonce);
+        env.events().publish(topics, (event_nonce, data));
+    }
+
+    // Nonce of the last published event
+    pub fn event_nonce(env: Env) -> u64 {
+        env.storage().instance().get(&DataKey::EventNonce).unwrap_or(0)
+    }
+
+    fn check_admin(env: Env, admin: Address) {
+        admin.require_auth();
+
+        if admin != Self::read_administrator(env) {
+            panic!("Only admin can perform this action")
+        }
     }
 
     fn read_administrator(env: Env) -> Address {
@@ -291,6 +2462,18 @@ impl NFTAuctionStorefront {
         env.storage().instance().has(&key)
     }
 
+    // Hands a sold token to the buyer, as a marketplace sale when the
+    // collection registered this contract so its cooldown exemption applies
+    fn transfer_sold_nft(env: Env, nft_client: &nft_contract::Client, from: &Address, to: &Address, token_id: u128) {
+        let marketplace_client = NFTMarketplaceClient::new(&env, &nft_client.address);
+
+        if nft_marketplace::is_registered(&env, &nft_client.address, &env.current_contract_address()) {
+            marketplace_client.marketplace_transfer(&env.current_contract_address(), from, to, &token_id);
+        } else {
+            nft_client.transfer_from(from, to, &token_id);
+        }
+    }
+
     fn get_nft_client(env: Env) -> nft_contract::Client<'static> {
         let contract = env
             .storage()
@@ -302,9 +2485,30 @@ impl NFTAuctionStorefront {
 
         return client;
     }
+
+    fn get_auction_nft_client(env: Env, token_id: u128) -> nft_contract::Client<'static> {
+        let collection = Self::get_auction_collection(env.clone(), token_id);
+
+        nft_contract::Client::new(&env, &collection)
+    }
 }
 
 #[cfg(test)]
 mod test;
 
+#[cfg(test)]
+mod nft_mock;
+
+#[cfg(test)]
+mod marketplace_testutils;
+
+#[cfg(test)]
+mod auction_lifecycle;
+
+#[cfg(test)]
+mod auction_budget;
+
+#[cfg(test)]
+mod auction_overflow;
+
 mod testutils;
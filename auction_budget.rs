@@ -0,0 +1,129 @@
+use soroban_sdk::{
+    testutils::{Address as _, Ledger},
+    token, Address, Env,
+};
+
+use crate::nft_mock::{MockNft, MockNftClient};
+use crate::{NFTAuctionStorefront, NFTAuctionStorefrontClient, REFUND_BATCH_SIZE};
+
+// Host budget one call may use. A change that needs more should raise the
+// ceiling in the same commit rather than slip past unnoticed.
+const BID_CPU_LIMIT: u64 = 10_000_000;
+const BID_MEMORY_LIMIT: u64 = 1_500_000;
+const SETTLE_CPU_LIMIT: u64 = 20_000_000;
+const SETTLE_MEMORY_LIMIT: u64 = 3_000_000;
+
+const EXPIRATION: u64 = 1_000;
+
+struct Setup<'a> {
+    env: Env,
+    auction: NFTAuctionStorefrontClient<'a>,
+    nft: MockNftClient<'a>,
+    token: Address,
+    seller: Address,
+    token_id: u128,
+}
+
+fn setup<'a>() -> Setup<'a> {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let seller = Address::generate(&env);
+
+    let nft = MockNftClient::new(&env, &env.register_contract(None, MockNft));
+    nft.initialize(&admin);
+    let token_id = nft.mint(&seller);
+
+    let token = env.register_stellar_asset_contract(admin.clone());
+
+    let auction = NFTAuctionStorefrontClient::new(&env, &env.register_contract(None, NFTAuctionStorefront));
+    auction.initialize(&nft.address, &admin);
+    auction.auction_nft(&seller, &token_id, &100, &EXPIRATION);
+
+    Setup {
+        env,
+        auction,
+        nft,
+        token,
+        seller,
+        token_id,
+    }
+}
+
+fn funded_bidder(setup: &Setup, amount: i128) -> Address {
+    let bidder = Address::generate(&setup.env);
+    token::StellarAssetClient::new(&setup.env, &setup.token).mint(&bidder, &amount);
+
+    bidder
+}
+
+fn assert_within_budget(env: &Env, cpu_limit: u64, memory_limit: u64) {
+    let cpu = env.budget().cpu_instruction_cost();
+    let memory = env.budget().memory_bytes_cost();
+
+    assert!(cpu <= cpu_limit, "cpu cost {} exceeds {}", cpu, cpu_limit);
+    assert!(memory <= memory_limit, "memory cost {} exceeds {}", memory, memory_limit);
+}
+
+#[test]
+fn first_bid_stays_within_budget() {
+    let setup = setup();
+    let bidder = funded_bidder(&setup, 1_000);
+
+    setup.env.budget().reset_default();
+    setup.auction.bid_nft(&bidder, &setup.token_id, &200, &setup.token);
+
+    assert_within_budget(&setup.env, BID_CPU_LIMIT, BID_MEMORY_LIMIT);
+}
+
+#[test]
+fn outbidding_stays_within_budget() {
+    let setup = setup();
+    let first = funded_bidder(&setup, 1_000);
+    let second = funded_bidder(&setup, 1_000);
+    setup.auction.bid_nft(&first, &setup.token_id, &200, &setup.token);
+
+    setup.env.budget().reset_default();
+    setup.auction.bid_nft(&second, &setup.token_id, &300, &setup.token);
+
+    assert_within_budget(&setup.env, BID_CPU_LIMIT, BID_MEMORY_LIMIT);
+}
+
+#[test]
+fn settlement_stays_within_budget() {
+    let setup = setup();
+    let bidder = funded_bidder(&setup, 1_000);
+    setup.auction.bid_nft(&bidder, &setup.token_id, &200, &setup.token);
+    setup.env.ledger().with_mut(|ledger| ledger.timestamp = EXPIRATION + 1);
+
+    setup.env.budget().reset_default();
+    setup.auction.sell_auctioned_nft(&setup.seller, &setup.token_id, &setup.token);
+
+    assert_within_budget(&setup.env, SETTLE_CPU_LIMIT, SETTLE_MEMORY_LIMIT);
+    assert_eq!(setup.nft.owner_of(&setup.token_id), bidder);
+}
+
+// Settlement refunds a full batch of outbid escrows, the worst case it pays for
+#[test]
+fn settlement_with_full_refund_batch_stays_within_budget() {
+    let setup = setup();
+    let mut price = 100;
+    let mut winner = None;
+
+    for _ in 0..REFUND_BATCH_SIZE + 1 {
+        price += 100;
+        let bidder = funded_bidder(&setup, price);
+        setup.auction.bid_nft(&bidder, &setup.token_id, &price, &setup.token);
+        winner = Some(bidder);
+    }
+    setup.env.ledger().with_mut(|ledger| ledger.timestamp = EXPIRATION + 1);
+
+    setup.env.budget().reset_default();
+    setup.auction.sell_auctioned_nft(&setup.seller, &setup.token_id, &setup.token);
+
+    assert_within_budget(&setup.env, SETTLE_CPU_LIMIT, SETTLE_MEMORY_LIMIT);
+    assert_eq!(setup.auction.pending_refunds(&setup.token_id), 0);
+    assert_eq!(setup.nft.owner_of(&setup.token_id), winner.unwrap());
+}
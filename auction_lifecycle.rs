@@ -0,0 +1,102 @@
+use soroban_sdk::testutils::Ledger;
+
+use crate::marketplace_testutils::Marketplace;
+use crate::{NFTAuctionStorefront, NFTAuctionStorefrontClient};
+
+const RESERVE: i128 = 100;
+const EXPIRATION: u64 = 1_000;
+const ROYALTY_BPS: u32 = 500;
+const ALLOWANCE_LEDGER: u32 = 1_000;
+
+fn auction<'a>(marketplace: &Marketplace) -> NFTAuctionStorefrontClient<'a> {
+    let env = &marketplace.env;
+    let auction = NFTAuctionStorefrontClient::new(env, &env.register_contract(None, NFTAuctionStorefront));
+    auction.initialize(&marketplace.nft.address, &marketplace.admin);
+    auction.set_royalty(&marketplace.admin, &marketplace.creator, &ROYALTY_BPS);
+
+    auction
+}
+
+fn expire(marketplace: &Marketplace) {
+    marketplace.env.ledger().with_mut(|ledger| ledger.timestamp = EXPIRATION + 1);
+}
+
+// Mint, auction, outbid and settle: the winner gets the token, the loser
+// their escrow back, and the royalty comes out of the winning bid
+#[test]
+fn auction_sale_refunds_losers_and_pays_creator() {
+    let marketplace = Marketplace::setup();
+    let auction = auction(&marketplace);
+    let seller = marketplace.account(0);
+    let loser = marketplace.account(300);
+    let winner = marketplace.account(400);
+    let token_id = marketplace.mint(&seller);
+
+    auction.auction_nft(&seller, &token_id, &RESERVE, &EXPIRATION);
+    auction.bid_nft(&loser, &token_id, &300, &marketplace.token.address);
+    auction.bid_nft(&winner, &token_id, &400, &marketplace.token.address);
+    expire(&marketplace);
+
+    auction.sell_auctioned_nft(&seller, &token_id, &marketplace.token.address);
+
+    assert_eq!(marketplace.owner_of(token_id), winner);
+    assert_eq!(auction.pending_refunds(&token_id), 0);
+    assert_eq!(marketplace.balance(&loser), 300);
+    assert_eq!(marketplace.balance(&winner), 0);
+    assert_eq!(marketplace.balance(&seller), 380);
+    assert_eq!(marketplace.balance(&marketplace.creator), 20);
+    assert_eq!(marketplace.balance(&auction.address), 0);
+}
+
+// The highest bid was an allowance that is revoked before settlement, so
+// the escrowed runner-up wins at their own price
+#[test]
+fn defaulted_allowance_bid_falls_back_to_escrow() {
+    let marketplace = Marketplace::setup();
+    let auction = auction(&marketplace);
+    let seller = marketplace.account(0);
+    let escrowed = marketplace.account(300);
+    let defaulter = marketplace.account(400);
+    let token_id = marketplace.mint(&seller);
+
+    auction.auction_nft(&seller, &token_id, &RESERVE, &EXPIRATION);
+    auction.bid_nft(&escrowed, &token_id, &300, &marketplace.token.address);
+
+    marketplace.token.approve(&defaulter, &auction.address, &400, &ALLOWANCE_LEDGER);
+    auction.bid_nft_with_allowance(&defaulter, &token_id, &400, &marketplace.token.address);
+    marketplace.token.approve(&defaulter, &auction.address, &0, &0);
+    expire(&marketplace);
+
+    auction.sell_auctioned_nft(&seller, &token_id, &marketplace.token.address);
+
+    assert_eq!(marketplace.owner_of(token_id), escrowed);
+    assert_eq!(marketplace.balance(&escrowed), 0);
+    assert_eq!(marketplace.balance(&defaulter), 400);
+    assert_eq!(marketplace.balance(&seller), 285);
+    assert_eq!(marketplace.balance(&marketplace.creator), 15);
+}
+
+// With every bidder defaulted the auction closes unsold and the seller
+// keeps the token
+#[test]
+fn auction_closes_unsold_when_every_bidder_defaults() {
+    let marketplace = Marketplace::setup();
+    let auction = auction(&marketplace);
+    let seller = marketplace.account(0);
+    let defaulter = marketplace.account(400);
+    let token_id = marketplace.mint(&seller);
+
+    auction.auction_nft(&seller, &token_id, &RESERVE, &EXPIRATION);
+
+    marketplace.token.approve(&defaulter, &auction.address, &400, &ALLOWANCE_LEDGER);
+    auction.bid_nft_with_allowance(&defaulter, &token_id, &400, &marketplace.token.address);
+    marketplace.token.approve(&defaulter, &auction.address, &0, &0);
+    expire(&marketplace);
+
+    auction.sell_auctioned_nft(&seller, &token_id, &marketplace.token.address);
+
+    assert_eq!(marketplace.owner_of(token_id), seller);
+    assert_eq!(auction.get_auctioned_nft(&token_id).token_id, 0);
+    assert_eq!(marketplace.balance(&defaulter), 400);
+    assert_eq!(marketplace.balance(&seller), 0);
+}
@@ -0,0 +1,197 @@
+use soroban_sdk::{
+    testutils::{Address as _, Ledger},
+    token, vec, Address, Env,
+};
+
+use crate::nft_mock::{MockNft, MockNftClient};
+use crate::{AuctionError, IncrementStep, NFTAuctionStorefront, NFTAuctionStorefrontClient, MAX_BPS};
+
+const EXPIRATION: u64 = 1_000;
+
+// Bids every overflow path has to survive: the i128 bounds, values just
+// under amounts whose bps products overflow, and the usual rejects
+const EXTREME_BIDS: [i128; 10] = [
+    i128::MIN,
+    -1,
+    0,
+    1,
+    i128::MAX / MAX_BPS as i128,
+    i128::MAX / MAX_BPS as i128 + 1,
+    i128::MAX / 2,
+    i128::MAX / 2 + 1,
+    i128::MAX - 1,
+    i128::MAX,
+];
+
+const RANDOM_BIDS: u32 = 200;
+
+struct Setup<'a> {
+    env: Env,
+    admin: Address,
+    auction: NFTAuctionStorefrontClient<'a>,
+    token: token::Client<'a>,
+    seller: Address,
+    token_id: u128,
+}
+
+fn setup<'a>() -> Setup<'a> {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let seller = Address::generate(&env);
+
+    let nft = MockNftClient::new(&env, &env.register_contract(None, MockNft));
+    nft.initialize(&admin);
+    let token_id = nft.mint(&seller);
+
+    let token = token::Client::new(&env, &env.register_stellar_asset_contract(admin.clone()));
+
+    let auction = NFTAuctionStorefrontClient::new(&env, &env.register_contract(None, NFTAuctionStorefront));
+    auction.initialize(&nft.address, &admin);
+    auction.auction_nft(&seller, &token_id, &1, &EXPIRATION);
+
+    Setup {
+        env,
+        admin,
+        auction,
+        token,
+        seller,
+        token_id,
+    }
+}
+
+fn bidder(setup: &Setup, funds: i128) -> Address {
+    let bidder = Address::generate(&setup.env);
+
+    if funds > 0 {
+        token::StellarAssetClient::new(&setup.env, &setup.token.address).mint(&bidder, &funds);
+    }
+
+    bidder
+}
+
+fn overflow() -> soroban_sdk::Error {
+    AuctionError::Overflow.into()
+}
+
+// xorshift64, so the sweep is the same on every run
+fn next_random(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+// Full range i128, shifted down half of the time so small and mid-sized
+// bids are covered as well as huge and negative ones
+fn random_bid(state: &mut u64) -> i128 {
+    let value = ((next_random(state) as i128) << 64) | next_random(state) as i128;
+    let shift = next_random(state) % 256;
+
+    if shift < 128 {
+        value >> shift
+    } else {
+        value
+    }
+}
+
+// Whatever the bid, it either fails or leaves the contract holding exactly
+// the escrow of every accepted bid, and only ever raises the highest bid
+fn assert_bids_never_wrap(setup: &Setup, bids: &[i128]) {
+    let mut escrowed: i128 = 0;
+    let mut highest: i128 = 0;
+
+    for &bid in bids {
+        let user = bidder(setup, bid);
+        let result = setup.auction.try_bid_nft(&user, &setup.token_id, &bid, &setup.token.address);
+
+        if result.is_ok() {
+            assert!(bid > highest, "accepted bid {} below highest {}", bid, highest);
+
+            escrowed = escrowed.checked_add(bid).expect("accepted bid overflowed the escrow");
+            highest = bid;
+        } else if bid > 0 {
+            assert_eq!(setup.token.balance(&user), bid);
+        }
+
+        assert_eq!(setup.token.balance(&setup.auction.address), escrowed);
+    }
+
+    let auction_nft = setup.auction.get_auctioned_nft(&setup.token_id);
+    assert_eq!(auction_nft.highest_bidder.map_or(0, |bid| bid.price), highest);
+}
+
+#[test]
+fn extreme_bids_never_wrap_escrow() {
+    let setup = setup();
+
+    assert_bids_never_wrap(&setup, &EXTREME_BIDS);
+}
+
+#[test]
+fn random_bids_never_wrap_escrow() {
+    let mut state = 0x9e37_79b9_7f4a_7c15;
+    let mut bids = [0i128; RANDOM_BIDS as usize];
+
+    for bid in bids.iter_mut() {
+        *bid = random_bid(&mut state);
+    }
+    bids.sort();
+
+    let setup = setup();
+    assert_bids_never_wrap(&setup, &bids);
+}
+
+#[test]
+fn min_next_bid_overflows_after_maximum_bid() {
+    let setup = setup();
+    let user = bidder(&setup, i128::MAX);
+
+    setup.auction.bid_nft(&user, &setup.token_id, &i128::MAX, &setup.token.address);
+
+    assert_eq!(setup.auction.try_min_next_bid(&setup.token_id), Err(Ok(overflow())));
+}
+
+#[test]
+fn increment_overflow_rejects_bid_with_typed_error() {
+    let setup = setup();
+    let steps = vec![
+        &setup.env,
+        IncrementStep {
+            min_price: 0,
+            increment_bps: 500,
+        },
+    ];
+    setup.auction.set_increment_schedule(&setup.admin, &steps);
+
+    let first = bidder(&setup, i128::MAX / 2);
+    let second = bidder(&setup, i128::MAX);
+    setup.auction.bid_nft(&first, &setup.token_id, &(i128::MAX / 2), &setup.token.address);
+
+    let result = setup.auction.try_bid_nft(&second, &setup.token_id, &i128::MAX, &setup.token.address);
+
+    assert_eq!(result, Err(Ok(overflow())));
+    assert_eq!(setup.token.balance(&second), i128::MAX);
+    assert_eq!(setup.token.balance(&setup.auction.address), i128::MAX / 2);
+}
+
+#[test]
+fn royalty_overflow_fails_settlement_with_typed_error() {
+    let setup = setup();
+    let creator = Address::generate(&setup.env);
+    setup.auction.set_royalty(&setup.admin, &creator, &500);
+
+    let user = bidder(&setup, i128::MAX);
+    setup.auction.bid_nft(&user, &setup.token_id, &i128::MAX, &setup.token.address);
+    setup.env.ledger().with_mut(|ledger| ledger.timestamp = EXPIRATION + 1);
+
+    let result = setup
+        .auction
+        .try_sell_auctioned_nft(&setup.seller, &setup.token_id, &setup.token.address);
+
+    assert_eq!(result, Err(Ok(overflow())));
+    assert_eq!(setup.token.balance(&creator), 0);
+    assert_eq!(setup.token.balance(&setup.auction.address), i128::MAX);
+}
@@ -0,0 +1,65 @@
+use soroban_sdk::{testutils::Address as _, token, Address, Env};
+
+use crate::nft_mock::{MockNft, MockNftClient};
+
+// Environment shared by the storefront and auction lifecycle tests: a
+// collection, a Stellar asset as payment token and a royalty recipient,
+// with every auth mocked. Each test target adds its own marketplace.
+pub struct Marketplace<'a> {
+    pub env: Env,
+    pub admin: Address,
+    pub creator: Address,
+    pub token: token::Client<'a>,
+    pub token_admin: token::StellarAssetClient<'a>,
+    pub nft: MockNftClient<'a>,
+}
+
+impl<'a> Marketplace<'a> {
+    pub fn setup() -> Self {
+        let env = Env::default();
+        env.mock_all_auths();
+        env.budget().reset_unlimited();
+
+        let admin = Address::generate(&env);
+        let creator = Address::generate(&env);
+
+        let token_address = env.register_stellar_asset_contract(admin.clone());
+        let token = token::Client::new(&env, &token_address);
+        let token_admin = token::StellarAssetClient::new(&env, &token_address);
+
+        let nft = MockNftClient::new(&env, &env.register_contract(None, MockNft));
+        nft.initialize(&admin);
+
+        Marketplace {
+            env,
+            admin,
+            creator,
+            token,
+            token_admin,
+            nft,
+        }
+    }
+
+    // New account holding balance of the payment token
+    pub fn account(&self, balance: i128) -> Address {
+        let account = Address::generate(&self.env);
+
+        if balance > 0 {
+            self.token_admin.mint(&account, &balance);
+        }
+
+        account
+    }
+
+    pub fn mint(&self, owner: &Address) -> u128 {
+        self.nft.mint(owner)
+    }
+
+    pub fn owner_of(&self, token_id: u128) -> Address {
+        self.nft.owner_of(&token_id)
+    }
+
+    pub fn balance(&self, account: &Address) -> i128 {
+        self.token.balance(account)
+    }
+}
@@ -1,13 +1,61 @@
 use soroban_sdk::{
-  contract, contractimpl, contracttype, symbol_short, Symbol, Address, Env, String
+  contract, contractclient, contracterror, contractimpl, contracttype, panic_with_error, symbol_short, Symbol,
+  xdr::ToXdr, Address, Bytes, BytesN, Env, String, Vec
 };
 
+mod token_id;
+
+use token_id::TokenId;
+
 const TRANSFER_EVENT: Symbol = symbol_short!("TRANSFER");
+// Legacy storage layout, read only to migrate into the config entry
 const METADATA_KEY: Symbol = symbol_short!("METADATA");
 const MINT_EVENT: Symbol = symbol_short!("MINT");
 const BURN_EVENT: Symbol = symbol_short!("BURN");
 const COUNTER: Symbol = symbol_short!("COUNTER");
 
+const CONFIG_VERSION: u32 = 1;
+const MAX_RANGE_MINT: u128 = 10_000;
+const MAX_URI_LEN: usize = 256;
+const LOCK_EVENT: Symbol = symbol_short!("LOCK");
+const APPROVE_EVENT: Symbol = symbol_short!("APPROVE");
+const FORCE_BURN_EVENT: Symbol = symbol_short!("FORCEBURN");
+const COA_EVENT: Symbol = symbol_short!("COA");
+const BASE_URI_EVENT: Symbol = symbol_short!("BASEURI");
+const DELEGATE_EVENT: Symbol = symbol_short!("DELEGATE");
+const PASS_EVENT: Symbol = symbol_short!("MINTPASS");
+const PAUSE_EVENT: Symbol = symbol_short!("PAUSE");
+const NEST_EVENT: Symbol = symbol_short!("NEST");
+const MAX_CHILDREN: u32 = 16;
+const MAX_URI_SCHEMES: u32 = 8;
+const MAX_SCHEME_LEN: usize = 16;
+const MAX_IMPORT_BATCH: u32 = 100;
+const MAX_QUOTE_SCAN: u32 = 200;
+
+const SUPPORTED_INTERFACES: [Symbol; 9] = [
+  symbol_short!("nft"),
+  symbol_short!("approvals"),
+  symbol_short!("burnable"),
+  symbol_short!("lockable"),
+  symbol_short!("memo"),
+  symbol_short!("metahash"),
+  symbol_short!("delegate"),
+  symbol_short!("royalties"),
+  symbol_short!("nesting"),
+];
+
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum UriError {
+  TooLong = 1,
+  SchemeNotAllowed = 2,
+}
+
+pub const REASON_ILLEGAL_CONTENT: u32 = 1;
+pub const REASON_COPYRIGHT: u32 = 2;
+pub const REASON_POLICY_VIOLATION: u32 = 3;
+
 #[derive(Clone)]
 #[contracttype]
 pub struct NFTMetadata {
@@ -19,6 +67,91 @@ pub struct NFTMetadata {
 #[contracttype]
 pub enum DataKey {
   Admin,
+  Config,
+  Lock(u128),
+  MarketLock(u128),
+  Approval(u128),
+  Creator(u128),
+  CreatorKey(Address),
+  Coa(u128),
+  Ranges,
+  BaseUri,
+  Suffixed(u128),
+  ReceiverHook(Address),
+  Claim(u128),
+  ClaimCommit(u128, BytesN<32>),
+  MetadataHash(u128),
+  DelegateAll(Address, Address),
+  DelegateToken(Address, Address, u128),
+  Cooldown,
+  LastTransfer(u128),
+  Marketplace(Address),
+  PassConfig,
+  Passes(Address),
+  RoyaltiesEarned(Address, Address),
+  TokenRoyalties(u128, Address),
+  Permissioned,
+  Allowlisted(Address),
+  ListingQuote(u128),
+  OfferQuote(u128, Address),
+  OfferMarkets(u128),
+  ImportFinalized,
+  ContentHash(u128),
+  ContentToken(BytesN<32>),
+  DuplicateAllowed(BytesN<32>),
+  MintPaused,
+  TransfersPaused,
+  BurnsPaused,
+  Parent(u128),
+  Children(u128),
+  UriPolicy,
+}
+
+// Price a registered marketplace last reported for a token
+#[derive(Clone)]
+#[contracttype]
+pub struct PriceQuote {
+  pub token_id: u128,
+  pub marketplace: Address,
+  pub price: i128,
+}
+
+
+#[derive(Clone)]
+#[contracttype]
+pub struct NFTConfig {
+  pub version: u32,
+  pub admin: Address,
+  pub name: String,
+  pub symbol: String,
+  pub counter: u128,
+}
+
+// Minimum time between two transfers of the same token, sales through a
+// registered marketplace skip it when marketplace_exempt is set
+#[derive(Clone)]
+#[contracttype]
+pub struct TransferCooldown {
+  pub period: u64,
+  pub marketplace_exempt: bool,
+}
+
+// Window in which mint passes can be redeemed, and whether holders may
+// transfer passes to other accounts
+#[derive(Clone)]
+#[contracttype]
+pub struct MintPassConfig {
+  pub start: u64,
+  pub end: u64,
+  pub transferable: bool,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct PassEvent {
+  pub from: Option<Address>,
+  pub to: Address,
+  pub count: u32
 }
 
 #[derive(Clone)]
@@ -28,6 +161,25 @@ pub struct NFTDetail {
   pub uri: String,
 }
 
+// Tokens start..=end minted in one batch, resolved on read until each
+// token is materialized into its own entry by a transfer or burn
+#[derive(Clone)]
+#[contracttype]
+pub struct MintRange {
+  pub start: u128,
+  pub end: u128,
+  pub owner: Address,
+  pub base_uri: String,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct MintRangeEvent {
+  pub address: Address,
+  pub start: u128,
+  pub end: u128
+}
+
 #[derive(Clone)]
 #[contracttype]
 pub struct MintEvent {
@@ -50,17 +202,246 @@ pub struct TransferEvent {
   pub token_id: u128
 }
 
+#[derive(Clone)]
+#[contracttype]
+pub struct TransferDataEvent {
+  pub from: Address,
+  pub to: Address,
+  pub token_id: u128,
+  pub data: Bytes
+}
+
+// token_id 0 delegates every token the vault holds
+#[derive(Clone)]
+#[contracttype]
+pub struct DelegateEvent {
+  pub vault: Address,
+  pub delegate: Address,
+  pub token_id: u128,
+  pub enabled: bool
+}
+
+// Limits on token and base URIs. An empty scheme list accepts any scheme,
+// otherwise a URI has to start with one of them, e.g. "ipfs://"
+#[derive(Clone)]
+#[contracttype]
+pub struct UriPolicy {
+  pub max_len: u32,
+  pub schemes: Vec<String>
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct NestEvent {
+  pub owner: Address,
+  pub parent: u128,
+  pub child: u128
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct PauseEvent {
+  pub paused: bool
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct LockEvent {
+  pub owner: Address,
+  pub token_id: u128,
+  pub until: u64
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct ApproveEvent {
+  pub owner: Address,
+  pub operator: Address,
+  pub token_id: u128
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct ForceBurnEvent {
+  pub owner: Address,
+  pub token_id: u128,
+  pub reason_code: u32
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct CertificateOfAuthenticity {
+  pub hash: BytesN<32>,
+  pub signature: BytesN<64>,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct CoaEvent {
+  pub creator: Address,
+  pub token_id: u128,
+  pub hash: BytesN<32>
+}
+
+// Implemented by receiver contracts that opted in to get transfer payloads
+#[contractclient(name = "NFTReceiverClient")]
+pub trait NFTReceiver {
+  fn on_nft_received(env: Env, from: Address, token_id: u128, data: Bytes);
+}
+
 pub trait NFTTrait {
   fn initialize(env: Env, admin: Address, name: String, symbol: String);
 
   fn mint_nft(env: Env, to: Address, token_uri: String) -> u128;
 
+  fn mint_range(env: Env, to: Address, count: u128, base_uri: String) -> u128;
+
+  fn mint_with_suffix(env: Env, to: Address, uri_suffix: String) -> u128;
+
+  fn mint_with_metadata_hash(env: Env, to: Address, token_uri: String, metadata_hash: BytesN<32>) -> u128;
+
+  fn mint_with_content_hash(env: Env, to: Address, token_uri: String, content_hash: Option<BytesN<32>>) -> u128;
+
+  fn allow_duplicate_content(env: Env, content_hash: BytesN<32>);
+
+  fn token_by_content_hash(env: Env, content_hash: BytesN<32>) -> Option<u128>;
+
+  fn content_hash(env: Env, token_id: u128) -> Option<BytesN<32>>;
+
+  fn metadata_hash(env: Env, token_id: u128) -> Option<BytesN<32>>;
+
+  fn verify_metadata(env: Env, token_id: u128, hash: BytesN<32>) -> bool;
+
+  fn mint_claimable(env: Env, code_hash: BytesN<32>, token_uri: String) -> u128;
+
+  fn import_tokens(env: Env, tokens: Vec<(u128, Address, String)>);
+
+  fn set_counter(env: Env, value: u128);
+
+  fn finalize_import(env: Env);
+
+  fn import_finalized(env: Env) -> bool;
+
+  fn set_pass_config(env: Env, start: u64, end: u64, transferable: bool);
+
+  fn issue_passes(env: Env, to: Address, count: u32);
+
+  fn transfer_passes(env: Env, from: Address, to: Address, count: u32);
+
+  fn pass_balance(env: Env, account: Address) -> u32;
+
+  fn redeem_pass(env: Env, holder: Address, token_uri: String) -> u128;
+
+  fn commit_claim(env: Env, token_id: u128, commitment: BytesN<32>);
+
+  fn claim_minted(env: Env, to: Address, token_id: u128, code_preimage: Bytes);
+
+  fn is_claimable(env: Env, token_id: u128) -> bool;
+
+  fn set_base_uri(env: Env, base_uri: String);
+
+  fn set_uri_policy(env: Env, max_len: u32, schemes: Vec<String>);
+
+  fn uri_policy(env: Env) -> UriPolicy;
+
+  fn base_uri(env: Env) -> String;
+
+  fn token_uri(env: Env, token_id: u128) -> String;
+
   fn burn_nft(env: Env, to: Address, token_id: u128);
 
+  fn approve(env: Env, owner: Address, operator: Address, token_id: u128);
+
+  fn get_approved(env: Env, token_id: u128) -> Option<Address>;
+
+  fn burn_from(env: Env, operator: Address, token_id: u128);
+
+  fn force_burn(env: Env, token_id: u128, reason_code: u32);
+
   fn transfer_nft(env: Env, from: Address, to: Address, token_id: u128);
 
+  fn transfer_with_data(env: Env, from: Address, to: Address, token_id: u128, data: Bytes);
+
+  fn marketplace_transfer(env: Env, marketplace: Address, from: Address, to: Address, token_id: u128);
+
+  fn set_marketplace(env: Env, marketplace: Address, allowed: bool);
+
+  fn is_marketplace(env: Env, marketplace: Address) -> bool;
+
+  fn record_royalty(env: Env, marketplace: Address, token_id: u128, creator: Address, payment_token: Address, amount: i128);
+
+  fn royalties_earned(env: Env, creator: Address, payment_token: Address) -> i128;
+
+  fn token_royalties(env: Env, token_id: u128, payment_token: Address) -> i128;
+
+  fn report_listing(env: Env, marketplace: Address, token_id: u128, price: Option<i128>);
+
+  fn report_offer(env: Env, marketplace: Address, token_id: u128, price: Option<i128>);
+
+  fn cheapest_listing(env: Env, start: u128, limit: u32) -> Option<PriceQuote>;
+
+  fn highest_offer(env: Env, token_id: u128) -> Option<PriceQuote>;
+
+  fn set_transfer_cooldown(env: Env, period: u64, marketplace_exempt: bool);
+
+  fn set_mint_paused(env: Env, paused: bool);
+
+  fn mint_paused(env: Env) -> bool;
+
+  fn set_transfers_paused(env: Env, paused: bool);
+
+  fn transfers_paused(env: Env) -> bool;
+
+  fn set_burns_paused(env: Env, paused: bool);
+
+  fn burns_paused(env: Env) -> bool;
+
+  fn set_permissioned(env: Env, enabled: bool);
+
+  fn is_permissioned(env: Env) -> bool;
+
+  fn set_allowlisted(env: Env, account: Address, allowed: bool);
+
+  fn is_allowlisted(env: Env, account: Address) -> bool;
+
+  fn next_transferable_at(env: Env, token_id: u128) -> u64;
+
+  fn set_receiver_hook(env: Env, receiver: Address, enabled: bool);
+
+  fn has_receiver_hook(env: Env, receiver: Address) -> bool;
+
+  fn delegate_all(env: Env, vault: Address, delegate: Address, enabled: bool);
+
+  fn delegate_token(env: Env, vault: Address, delegate: Address, token_id: u128, enabled: bool);
+
+  fn is_delegate(env: Env, vault: Address, delegate: Address, token_id: u128) -> bool;
+
+  fn attach(env: Env, owner: Address, parent: u128, child: u128);
+
+  fn detach(env: Env, owner: Address, parent: u128, child: u128);
+
+  fn children_of(env: Env, token_id: u128) -> Vec<u128>;
+
+  fn parent_of(env: Env, token_id: u128) -> Option<u128>;
+
+  fn lock(env: Env, owner: Address, token_id: u128, until: u64);
+
+  fn unlock(env: Env, owner: Address, token_id: u128);
+
+  fn marketplace_lock(env: Env, marketplace: Address, token_id: u128, until: u64);
+
+  fn locked_until(env: Env, token_id: u128) -> u64;
+
   fn get_nft_detail(env: Env, token_id: u128) -> NFTDetail;
 
+  fn creator_of(env: Env, token_id: u128) -> Address;
+
+  fn set_creator_key(env: Env, creator: Address, public_key: BytesN<32>);
+
+  fn attach_coa(env: Env, token_id: u128, hash: BytesN<32>, signature: BytesN<64>);
+
+  fn get_coa(env: Env, token_id: u128) -> Option<CertificateOfAuthenticity>;
+
   fn read_administrator(env: Env) -> Address;
 
   fn has_administrator(env: Env) -> bool;
@@ -70,6 +451,8 @@ pub trait NFTTrait {
   fn name(env: Env) -> String;
 
   fn symbol(env: Env) -> String;
+
+  fn supports(env: Env, interface_id: Symbol) -> bool;
 }
 
 #[contract]
@@ -82,126 +465,1325 @@ impl NFTTrait for NFTContract {
           panic!("Contract already initialized")
       }
 
-      let metadata = NFTMetadata { name, symbol };
+      let config = NFTConfig { version: CONFIG_VERSION, admin, name, symbol, counter: 0 };
 
-      env.storage().instance().set(&DataKey::Admin, &admin);
-      env.storage().persistent().set(&METADATA_KEY, &metadata);
+      write_config(&env, &config);
   }
 
   fn mint_nft(env: Env, to: Address, token_uri: String) -> u128 {
       to.require_auth();
+      check_uri(&env, &token_uri, true);
 
-      if to == env.current_contract_address() {
-          panic!("Sender can not be contract address")
-      } else if token_uri == String::from_slice(&env, "") {
-          panic!("NFT URI can not be empty")
-      }
+      mint_token(&env, to, token_uri)
+  }
 
-      let mut token_id: u128 = env.storage().instance().get(&COUNTER).unwrap_or(0);
+  fn mint_with_suffix(env: Env, to: Address, uri_suffix: String) -> u128 {
+      to.require_auth();
+      check_uri(&env, &uri_suffix, false);
 
-      token_id += 1;
+      // Only the suffix is stored, token_uri prepends the collection base URI
+      let token_id = mint_token(&env, to, uri_suffix);
 
-      let mint_event: MintEvent = MintEvent { address: to.clone(), token_id };
-      let nft_detail: NFTDetail = NFTDetail {
-          owner: to,
-          uri: token_uri,
-      };
+      env.storage().instance().set(&DataKey::Suffixed(token_id), &true);
 
-      env.storage().instance().set(&token_id, &nft_detail);
-      env.storage().instance().set(&COUNTER, &token_id);
-      env.events().publish((MINT_EVENT, symbol_short!("mint")), mint_event);
-      
       token_id
   }
 
-  fn burn_nft(env: Env, owner: Address, token_id: u128) {
-      owner.require_auth();
+  // Mints a token with the hash of its metadata JSON so marketplaces can
+  // detect content swapped behind a mutable gateway URI
+  fn mint_with_metadata_hash(env: Env, to: Address, token_uri: String, metadata_hash: BytesN<32>) -> u128 {
+      to.require_auth();
+      check_uri(&env, &token_uri, true);
 
-      if Self::has_nft_owner(env.clone(), owner.clone(), token_id) {
-          panic!("Invalid Sender")
-      } else if owner == env.current_contract_address() {
-          panic!("Sender can not be contract address")
+      let token_id = mint_token(&env, to, token_uri);
+
+      env.storage().persistent().set(&DataKey::MetadataHash(token_id), &metadata_hash);
+
+      token_id
+  }
+
+  // Mints a token with the hash of its content, rejecting content already
+  // minted in the collection unless the admin allowed one duplicate of it
+  fn mint_with_content_hash(env: Env, to: Address, token_uri: String, content_hash: Option<BytesN<32>>) -> u128 {
+      to.require_auth();
+      check_uri(&env, &token_uri, true);
+
+      let content_hash = match content_hash {
+          Some(content_hash) => content_hash,
+          None => return mint_token(&env, to, token_uri),
+      };
+
+      let allowed_key = DataKey::DuplicateAllowed(content_hash.clone());
+      let duplicate = Self::token_by_content_hash(env.clone(), content_hash.clone()).is_some();
+
+      if duplicate && !env.storage().persistent().has(&allowed_key) {
+          panic!("Content already minted")
       }
 
-      let mut nft_detail = Self::get_nft_detail(env.clone(), token_id);
+      let token_id = mint_token(&env, to, token_uri);
 
-      if nft_detail.owner != owner || nft_detail.owner == env.current_contract_address() {
-          panic!("NFT not exist")
+      env.storage().persistent().set(&DataKey::ContentHash(token_id), &content_hash);
+      if duplicate {
+          env.storage().persistent().remove(&allowed_key);
+      } else {
+          env.storage().persistent().set(&DataKey::ContentToken(content_hash), &token_id);
       }
 
-      nft_detail.owner = env.current_contract_address();
-      nft_detail.uri = String::from_slice(&env, "");
+      token_id
+  }
 
-      let burn_event: BurnEvent = BurnEvent { address: owner.clone(), token_id };
+  // Lets the next mint of already minted content through, e.g. editions
+  fn allow_duplicate_content(env: Env, content_hash: BytesN<32>) {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
 
-      env.storage().instance().set(&token_id, &nft_detail);
-      env.events().publish((BURN_EVENT, symbol_short!("burn")), burn_event);
+      env.storage().persistent().set(&DataKey::DuplicateAllowed(content_hash), &true);
   }
 
-  fn transfer_nft(env: Env, from: Address, to: Address, token_id: u128) {
-      from.require_auth();
+  // First live token minted with the content hash
+  fn token_by_content_hash(env: Env, content_hash: BytesN<32>) -> Option<u128> {
+      env.storage().persistent().get(&DataKey::ContentToken(content_hash))
+  }
 
-      if Self::has_nft_owner(env.clone(), from.clone(), token_id) {
-          panic!("Invalid Sender")
-      } else if from == env.current_contract_address() {
-          panic!("Sender can not be contract address")
-      }
+  fn content_hash(env: Env, token_id: u128) -> Option<BytesN<32>> {
+      env.storage().persistent().get(&DataKey::ContentHash(token_id))
+  }
 
-      let mut nft_detail = Self::get_nft_detail(env.clone(), token_id);
+  fn metadata_hash(env: Env, token_id: u128) -> Option<BytesN<32>> {
+      env.storage().persistent().get(&DataKey::MetadataHash(token_id))
+  }
 
-      if nft_detail.owner != from || nft_detail.owner == env.current_contract_address() {
-          panic!("NFT not exist")
-      }
+  fn verify_metadata(env: Env, token_id: u128, hash: BytesN<32>) -> bool {
+      Self::metadata_hash(env, token_id) == Some(hash)
+  }
 
-      let transfer_event: TransferEvent = TransferEvent { from: from.clone(), to: to.clone(), token_id };
-      nft_detail.owner = to;
+  fn set_pass_config(env: Env, start: u64, end: u64, transferable: bool) {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
 
-      env.storage().instance().set(&token_id, &nft_detail);
-      env.events().publish((TRANSFER_EVENT, symbol_short!("transfer")), transfer_event);
+      if end <= start {
+          panic!("Invalid redemption window")
+      }
+
+      env.storage().instance().set(&DataKey::PassConfig, &MintPassConfig { start, end, transferable });
   }
 
-  fn get_nft_detail(env: Env, token_id: u128) -> NFTDetail {
-      let detail: NFTDetail = env
-          .storage()
-          .instance()
-          .get(&token_id)
-          .unwrap_or(NFTDetail {
-              owner: env.current_contract_address(),
-              uri: String::from_slice(&env, ""),
-          });
+  // Passes are redeemed one per mint, for pre-sales and loyalty drops
+  fn issue_passes(env: Env, to: Address, count: u32) {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
 
-      return detail;
+      let balance = Self::pass_balance(env.clone(), to.clone());
+      env.storage().persistent().set(&DataKey::Passes(to.clone()), &(balance + count));
+
+      let pass_event: PassEvent = PassEvent { from: None, to, count };
+      env.events().publish((PASS_EVENT, symbol_short!("issue")), pass_event);
   }
 
-  fn read_administrator(env: Env) -> Address {
-      env.storage().instance().get(&DataKey::Admin).unwrap()
+  fn transfer_passes(env: Env, from: Address, to: Address, count: u32) {
+      from.require_auth();
+
+      let config: MintPassConfig = env.storage().instance().get(&DataKey::PassConfig).expect("Mint passes not configured");
+
+      if !config.transferable {
+          panic!("Mint passes are account bound")
+      }
+
+      let balance = Self::pass_balance(env.clone(), from.clone());
+
+      if balance < count {
+          panic!("Not enough mint passes")
+      }
+
+      let to_balance = Self::pass_balance(env.clone(), to.clone());
+      env.storage().persistent().set(&DataKey::Passes(from.clone()), &(balance - count));
+      env.storage().persistent().set(&DataKey::Passes(to.clone()), &(to_balance + count));
+
+      let pass_event: PassEvent = PassEvent { from: Some(from), to, count };
+      env.events().publish((PASS_EVENT, symbol_short!("transfer")), pass_event);
   }
 
-  fn has_administrator(env: Env) -> bool {
-      let key = DataKey::Admin;
-      env.storage().instance().has(&key)
+  fn pass_balance(env: Env, account: Address) -> u32 {
+      env.storage().persistent().get(&DataKey::Passes(account)).unwrap_or(0)
   }
 
-  fn has_nft_owner(env: Env, account: Address, token_id: u128) -> bool {
-      let nft_detail = Self::get_nft_detail(env.clone(), token_id.clone());
+  fn redeem_pass(env: Env, holder: Address, token_uri: String) -> u128 {
+      holder.require_auth();
+      check_uri(&env, &token_uri, true);
 
-      if nft_detail.owner != account {
-          return true;
-      } else {
-          return false;
+      let config: MintPassConfig = env.storage().instance().get(&DataKey::PassConfig).expect("Mint passes not configured");
+      let now = env.ledger().timestamp();
+
+      if now < config.start || now >= config.end {
+          panic!("Outside the redemption window")
+      }
+
+      let balance = Self::pass_balance(env.clone(), holder.clone());
+
+      if balance == 0 {
+          panic!("No mint pass")
       }
+
+      env.storage().persistent().set(&DataKey::Passes(holder.clone()), &(balance - 1));
+
+      mint_token(&env, holder, token_uri)
   }
 
-  fn name(env: Env) -> String {
-      let metadata: NFTMetadata = env.storage().persistent().get(&METADATA_KEY).unwrap();
+  // Migrates tokens of an existing collection under their original ids and
+  // owners. Only available until finalize_import, the counter is moved past
+  // every imported id so later mints can't collide with them.
+  fn import_tokens(env: Env, tokens: Vec<(u128, Address, String)>) {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
+
+      if Self::import_finalized(env.clone()) {
+          panic!("Import already finalized")
+      } else if tokens.len() > MAX_IMPORT_BATCH {
+          panic!("Too many tokens")
+      }
+
+      let mut config = read_config(&env);
+
+      for (token_id, owner, token_uri) in tokens.iter() {
+          TokenId::from_u128(token_id);
+
+          if owner == env.current_contract_address() {
+              panic!("Owner can not be contract address")
+          } else if token_uri == String::from_slice(&env, "") {
+              panic!("NFT URI can not be empty")
+          } else if Self::get_nft_detail(env.clone(), token_id).owner != env.current_contract_address() {
+              panic!("NFT already exist")
+          }
+          check_uri(&env, &token_uri, true);
 
-      metadata.name
+          let mint_event: MintEvent = MintEvent { address: owner.clone(), token_id };
+          let nft_detail: NFTDetail = NFTDetail { owner, uri: token_uri };
+
+          env.storage().instance().set(&token_id, &nft_detail);
+          env.storage().persistent().set(&DataKey::Creator(token_id), &admin);
+          env.events().publish((MINT_EVENT, symbol_short!("import")), mint_event);
+
+          if token_id > config.counter {
+              config.counter = token_id;
+          }
+      }
+
+      write_config(&env, &config);
   }
 
-  fn symbol(env: Env) -> String {
-      let metadata: NFTMetadata = env.storage().persistent().get(&METADATA_KEY).unwrap();
+  // Sets the last issued token id, e.g. to skip ids burned in the source collection
+  fn set_counter(env: Env, value: u128) {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
+
+      let mut config = read_config(&env);
+
+      if Self::import_finalized(env.clone()) {
+          panic!("Import already finalized")
+      } else if value < config.counter {
+          panic!("Counter can not go backwards")
+      }
 
-      metadata.symbol
+      TokenId::from_u128(value);
+
+      config.counter = value;
+      write_config(&env, &config);
+  }
+
+  fn finalize_import(env: Env) {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
+
+      env.storage().instance().set(&DataKey::ImportFinalized, &true);
+  }
+
+  fn import_finalized(env: Env) -> bool {
+      env.storage().instance().has(&DataKey::ImportFinalized)
+  }
+
+  // Mints a token held by the contract until someone presents the preimage
+  // of code_hash, for onboarding users who don't have a wallet yet
+  fn mint_claimable(env: Env, code_hash: BytesN<32>, token_uri: String) -> u128 {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
+      check_uri(&env, &token_uri, true);
+
+      if token_uri == String::from_slice(&env, "") {
+          panic!("NFT URI can not be empty")
+      }
+
+      let token_id = next_token_id(&env);
+
+      let mint_event: MintEvent = MintEvent { address: env.current_contract_address(), token_id };
+      let nft_detail: NFTDetail = NFTDetail {
+          owner: env.current_contract_address(),
+          uri: token_uri,
+      };
+
+      env.storage().instance().set(&token_id, &nft_detail);
+      env.storage().persistent().set(&DataKey::Creator(token_id), &admin);
+      env.storage().persistent().set(&DataKey::Claim(token_id), &code_hash);
+      env.events().publish((MINT_EVENT, symbol_short!("claimable")), mint_event);
+
+      token_id
+  }
+
+  // First step of a claim: commits to sha256 of the claim code followed by
+  // the receiver's XDR, so the code revealed by claim_minted in a later
+  // ledger can't be front-run to another receiver
+  fn commit_claim(env: Env, token_id: u128, commitment: BytesN<32>) {
+      if !Self::is_claimable(env.clone(), token_id) {
+          panic!("NFT not claimable")
+      }
+
+      env.storage().temporary().set(&DataKey::ClaimCommit(token_id, commitment), &env.ledger().sequence());
+  }
+
+  fn claim_minted(env: Env, to: Address, token_id: u128, code_preimage: Bytes) {
+      to.require_auth();
+
+      let code_hash: BytesN<32> = env
+          .storage()
+          .persistent()
+          .get(&DataKey::Claim(token_id))
+          .expect("NFT not claimable");
+
+      let mut commitment_preimage = code_preimage.clone();
+      commitment_preimage.append(&to.clone().to_xdr(&env));
+      let commit_key = DataKey::ClaimCommit(token_id, env.crypto().sha256(&commitment_preimage));
+      let committed_at: Option<u32> = env.storage().temporary().get(&commit_key);
+
+      if Self::transfers_paused(env.clone()) {
+          panic!("Transfers are paused")
+      } else if env.crypto().sha256(&code_preimage) != code_hash {
+          panic!("Invalid claim code")
+      } else if committed_at.map_or(true, |ledger| ledger >= env.ledger().sequence()) {
+          panic!("Claim not committed in an earlier ledger")
+      } else if to == env.current_contract_address() {
+          panic!("Receiver can not be contract address")
+      } else if !can_hold(&env, &to) {
+          panic!("Receiver not allowlisted")
+      }
+
+      env.storage().temporary().remove(&commit_key);
+
+      let mut nft_detail = Self::get_nft_detail(env.clone(), token_id);
+      nft_detail.owner = to.clone();
+
+      let transfer_event: TransferEvent = TransferEvent { from: env.current_contract_address(), to, token_id };
+
+      env.storage().instance().set(&token_id, &nft_detail);
+      env.storage().persistent().remove(&DataKey::Claim(token_id));
+      env.events().publish((TRANSFER_EVENT, symbol_short!("claim")), transfer_event);
+  }
+
+  fn is_claimable(env: Env, token_id: u128) -> bool {
+      env.storage().persistent().has(&DataKey::Claim(token_id))
+  }
+
+  fn set_base_uri(env: Env, base_uri: String) {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
+      check_uri(&env, &base_uri, true);
+
+      env.storage().instance().set(&DataKey::BaseUri, &base_uri);
+      env.events().publish((BASE_URI_EVENT, symbol_short!("set")), base_uri);
+  }
+
+  fn set_uri_policy(env: Env, max_len: u32, schemes: Vec<String>) {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
+
+      if max_len == 0 || max_len as usize > MAX_URI_LEN {
+          panic!("Invalid max URI length")
+      } else if schemes.len() > MAX_URI_SCHEMES {
+          panic!("Too many URI schemes")
+      }
+
+      for scheme in schemes.iter() {
+          if scheme.len() == 0 || scheme.len() as usize > MAX_SCHEME_LEN {
+              panic!("Invalid URI scheme")
+          }
+      }
+
+      env.storage().instance().set(&DataKey::UriPolicy, &UriPolicy { max_len, schemes });
+  }
+
+  fn uri_policy(env: Env) -> UriPolicy {
+      env.storage().instance().get(&DataKey::UriPolicy).unwrap_or(UriPolicy {
+          max_len: MAX_URI_LEN as u32,
+          schemes: Vec::new(&env),
+      })
+  }
+
+  fn base_uri(env: Env) -> String {
+      env.storage().instance().get(&DataKey::BaseUri).unwrap_or(String::from_slice(&env, ""))
+  }
+
+  fn token_uri(env: Env, token_id: u128) -> String {
+      let nft_detail = Self::get_nft_detail(env.clone(), token_id);
+
+      if !env.storage().instance().has(&DataKey::Suffixed(token_id)) {
+          return nft_detail.uri;
+      }
+
+      let mut suffix = [0u8; MAX_URI_LEN];
+      let suffix_len = nft_detail.uri.len() as usize;
+
+      if suffix_len > MAX_URI_LEN {
+          panic!("NFT URI too long")
+      }
+      nft_detail.uri.copy_into_slice(&mut suffix[..suffix_len]);
+
+      join_uri(&env, &Self::base_uri(env.clone()), &suffix[..suffix_len])
+  }
+
+  fn mint_range(env: Env, to: Address, count: u128, base_uri: String) -> u128 {
+      to.require_auth();
+      check_uri(&env, &base_uri, true);
+
+      if to == env.current_contract_address() {
+          panic!("Sender can not be contract address")
+      } else if base_uri == String::from_slice(&env, "") {
+          panic!("NFT URI can not be empty")
+      } else if count == 0 || count > MAX_RANGE_MINT {
+          panic!("Invalid mint count")
+      } else if !can_hold(&env, &to) {
+          panic!("Receiver not allowlisted")
+      }
+
+      let start = allocate_token_ids(&env, count);
+      let end = start + count - 1;
+
+      // The last id has the most digits, so its URI is the longest of the range
+      check_uri(&env, &compose_uri(&env, &base_uri, end), false);
+
+      let mut ranges = read_ranges(&env);
+      ranges.push_back(MintRange { start, end, owner: to.clone(), base_uri });
+
+      let mint_event: MintRangeEvent = MintRangeEvent { address: to, start, end };
+
+      env.storage().persistent().set(&DataKey::Ranges, &ranges);
+      env.events().publish((MINT_EVENT, symbol_short!("mintrange")), mint_event);
+
+      start
+  }
+
+  fn burn_nft(env: Env, owner: Address, token_id: u128) {
+      owner.require_auth();
+      TokenId::from_u128(token_id);
+
+      if Self::burns_paused(env.clone()) {
+          panic!("Burns are paused")
+      } else if Self::has_nft_owner(env.clone(), owner.clone(), token_id) {
+          panic!("Invalid Sender")
+      } else if owner == env.current_contract_address() {
+          panic!("Sender can not be contract address")
+      }
+
+      if Self::locked_until(env.clone(), token_id) > env.ledger().timestamp() {
+          panic!("NFT is locked")
+      }
+
+      let nft_detail = Self::get_nft_detail(env.clone(), token_id);
+
+      if nft_detail.owner != owner || nft_detail.owner == env.current_contract_address() {
+          panic!("NFT not exist")
+      }
+
+      let burn_event: BurnEvent = BurnEvent { address: owner.clone(), token_id };
+
+      clear_token(&env, token_id);
+      env.events().publish((BURN_EVENT, symbol_short!("burn")), burn_event);
+  }
+
+  fn approve(env: Env, owner: Address, operator: Address, token_id: u128) {
+      owner.require_auth();
+      TokenId::from_u128(token_id);
+
+      if Self::has_nft_owner(env.clone(), owner.clone(), token_id) {
+          panic!("Invalid Sender")
+      } else if owner == operator {
+          panic!("Owner can not be operator")
+      }
+
+      let approve_event: ApproveEvent = ApproveEvent { owner, operator: operator.clone(), token_id };
+
+      env.storage().instance().set(&DataKey::Approval(token_id), &operator);
+      env.events().publish((APPROVE_EVENT, symbol_short!("approve")), approve_event);
+  }
+
+  fn get_approved(env: Env, token_id: u128) -> Option<Address> {
+      env.storage().instance().get(&DataKey::Approval(token_id))
+  }
+
+  fn burn_from(env: Env, operator: Address, token_id: u128) {
+      operator.require_auth();
+
+      if Self::burns_paused(env.clone()) {
+          panic!("Burns are paused")
+      } else if Self::get_approved(env.clone(), token_id) != Some(operator) {
+          panic!("Operator not approved")
+      }
+
+      if Self::locked_until(env.clone(), token_id) > env.ledger().timestamp() {
+          panic!("NFT is locked")
+      }
+
+      let nft_detail = Self::get_nft_detail(env.clone(), token_id);
+      let burn_event: BurnEvent = BurnEvent { address: nft_detail.owner, token_id };
+
+      clear_token(&env, token_id);
+      env.events().publish((BURN_EVENT, symbol_short!("burn")), burn_event);
+  }
+
+  fn force_burn(env: Env, token_id: u128, reason_code: u32) {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
+
+      if reason_code != REASON_ILLEGAL_CONTENT
+          && reason_code != REASON_COPYRIGHT
+          && reason_code != REASON_POLICY_VIOLATION
+      {
+          panic!("Invalid reason code")
+      }
+
+      let nft_detail = Self::get_nft_detail(env.clone(), token_id);
+
+      if nft_detail.owner == env.current_contract_address() {
+          panic!("NFT not exist")
+      }
+
+      let force_burn_event: ForceBurnEvent = ForceBurnEvent { owner: nft_detail.owner, token_id, reason_code };
+
+      clear_token(&env, token_id);
+      env.storage().instance().remove(&DataKey::Lock(token_id));
+      env.storage().instance().remove(&DataKey::MarketLock(token_id));
+      env.events().publish((FORCE_BURN_EVENT, symbol_short!("forceburn")), force_burn_event);
+  }
+
+  fn transfer_nft(env: Env, from: Address, to: Address, token_id: u128) {
+      from.require_auth();
+
+      move_token(&env, &from, &to, token_id, false);
+
+      let transfer_event: TransferEvent = TransferEvent { from, to, token_id };
+      env.events().publish((TRANSFER_EVENT, symbol_short!("transfer")), transfer_event);
+  }
+
+  // Transfers with an attached payload (invoice, order id, compliance note),
+  // forwarded to the receiver if it registered a hook
+  fn transfer_with_data(env: Env, from: Address, to: Address, token_id: u128, data: Bytes) {
+      from.require_auth();
+
+      move_token(&env, &from, &to, token_id, false);
+
+      let transfer_event = TransferDataEvent {
+          from: from.clone(),
+          to: to.clone(),
+          token_id,
+          data: data.clone(),
+      };
+      env.events().publish((TRANSFER_EVENT, symbol_short!("withdata")), transfer_event);
+
+      if Self::has_receiver_hook(env.clone(), to.clone()) {
+          NFTReceiverClient::new(&env, &to).on_nft_received(&from, &token_id, &data);
+      }
+  }
+
+  // Transfer for a sale settled by a registered marketplace. The seller
+  // authorized the sale when listing it there, so only the marketplace signs.
+  fn marketplace_transfer(env: Env, marketplace: Address, from: Address, to: Address, token_id: u128) {
+      marketplace.require_auth();
+
+      if !env.storage().instance().has(&DataKey::Marketplace(marketplace)) {
+          panic!("Marketplace not registered")
+      }
+
+      move_token(&env, &from, &to, token_id, true);
+
+      let transfer_event: TransferEvent = TransferEvent { from, to, token_id };
+      env.events().publish((TRANSFER_EVENT, symbol_short!("sale")), transfer_event);
+  }
+
+  fn set_marketplace(env: Env, marketplace: Address, allowed: bool) {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
+
+      if allowed {
+          env.storage().instance().set(&DataKey::Marketplace(marketplace), &true);
+      } else {
+          env.storage().instance().remove(&DataKey::Marketplace(marketplace));
+      }
+  }
+
+  fn is_marketplace(env: Env, marketplace: Address) -> bool {
+      env.storage().instance().has(&DataKey::Marketplace(marketplace))
+  }
+
+  // Registered marketplaces report each royalty they pay out so creators can
+  // check their totals on-chain, amounts are kept per payment token
+  fn record_royalty(env: Env, marketplace: Address, token_id: u128, creator: Address, payment_token: Address, amount: i128) {
+      marketplace.require_auth();
+
+      if !Self::is_marketplace(env.clone(), marketplace) {
+          panic!("Marketplace not registered")
+      } else if amount <= 0 {
+          panic!("Royalty must be positive")
+      }
+
+      let earned = Self::royalties_earned(env.clone(), creator.clone(), payment_token.clone());
+      let token_total = Self::token_royalties(env.clone(), token_id, payment_token.clone());
+
+      let earned = earned.checked_add(amount).expect("Royalties overflow");
+      let token_total = token_total.checked_add(amount).expect("Royalties overflow");
+
+      env.storage()
+          .persistent()
+          .set(&DataKey::RoyaltiesEarned(creator, payment_token.clone()), &earned);
+      env.storage()
+          .persistent()
+          .set(&DataKey::TokenRoyalties(token_id, payment_token), &token_total);
+  }
+
+  fn royalties_earned(env: Env, creator: Address, payment_token: Address) -> i128 {
+      env.storage()
+          .persistent()
+          .get(&DataKey::RoyaltiesEarned(creator, payment_token))
+          .unwrap_or(0)
+  }
+
+  fn token_royalties(env: Env, token_id: u128, payment_token: Address) -> i128 {
+      env.storage()
+          .persistent()
+          .get(&DataKey::TokenRoyalties(token_id, payment_token))
+          .unwrap_or(0)
+  }
+
+  // Marketplaces keep the collection's price views current by reporting
+  // each listing as it opens (Some) and closes (None)
+  fn report_listing(env: Env, marketplace: Address, token_id: u128, price: Option<i128>) {
+      marketplace.require_auth();
+
+      if !Self::is_marketplace(env.clone(), marketplace.clone()) {
+          panic!("Marketplace not registered")
+      }
+
+      match price {
+          Some(price) => {
+              let quote = PriceQuote { token_id, marketplace, price };
+              env.storage().persistent().set(&DataKey::ListingQuote(token_id), &quote);
+          }
+          None => env.storage().persistent().remove(&DataKey::ListingQuote(token_id)),
+      }
+  }
+
+  // Same as report_listing for the best offer or bid a marketplace holds on a token
+  fn report_offer(env: Env, marketplace: Address, token_id: u128, price: Option<i128>) {
+      marketplace.require_auth();
+
+      if !Self::is_marketplace(env.clone(), marketplace.clone()) {
+          panic!("Marketplace not registered")
+      }
+
+      let mut markets: Vec<Address> = env
+          .storage()
+          .persistent()
+          .get(&DataKey::OfferMarkets(token_id))
+          .unwrap_or(Vec::new(&env));
+      let index = markets.first_index_of(marketplace.clone());
+      let key = DataKey::OfferQuote(token_id, marketplace.clone());
+
+      match price {
+          Some(price) => {
+              env.storage().persistent().set(&key, &PriceQuote { token_id, marketplace: marketplace.clone(), price });
+
+              if index.is_none() {
+                  markets.push_back(marketplace);
+              }
+          }
+          None => {
+              env.storage().persistent().remove(&key);
+
+              if let Some(index) = index {
+                  markets.remove(index);
+              }
+          }
+      }
+
+      env.storage().persistent().set(&DataKey::OfferMarkets(token_id), &markets);
+  }
+
+  // Floor among tokens start..start + limit across every reporting
+  // marketplace, at most MAX_QUOTE_SCAN ids per call. Clients page through
+  // the collection and keep the lowest quote.
+  fn cheapest_listing(env: Env, start: u128, limit: u32) -> Option<PriceQuote> {
+      let counter = read_config(&env).counter;
+      let end = start.saturating_add(limit.min(MAX_QUOTE_SCAN) as u128).min(counter.saturating_add(1));
+      let mut cheapest: Option<PriceQuote> = None;
+
+      for token_id in start.max(1)..end {
+          let quote: Option<PriceQuote> = env.storage().persistent().get(&DataKey::ListingQuote(token_id));
+
+          if let Some(quote) = quote {
+              if cheapest.as_ref().map_or(true, |cheapest| quote.price < cheapest.price) {
+                  cheapest = Some(quote);
+              }
+          }
+      }
+
+      cheapest
+  }
+
+  fn highest_offer(env: Env, token_id: u128) -> Option<PriceQuote> {
+      let markets: Vec<Address> = env
+          .storage()
+          .persistent()
+          .get(&DataKey::OfferMarkets(token_id))
+          .unwrap_or(Vec::new(&env));
+      let mut highest: Option<PriceQuote> = None;
+
+      for marketplace in markets.iter() {
+          let quote: PriceQuote = env.storage().persistent().get(&DataKey::OfferQuote(token_id, marketplace)).unwrap();
+
+          if highest.as_ref().map_or(true, |highest| quote.price > highest.price) {
+              highest = Some(quote);
+          }
+      }
+
+      highest
+  }
+
+  // Deters wash trading, a period of 0 disables the cooldown
+  fn set_transfer_cooldown(env: Env, period: u64, marketplace_exempt: bool) {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
+
+      env.storage().instance().set(&DataKey::Cooldown, &TransferCooldown { period, marketplace_exempt });
+  }
+
+  // Minting, transfers and burns are paused independently, so a botched
+  // drop can be stopped without freezing the secondary market
+  fn set_mint_paused(env: Env, paused: bool) {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
+
+      env.storage().instance().set(&DataKey::MintPaused, &paused);
+      env.events().publish((PAUSE_EVENT, symbol_short!("mint")), PauseEvent { paused });
+  }
+
+  fn mint_paused(env: Env) -> bool {
+      env.storage().instance().get(&DataKey::MintPaused).unwrap_or(false)
+  }
+
+  fn set_transfers_paused(env: Env, paused: bool) {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
+
+      env.storage().instance().set(&DataKey::TransfersPaused, &paused);
+      env.events().publish((PAUSE_EVENT, symbol_short!("transfer")), PauseEvent { paused });
+  }
+
+  fn transfers_paused(env: Env) -> bool {
+      env.storage().instance().get(&DataKey::TransfersPaused).unwrap_or(false)
+  }
+
+  // Forced burns by the admin stay available while burns are paused
+  fn set_burns_paused(env: Env, paused: bool) {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
+
+      env.storage().instance().set(&DataKey::BurnsPaused, &paused);
+      env.events().publish((PAUSE_EVENT, symbol_short!("burn")), PauseEvent { paused });
+  }
+
+  fn burns_paused(env: Env) -> bool {
+      env.storage().instance().get(&DataKey::BurnsPaused).unwrap_or(false)
+  }
+
+  // In a permissioned collection tokens can only be minted to and moved
+  // between allowlisted addresses, e.g. wallets that passed KYC
+  fn set_permissioned(env: Env, enabled: bool) {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
+
+      env.storage().instance().set(&DataKey::Permissioned, &enabled);
+  }
+
+  fn is_permissioned(env: Env) -> bool {
+      env.storage().instance().get(&DataKey::Permissioned).unwrap_or(false)
+  }
+
+  fn set_allowlisted(env: Env, account: Address, allowed: bool) {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
+
+      if allowed {
+          env.storage().persistent().set(&DataKey::Allowlisted(account), &true);
+      } else {
+          env.storage().persistent().remove(&DataKey::Allowlisted(account));
+      }
+  }
+
+  fn is_allowlisted(env: Env, account: Address) -> bool {
+      env.storage().persistent().has(&DataKey::Allowlisted(account))
+  }
+
+  fn next_transferable_at(env: Env, token_id: u128) -> u64 {
+      let cooldown: Option<TransferCooldown> = env.storage().instance().get(&DataKey::Cooldown);
+      let last_transfer: Option<u64> = env.storage().persistent().get(&DataKey::LastTransfer(token_id));
+
+      match (cooldown, last_transfer) {
+          (Some(cooldown), Some(last_transfer)) => last_transfer.checked_add(cooldown.period).unwrap_or(u64::MAX),
+          _ => 0,
+      }
+  }
+
+  fn set_receiver_hook(env: Env, receiver: Address, enabled: bool) {
+      receiver.require_auth();
+
+      if enabled {
+          env.storage().persistent().set(&DataKey::ReceiverHook(receiver), &true);
+      } else {
+          env.storage().persistent().remove(&DataKey::ReceiverHook(receiver));
+      }
+  }
+
+  fn has_receiver_hook(env: Env, receiver: Address) -> bool {
+      env.storage().persistent().has(&DataKey::ReceiverHook(receiver))
+  }
+
+  // Delegates grant a hot wallet the utility of the vault's tokens (claims,
+  // gated access) without any transfer right
+  fn delegate_all(env: Env, vault: Address, delegate: Address, enabled: bool) {
+      vault.require_auth();
+
+      let key = DataKey::DelegateAll(vault.clone(), delegate.clone());
+      if enabled {
+          env.storage().persistent().set(&key, &true);
+      } else {
+          env.storage().persistent().remove(&key);
+      }
+
+      let delegate_event: DelegateEvent = DelegateEvent { vault, delegate, token_id: 0, enabled };
+      env.events().publish((DELEGATE_EVENT, symbol_short!("all")), delegate_event);
+  }
+
+  fn delegate_token(env: Env, vault: Address, delegate: Address, token_id: u128, enabled: bool) {
+      vault.require_auth();
+
+      if Self::has_nft_owner(env.clone(), vault.clone(), token_id) {
+          panic!("Invalid Sender")
+      }
+
+      let key = DataKey::DelegateToken(vault.clone(), delegate.clone(), token_id);
+      if enabled {
+          env.storage().persistent().set(&key, &true);
+      } else {
+          env.storage().persistent().remove(&key);
+      }
+
+      let delegate_event: DelegateEvent = DelegateEvent { vault, delegate, token_id, enabled };
+      env.events().publish((DELEGATE_EVENT, symbol_short!("token")), delegate_event);
+  }
+
+  // A delegation only counts while the vault still holds the token
+  fn is_delegate(env: Env, vault: Address, delegate: Address, token_id: u128) -> bool {
+      if Self::has_nft_owner(env.clone(), vault.clone(), token_id) {
+          return false;
+      }
+
+      env.storage().persistent().has(&DataKey::DelegateAll(vault.clone(), delegate.clone()))
+          || env.storage().persistent().has(&DataKey::DelegateToken(vault, delegate, token_id))
+  }
+
+  // Nests child under parent, after which the child moves with the parent
+  // and can not be transferred on its own. Nesting is one level deep, a
+  // parent can not be a child and a child can not have children.
+  fn attach(env: Env, owner: Address, parent: u128, child: u128) {
+      owner.require_auth();
+      TokenId::from_u128(parent);
+      TokenId::from_u128(child);
+
+      let mut children = Self::children_of(env.clone(), parent);
+
+      if Self::has_nft_owner(env.clone(), owner.clone(), parent)
+          || Self::has_nft_owner(env.clone(), owner.clone(), child)
+      {
+          panic!("Invalid Sender")
+      } else if parent == child {
+          panic!("Token can not be its own child")
+      } else if Self::parent_of(env.clone(), child).is_some() {
+          panic!("Child already attached")
+      } else if Self::parent_of(env.clone(), parent).is_some() {
+          panic!("Parent is attached to another token")
+      } else if !Self::children_of(env.clone(), child).is_empty() {
+          panic!("Child has children")
+      } else if children.len() >= MAX_CHILDREN {
+          panic!("Too many children")
+      } else if Self::locked_until(env.clone(), child) > env.ledger().timestamp() {
+          panic!("NFT is locked")
+      } else if Self::next_transferable_at(env.clone(), child) > env.ledger().timestamp() {
+          panic!("NFT transfer cooldown active")
+      }
+
+      children.push_back(child);
+
+      let nest_event: NestEvent = NestEvent { owner, parent, child };
+
+      env.storage().persistent().set(&DataKey::Children(parent), &children);
+      env.storage().persistent().set(&DataKey::Parent(child), &parent);
+      env.storage().instance().remove(&DataKey::Approval(child));
+      env.events().publish((NEST_EVENT, symbol_short!("attach")), nest_event);
+  }
+
+  fn detach(env: Env, owner: Address, parent: u128, child: u128) {
+      owner.require_auth();
+
+      if Self::has_nft_owner(env.clone(), owner.clone(), parent) {
+          panic!("Invalid Sender")
+      } else if Self::parent_of(env.clone(), child) != Some(parent) {
+          panic!("Child not attached to parent")
+      }
+
+      let nest_event: NestEvent = NestEvent { owner, parent, child };
+
+      unlink_child(&env, parent, child);
+      env.events().publish((NEST_EVENT, symbol_short!("detach")), nest_event);
+  }
+
+  fn children_of(env: Env, token_id: u128) -> Vec<u128> {
+      env.storage().persistent().get(&DataKey::Children(token_id)).unwrap_or(Vec::new(&env))
+  }
+
+  fn parent_of(env: Env, token_id: u128) -> Option<u128> {
+      env.storage().persistent().get(&DataKey::Parent(token_id))
+  }
+
+  fn lock(env: Env, owner: Address, token_id: u128, until: u64) {
+      owner.require_auth();
+      TokenId::from_u128(token_id);
+
+      if Self::has_nft_owner(env.clone(), owner.clone(), token_id) {
+          panic!("Invalid Sender")
+      } else if until <= env.ledger().timestamp() {
+          panic!("Lock must end in the future")
+      }
+
+      let lock_event: LockEvent = LockEvent { owner, token_id, until };
+
+      env.storage().instance().set(&DataKey::Lock(token_id), &until);
+      env.events().publish((LOCK_EVENT, symbol_short!("lock")), lock_event);
+  }
+
+  fn unlock(env: Env, owner: Address, token_id: u128) {
+      owner.require_auth();
+
+      if Self::has_nft_owner(env.clone(), owner.clone(), token_id) {
+          panic!("Invalid Sender")
+      } else if !env.storage().instance().has(&DataKey::Lock(token_id)) {
+          panic!("NFT not locked")
+      }
+
+      let lock_event: LockEvent = LockEvent { owner, token_id, until: 0 };
+
+      env.storage().instance().remove(&DataKey::Lock(token_id));
+      env.events().publish((LOCK_EVENT, symbol_short!("unlock")), lock_event);
+  }
+
+  // Lock a registered marketplace applies to a token it just sold, e.g. a
+  // resale lock the seller disclosed. It is kept apart from the owner's own
+  // lock so unlock can't lift it, and only ever extends an existing one.
+  fn marketplace_lock(env: Env, marketplace: Address, token_id: u128, until: u64) {
+      marketplace.require_auth();
+      TokenId::from_u128(token_id);
+
+      if !Self::is_marketplace(env.clone(), marketplace) {
+          panic!("Marketplace not registered")
+      } else if until <= env.ledger().timestamp() {
+          panic!("Lock must end in the future")
+      }
+
+      let until = until.max(env.storage().instance().get(&DataKey::MarketLock(token_id)).unwrap_or(0));
+      let owner = Self::get_nft_detail(env.clone(), token_id).owner;
+      let lock_event: LockEvent = LockEvent { owner, token_id, until };
+
+      env.storage().instance().set(&DataKey::MarketLock(token_id), &until);
+      env.events().publish((LOCK_EVENT, symbol_short!("market")), lock_event);
+  }
+
+  fn locked_until(env: Env, token_id: u128) -> u64 {
+      let owner_lock: u64 = env.storage().instance().get(&DataKey::Lock(token_id)).unwrap_or(0);
+      let market_lock: u64 = env.storage().instance().get(&DataKey::MarketLock(token_id)).unwrap_or(0);
+      owner_lock.max(market_lock)
+  }
+
+  fn get_nft_detail(env: Env, token_id: u128) -> NFTDetail {
+      if let Some(detail) = env.storage().instance().get(&token_id) {
+          return detail;
+      }
+
+      match find_range(&env, token_id) {
+          Some(range) => NFTDetail {
+              owner: range.owner,
+              uri: compose_uri(&env, &range.base_uri, token_id),
+          },
+          None => NFTDetail {
+              owner: env.current_contract_address(),
+              uri: String::from_slice(&env, ""),
+          },
+      }
+  }
+
+  fn creator_of(env: Env, token_id: u128) -> Address {
+      if let Some(creator) = env.storage().persistent().get(&DataKey::Creator(token_id)) {
+          return creator;
+      }
+
+      find_range(&env, token_id).expect("NFT not exist").owner
+  }
+
+  fn set_creator_key(env: Env, creator: Address, public_key: BytesN<32>) {
+      creator.require_auth();
+
+      env.storage().persistent().set(&DataKey::CreatorKey(creator), &public_key);
+  }
+
+  fn attach_coa(env: Env, token_id: u128, hash: BytesN<32>, signature: BytesN<64>) {
+      let creator = Self::creator_of(env.clone(), token_id);
+      creator.require_auth();
+
+      let public_key: BytesN<32> = env
+          .storage()
+          .persistent()
+          .get(&DataKey::CreatorKey(creator.clone()))
+          .expect("Creator key not set");
+
+      // Panics if the signature over the hash was not made with the creator key
+      env.crypto().ed25519_verify(&public_key, &hash.clone().into(), &signature);
+
+      let coa: CertificateOfAuthenticity = CertificateOfAuthenticity { hash: hash.clone(), signature };
+      let coa_event: CoaEvent = CoaEvent { creator, token_id, hash };
+
+      env.storage().persistent().set(&DataKey::Coa(token_id), &coa);
+      env.events().publish((COA_EVENT, symbol_short!("attach")), coa_event);
+  }
+
+  fn get_coa(env: Env, token_id: u128) -> Option<CertificateOfAuthenticity> {
+      env.storage().persistent().get(&DataKey::Coa(token_id))
+  }
+
+  fn read_administrator(env: Env) -> Address {
+      read_config(&env).admin
+  }
+
+  fn has_administrator(env: Env) -> bool {
+      env.storage().instance().has(&DataKey::Config) || env.storage().instance().has(&DataKey::Admin)
+  }
+
+  fn has_nft_owner(env: Env, account: Address, token_id: u128) -> bool {
+      let nft_detail = Self::get_nft_detail(env.clone(), token_id.clone());
+
+      if nft_detail.owner != account {
+          return true;
+      } else {
+          return false;
+      }
+  }
+
+  fn name(env: Env) -> String {
+      read_config(&env).name
+  }
+
+  fn symbol(env: Env) -> String {
+      read_config(&env).symbol
+  }
+
+  fn supports(_env: Env, interface_id: Symbol) -> bool {
+      SUPPORTED_INTERFACES.contains(&interface_id)
+  }
+}
+
+// Reads the config entry, assembling it from the legacy layout if the
+// contract was initialized before the config was introduced
+fn read_config(env: &Env) -> NFTConfig {
+  if let Some(config) = env.storage().instance().get(&DataKey::Config) {
+      return config;
+  }
+
+  let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+  let metadata: NFTMetadata = env.storage().persistent().get(&METADATA_KEY).unwrap();
+  let counter: u128 = env.storage().instance().get(&COUNTER).unwrap_or(0);
+
+  NFTConfig { version: CONFIG_VERSION, admin, name: metadata.name, symbol: metadata.symbol, counter }
+}
+
+// Writes the config entry and drops any leftover legacy keys
+fn write_config(env: &Env, config: &NFTConfig) {
+  env.storage().instance().set(&DataKey::Config, config);
+
+  if env.storage().instance().has(&DataKey::Admin) {
+      env.storage().instance().remove(&DataKey::Admin);
+      env.storage().instance().remove(&COUNTER);
+      env.storage().persistent().remove(&METADATA_KEY);
+  }
+}
+
+// Bumps the token counter in a single read-modify-write of the config
+fn next_token_id(env: &Env) -> u128 {
+  allocate_token_ids(env, 1)
+}
+
+// Reserves count consecutive token ids and returns the first one
+fn allocate_token_ids(env: &Env, count: u128) -> u128 {
+  if NFTContract::mint_paused(env.clone()) {
+      panic!("Minting is paused")
+  }
+
+  let mut config = read_config(env);
+  let start = config.counter + 1;
+
+  config.counter += count;
+  TokenId::from_u128(config.counter);
+  write_config(env, &config);
+
+  start
+}
+
+fn read_ranges(env: &Env) -> Vec<MintRange> {
+  env.storage().persistent().get(&DataKey::Ranges).unwrap_or(Vec::new(env))
+}
+
+// Binary search over the batch ranges, which are stored in ascending order
+fn find_range(env: &Env, token_id: u128) -> Option<MintRange> {
+  let ranges = read_ranges(env);
+  let mut low = 0;
+  let mut high = ranges.len();
+
+  while low < high {
+      let mid = (low + high) / 2;
+      let range = ranges.get_unchecked(mid);
+
+      if token_id < range.start {
+          high = mid;
+      } else if token_id > range.end {
+          low = mid + 1;
+      } else {
+          return Some(range);
+      }
+  }
+
+  None
+}
+
+// Appends the decimal token id to a base URI
+fn compose_uri(env: &Env, base_uri: &String, token_id: u128) -> String {
+  let mut digits = [0u8; 39];
+  let mut digit_count = 0;
+  let mut value = token_id;
+
+  loop {
+      digits[digit_count] = b'0' + (value % 10) as u8;
+      digit_count += 1;
+      value /= 10;
+      if value == 0 {
+          break;
+      }
+  }
+
+  digits[..digit_count].reverse();
+
+  join_uri(env, base_uri, &digits[..digit_count])
+}
+
+// Concatenates a base URI and a suffix into a single URI
+fn join_uri(env: &Env, base_uri: &String, suffix: &[u8]) -> String {
+  let base_len = base_uri.len() as usize;
+  let mut buffer = [0u8; MAX_URI_LEN];
+
+  if base_len + suffix.len() > MAX_URI_LEN {
+      panic!("NFT URI too long")
+  }
+
+  base_uri.copy_into_slice(&mut buffer[..base_len]);
+  buffer[base_len..base_len + suffix.len()].copy_from_slice(suffix);
+
+  String::from_slice(env, core::str::from_utf8(&buffer[..base_len + suffix.len()]).unwrap())
+}
+
+// Whether an account may hold tokens, always true unless the collection
+// is permissioned
+fn can_hold(env: &Env, account: &Address) -> bool {
+  !NFTContract::is_permissioned(env.clone()) || NFTContract::is_allowlisted(env.clone(), account.clone())
+}
+
+// Stores a newly minted token and records its creator
+fn mint_token(env: &Env, to: Address, token_uri: String) -> u128 {
+  if to == env.current_contract_address() {
+      panic!("Sender can not be contract address")
+  } else if token_uri == String::from_slice(env, "") {
+      panic!("NFT URI can not be empty")
+  } else if !can_hold(env, &to) {
+      panic!("Receiver not allowlisted")
+  }
+
+  let token_id = next_token_id(env);
+
+  let mint_event: MintEvent = MintEvent { address: to.clone(), token_id };
+  let nft_detail: NFTDetail = NFTDetail {
+      owner: to,
+      uri: token_uri,
+  };
+
+  env.storage().instance().set(&token_id, &nft_detail);
+  env.storage().persistent().set(&DataKey::Creator(token_id), &nft_detail.owner);
+  env.events().publish((MINT_EVENT, symbol_short!("mint")), mint_event);
+
+  token_id
+}
+
+// Rejects URIs over the policy's max length and, when check_scheme is set,
+// URIs without an allowed scheme. Suffixes only get the length check, the
+// scheme comes from the base URI.
+fn check_uri(env: &Env, uri: &String, check_scheme: bool) {
+  let policy = NFTContract::uri_policy(env.clone());
+  let len = uri.len() as usize;
+
+  if len > policy.max_len as usize {
+      panic_with_error!(env, UriError::TooLong)
+  } else if !check_scheme || policy.schemes.is_empty() {
+      return;
+  }
+
+  let mut bytes = [0u8; MAX_URI_LEN];
+  uri.copy_into_slice(&mut bytes[..len]);
+
+  for scheme in policy.schemes.iter() {
+      let scheme_len = scheme.len() as usize;
+      let mut prefix = [0u8; MAX_SCHEME_LEN];
+      scheme.copy_into_slice(&mut prefix[..scheme_len]);
+
+      if scheme_len <= len && bytes[..scheme_len] == prefix[..scheme_len] {
+          return;
+      }
+  }
+
+  panic_with_error!(env, UriError::SchemeNotAllowed)
+}
+
+// Hands a token from its owner to a new owner, dropping any approval
+fn move_token(env: &Env, from: &Address, to: &Address, token_id: u128, marketplace_sale: bool) {
+  TokenId::from_u128(token_id);
+
+  if NFTContract::transfers_paused(env.clone()) {
+      panic!("Transfers are paused")
+  }
+
+  if NFTContract::has_nft_owner(env.clone(), from.clone(), token_id) {
+      panic!("Invalid Sender")
+  } else if *from == env.current_contract_address() {
+      panic!("Sender can not be contract address")
+  } else if NFTContract::parent_of(env.clone(), token_id).is_some() {
+      panic!("Token is attached to a parent")
+  }
+
+  if NFTContract::locked_until(env.clone(), token_id) > env.ledger().timestamp() {
+      panic!("NFT is locked")
+  }
+
+  if !can_hold(env, from) {
+      panic!("Sender not allowlisted")
+  } else if !can_hold(env, to) {
+      panic!("Receiver not allowlisted")
+  }
+
+  let cooldown: Option<TransferCooldown> = env.storage().instance().get(&DataKey::Cooldown);
+  let exempt = marketplace_sale && cooldown.map_or(false, |cooldown| cooldown.marketplace_exempt);
+
+  if !exempt && NFTContract::next_transferable_at(env.clone(), token_id) > env.ledger().timestamp() {
+      panic!("NFT transfer cooldown active")
+  }
+
+  // Children move with their parent, so each must be free to move too. They
+  // share the parent's sender and receiver, already checked against the allowlist.
+  let children = NFTContract::children_of(env.clone(), token_id);
+
+  for child in children.iter() {
+      if NFTContract::locked_until(env.clone(), child) > env.ledger().timestamp() {
+          panic!("NFT is locked")
+      } else if !exempt && NFTContract::next_transferable_at(env.clone(), child) > env.ledger().timestamp() {
+          panic!("NFT transfer cooldown active")
+      }
+  }
+
+  let mut nft_detail = NFTContract::get_nft_detail(env.clone(), token_id);
+
+  if nft_detail.owner != *from || nft_detail.owner == env.current_contract_address() {
+      panic!("NFT not exist")
+  }
+
+  nft_detail.owner = to.clone();
+
+  env.storage().instance().set(&token_id, &nft_detail);
+  env.storage().instance().remove(&DataKey::Approval(token_id));
+  env.storage().persistent().set(&DataKey::LastTransfer(token_id), &env.ledger().timestamp());
+
+  // Attached children follow their parent
+  for child in children.iter() {
+      let mut child_detail = NFTContract::get_nft_detail(env.clone(), child);
+      child_detail.owner = to.clone();
+
+      env.storage().instance().set(&child, &child_detail);
+      env.storage().persistent().set(&DataKey::LastTransfer(child), &env.ledger().timestamp());
+  }
+}
+
+fn unlink_child(env: &Env, parent: u128, child: u128) {
+  let mut children = NFTContract::children_of(env.clone(), parent);
+
+  if let Some(index) = children.first_index_of(child) {
+      children.remove(index);
+  }
+
+  if children.is_empty() {
+      env.storage().persistent().remove(&DataKey::Children(parent));
+  } else {
+      env.storage().persistent().set(&DataKey::Children(parent), &children);
+  }
+  env.storage().persistent().remove(&DataKey::Parent(child));
+}
+
+// Burns a token by handing it to the contract and dropping its approval
+fn clear_token(env: &Env, token_id: u128) {
+  let nft_detail = NFTDetail {
+      owner: env.current_contract_address(),
+      uri: String::from_slice(env, ""),
+  };
+
+  env.storage().instance().set(&token_id, &nft_detail);
+  env.storage().instance().remove(&DataKey::Approval(token_id));
+  env.storage().instance().remove(&DataKey::Suffixed(token_id));
+  env.storage().persistent().remove(&DataKey::Claim(token_id));
+  env.storage().persistent().remove(&DataKey::MetadataHash(token_id));
+
+  // Burning a parent releases its children to the owner, burning a child
+  // detaches it
+  if let Some(parent) = NFTContract::parent_of(env.clone(), token_id) {
+      unlink_child(env, parent, token_id);
+  }
+  for child in NFTContract::children_of(env.clone(), token_id).iter() {
+      env.storage().persistent().remove(&DataKey::Parent(child));
+  }
+  env.storage().persistent().remove(&DataKey::Children(token_id));
+
+  // Burning the indexed token frees its content for a new mint
+  let content_key = DataKey::ContentHash(token_id);
+  if let Some(content_hash) = env.storage().persistent().get::<_, BytesN<32>>(&content_key) {
+      let token_key = DataKey::ContentToken(content_hash);
+      if env.storage().persistent().get::<_, u128>(&token_key) == Some(token_id) {
+          env.storage().persistent().remove(&token_key);
+      }
+      env.storage().persistent().remove(&content_key);
   }
 }
 
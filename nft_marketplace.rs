@@ -0,0 +1,26 @@
+use soroban_sdk::{contractclient, Address, Env};
+
+// Marketplace entry points of the NFT contract in nft.rs that
+// nft/nft_soroban.wasm, built before they were added, does not export.
+// Callers check is_registered first, so collections deployed from that
+// wasm keep selling through transfer_from.
+#[contractclient(name = "NFTMarketplaceClient")]
+pub trait NFTMarketplace {
+    fn is_marketplace(env: Env, marketplace: Address) -> bool;
+
+    fn record_royalty(env: Env, marketplace: Address, token_id: u128, creator: Address, payment_token: Address, amount: i128);
+
+    fn report_listing(env: Env, marketplace: Address, token_id: u128, price: Option<i128>);
+
+    fn report_offer(env: Env, marketplace: Address, token_id: u128, price: Option<i128>);
+
+    fn marketplace_transfer(env: Env, marketplace: Address, from: Address, to: Address, token_id: u128);
+
+    fn marketplace_lock(env: Env, marketplace: Address, token_id: u128, until: u64);
+}
+
+// Whether marketplace is registered with the collection. A collection that
+// doesn't export is_marketplace makes the call fail, which counts as no.
+pub fn is_registered(env: &Env, collection: &Address, marketplace: &Address) -> bool {
+    matches!(NFTMarketplaceClient::new(env, collection).try_is_marketplace(marketplace), Ok(Ok(true)))
+}
@@ -0,0 +1,53 @@
+use soroban_sdk::{contract, contractimpl, contracttype, Address, Env};
+
+// Stand-in for the NFT contract in marketplace tests, exposing the calls the
+// storefront and auction make. Like nft/nft_soroban.wasm it has no
+// is_marketplace, so sales go through transfer_from and the marketplace
+// hooks are not exercised.
+#[derive(Clone)]
+#[contracttype]
+enum MockKey {
+    Admin,
+    Count,
+    Owner(u128),
+}
+
+#[contract]
+pub struct MockNft;
+
+#[contractimpl]
+impl MockNft {
+    pub fn initialize(env: Env, admin: Address) {
+        env.storage().instance().set(&MockKey::Admin, &admin);
+    }
+
+    pub fn mint(env: Env, to: Address) -> u128 {
+        let token_id = env.storage().instance().get::<MockKey, u128>(&MockKey::Count).unwrap_or(0) + 1;
+
+        env.storage().instance().set(&MockKey::Count, &token_id);
+        env.storage().instance().set(&MockKey::Owner(token_id), &to);
+
+        token_id
+    }
+
+    pub fn owner_of(env: Env, token_id: u128) -> Address {
+        env.storage().instance().get(&MockKey::Owner(token_id)).expect("Token does not exist")
+    }
+
+    // Same inverted sense as the real contract: true when account is NOT the owner
+    pub fn has_nft_owner(env: Env, account: Address, token_id: u128) -> bool {
+        Self::owner_of(env, token_id) != account
+    }
+
+    pub fn transfer_from(env: Env, from: Address, to: Address, token_id: u128) {
+        if Self::owner_of(env.clone(), token_id) != from {
+            panic!("Invalid Sender")
+        }
+
+        env.storage().instance().set(&MockKey::Owner(token_id), &to);
+    }
+
+    pub fn read_administrator(env: Env) -> Address {
+        env.storage().instance().get(&MockKey::Admin).unwrap()
+    }
+}
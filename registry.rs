@@ -0,0 +1,106 @@
+#![no_std]
+use soroban_sdk::{contract, contractimpl, contracttype, Address, Env};
+
+mod nft_contract {
+    soroban_sdk::contractimport!(file = "nft/nft_soroban.wasm");
+}
+
+mod registry_interface;
+
+use registry_interface::{SaleItem, SaleItemSourceClient};
+
+#[derive(Clone)]
+#[contracttype]
+pub enum DataKey {
+    Admin,
+    Marketplace(Address),
+    Lock(Address, u128),
+}
+
+// Shared registry the storefront and auction contracts use so a token can
+// only be for sale in one of them at a time
+#[contract]
+pub struct ListingRegistry;
+
+#[contractimpl]
+impl ListingRegistry {
+    pub fn initialize(env: Env, admin: Address) {
+        if env.storage().instance().has(&DataKey::Admin) {
+            panic!("already initialized")
+        }
+
+        env.storage().instance().set(&DataKey::Admin, &admin);
+    }
+
+    pub fn set_marketplace(env: Env, marketplace: Address, allowed: bool) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+
+        if allowed {
+            env.storage().instance().set(&DataKey::Marketplace(marketplace), &true);
+        } else {
+            env.storage().instance().remove(&DataKey::Marketplace(marketplace));
+        }
+    }
+
+    pub fn lock(env: Env, marketplace: Address, collection: Address, token_id: u128) {
+        marketplace.require_auth();
+
+        if !env.storage().instance().has(&DataKey::Marketplace(marketplace.clone())) {
+            panic!("Marketplace not registered")
+        }
+
+        if let Some(holder) = Self::locked_by(env.clone(), collection.clone(), token_id) {
+            if holder != marketplace {
+                panic!("NFT listed in another marketplace")
+            }
+        }
+
+        env.storage()
+            .persistent()
+            .set(&DataKey::Lock(collection, token_id), &marketplace);
+    }
+
+    pub fn unlock(env: Env, marketplace: Address, collection: Address, token_id: u128) {
+        marketplace.require_auth();
+
+        if Self::locked_by(env.clone(), collection.clone(), token_id) == Some(marketplace) {
+            env.storage().persistent().remove(&DataKey::Lock(collection, token_id));
+        }
+    }
+
+    // Clears a lock the holding marketplace left behind, once it no longer
+    // has the token for sale or its seller no longer owns the token. Anyone
+    // can call it, so a stale lock can't keep the token off other marketplaces.
+    pub fn clear_stale_lock(env: Env, collection: Address, token_id: u128) {
+        let marketplace = Self::locked_by(env.clone(), collection.clone(), token_id).expect("NFT not locked");
+
+        let seller = match SaleItemSourceClient::new(&env, &marketplace).get_item(&token_id) {
+            SaleItem::None => None,
+            SaleItem::Listing(listing) => Some(listing.owner),
+            SaleItem::Auction(auction) => Some(auction.owner),
+        };
+
+        if let Some(seller) = seller {
+            let nft_client = nft_contract::Client::new(&env, &collection);
+
+            if nft_client.get_nft_detail(&token_id).owner == seller {
+                panic!("Lock still held for the token owner")
+            }
+        }
+
+        env.storage().persistent().remove(&DataKey::Lock(collection, token_id));
+    }
+
+    pub fn locked_by(env: Env, collection: Address, token_id: u128) -> Option<Address> {
+        env.storage().persistent().get(&DataKey::Lock(collection, token_id))
+    }
+
+    // Current sale state of a token, fetched from the marketplace holding it
+    pub fn get_item(env: Env, collection: Address, token_id: u128) -> SaleItem {
+        match Self::locked_by(env.clone(), collection, token_id) {
+            Some(marketplace) => SaleItemSourceClient::new(&env, &marketplace).get_item(&token_id),
+            None => SaleItem::None,
+        }
+    }
+}
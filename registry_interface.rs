@@ -0,0 +1,46 @@
+use soroban_sdk::{contractclient, contracttype, Address, Env};
+
+// Interface of the shared listing registry that keeps a token from being
+// listed in the storefront and auctioned at the same time
+#[contractclient(name = "ListingRegistryClient")]
+pub trait ListingRegistry {
+    fn lock(env: Env, marketplace: Address, collection: Address, token_id: u128);
+
+    fn unlock(env: Env, marketplace: Address, collection: Address, token_id: u128);
+}
+
+// Sale state of a token as returned by get_item, shared by the registry,
+// storefront and auction so a client needs a single call per token
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct FixedListing {
+    pub marketplace: Address,
+    pub owner: Address,
+    pub price: i128,
+}
+
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct AuctionItem {
+    pub marketplace: Address,
+    pub owner: Address,
+    pub start_price: i128,
+    pub expiration_date: u64,
+    pub highest_bid: i128,
+    pub bid_count: u32,
+}
+
+#[contracttype]
+#[derive(Clone, Debug)]
+pub enum SaleItem {
+    None,
+    Listing(FixedListing),
+    Auction(AuctionItem),
+}
+
+// Implemented by every registered marketplace so the registry can forward
+// get_item to whichever one holds the token
+#[contractclient(name = "SaleItemSourceClient")]
+pub trait SaleItemSource {
+    fn get_item(env: Env, token_id: u128) -> SaleItem;
+}
@@ -1,10 +1,25 @@
 use soroban_sdk::{
-  contract, contractimpl, contracttype, symbol_short, Symbol, Address, Env, String
+  contract, contractimpl, contracttype, symbol_short, Symbol, Address, BytesN, Env, String, Vec
 };
 
 const METADATA_KEY: Symbol = symbol_short!("METADATA");
 const MINT_EVENT: Symbol = symbol_short!("MINT");
 const COUNTER: Symbol = symbol_short!("COUNTER");
+const FIELD_EVENT: Symbol = symbol_short!("FIELD");
+const OFFER_EVENT: Symbol = symbol_short!("OFFER");
+const CONTROLLER_EVENT: Symbol = symbol_short!("CONTROL");
+const PRESENT_EVENT: Symbol = symbol_short!("PRESENT");
+const RENEWAL_EVENT: Symbol = symbol_short!("RENEWAL");
+const SCORE_EVENT: Symbol = symbol_short!("SCORE");
+const REVOKE_EVENT: Symbol = symbol_short!("REVOKE");
+const DID_EVENT: Symbol = symbol_short!("DID");
+const IMPORT_EVENT: Symbol = symbol_short!("IMPORT");
+const EXPIRY_EVENT: Symbol = symbol_short!("EXPIRY");
+
+const MAX_DID_LEN: u32 = 256;
+const MAX_PREREQUISITES: u32 = 8;
+const DAY_IN_SECONDS: u64 = 86400;
+const DEFAULT_EXPIRY_WARNING_DAYS: u64 = 30;
 
 #[derive(Clone)]
 #[contracttype]
@@ -17,6 +32,30 @@ pub struct SBTMetadata {
 #[contracttype]
 pub enum DataKey {
   Admin,
+  Field(u128, Symbol),
+  FieldNames(u128),
+  OfferCount,
+  Offer(u64),
+  Controller(Address),
+  PrivacyMode,
+  Verifier(Address),
+  Score(u128),
+  ScoredTokens(Address, Symbol),
+  Issuer(Address),
+  Issuance(u128),
+  IssuedBy(Address),
+  SchemaTokens(Symbol),
+  Revoked(u128),
+  Did(Address),
+  DidAccount(String),
+  Relayer(Address),
+  Provenance(u128),
+  Imported(Symbol, BytesN<32>),
+  Prerequisites(Symbol),
+  HolderSchema(Address, Symbol),
+  ExpiryWarningDays,
+  ExpiryWarned(u128),
+  HolderTokens(Address),
 }
 
 #[derive(Clone)]
@@ -33,13 +72,247 @@ pub struct MintEvent {
   pub token_id: u128
 }
 
+#[derive(Clone)]
+#[contracttype]
+pub struct SBTField {
+  pub uri: String,
+  pub public: bool,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct FieldEvent {
+  pub token_id: u128,
+  pub name: Symbol,
+  pub public: bool
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct SBTOffer {
+  pub to: Address,
+  pub uri: String,
+  pub expires_at: u64,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct OfferEvent {
+  pub offer_id: u64,
+  pub address: Address,
+  pub token_id: u128
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct ControllerEvent {
+  pub account: Address,
+  pub controller: Option<Address>
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct DidEvent {
+  pub account: Address,
+  pub did: String
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct PresentEvent {
+  pub token_id: u128,
+  pub holder: Address,
+  pub verifier: Address
+}
+
+// Numeric value a credential contributes to its schema, counted until
+// expires_at (0 never expires)
+#[derive(Clone)]
+#[contracttype]
+pub struct SBTScore {
+  pub schema_id: Symbol,
+  pub value: i128,
+  pub expires_at: u64,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct ScoreEvent {
+  pub token_id: u128,
+  pub schema_id: Symbol,
+  pub value: i128
+}
+
+// Issuer and schema of a credential issued through issue_sbt
+#[derive(Clone)]
+#[contracttype]
+pub struct SBTIssuance {
+  pub issuer: Address,
+  pub schema_id: Symbol,
+}
+
+// Aggregated over one batch, schema_id is None for revocations by issuer
+#[derive(Clone)]
+#[contracttype]
+pub struct RevokeEvent {
+  pub issuer: Address,
+  pub schema_id: Option<Symbol>,
+  pub revoked: u32
+}
+
+// Where a credential mirrored from another chain was originally issued,
+// origin_ref identifies it on the origin chain and proof_hash commits to
+// the attestation proof the relayer checked
+#[derive(Clone)]
+#[contracttype]
+pub struct SBTProvenance {
+  pub origin_chain: Symbol,
+  pub origin_ref: BytesN<32>,
+  pub proof_hash: BytesN<32>,
+  pub relayer: Address,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct ImportEvent {
+  pub token_id: u128,
+  pub holder: Address,
+  pub origin_chain: Symbol,
+  pub origin_ref: BytesN<32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[contracttype]
+pub enum CredentialStatus {
+  Active,
+  Expired,
+  Revoked,
+}
+
+// One issued credential of a holder, expiry is the score expiry (0 never
+// expires)
+#[derive(Clone)]
+#[contracttype]
+pub struct CredentialSummary {
+  pub token_id: u128,
+  pub schema_id: Symbol,
+  pub issuer: Address,
+  pub status: CredentialStatus,
+  pub expiry: u64
+}
+
+// Emitted once per expiry date when a scored credential is within the
+// warning window, so holders can be prompted to renew
+#[derive(Clone)]
+#[contracttype]
+pub struct CredentialExpiringEvent {
+  pub token_id: u128,
+  pub holder: Address,
+  pub schema_id: Symbol,
+  pub expires_at: u64
+}
+
 pub trait SBTTrait {
   fn initialize(env: Env, admin: Address, name: String, symbol: String);
 
   fn mint_sbt(env: Env, to: Address, token_uri: String) -> u128;
 
+  fn offer_sbt(env: Env, to: Address, token_uri: String, expires_at: u64) -> u64;
+
+  fn claim_sbt(env: Env, holder: Address, offer_id: u64) -> u128;
+
+  fn decline_sbt(env: Env, holder: Address, offer_id: u64);
+
+  fn get_offer(env: Env, offer_id: u64) -> SBTOffer;
+
+  fn set_controller(env: Env, account: Address, controller: Option<Address>);
+
+  fn get_controller(env: Env, account: Address) -> Option<Address>;
+
+  fn present_sbt(env: Env, token_id: u128, verifier: Address);
+
+  fn bind_did(env: Env, account: Address, did: String, token_id: u128, attester: Address);
+
+  fn unbind_did(env: Env, account: Address);
+
+  fn resolve_did(env: Env, account: Address) -> Option<String>;
+
+  fn account_for_did(env: Env, did: String) -> Option<Address>;
+
+  fn request_renewal(env: Env, token_id: u128);
+
+  fn set_expiry_warning_days(env: Env, days: u64);
+
+  fn expiry_warning_days(env: Env) -> u64;
+
+  fn scan_expiring(env: Env, start: u128, limit: u32) -> u32;
+
+  fn set_issuer(env: Env, issuer: Address, allowed: bool);
+
+  fn is_issuer(env: Env, issuer: Address) -> bool;
+
+  fn issue_sbt(env: Env, issuer: Address, to: Address, token_uri: String, schema_id: Symbol) -> u128;
+
+  fn get_issuance(env: Env, token_id: u128) -> Option<SBTIssuance>;
+
+  fn set_relayer(env: Env, relayer: Address, allowed: bool);
+
+  fn is_relayer(env: Env, relayer: Address) -> bool;
+
+  #[allow(clippy::too_many_arguments)]
+  fn import_attestation(
+      env: Env,
+      relayer: Address,
+      origin_chain: Symbol,
+      origin_ref: BytesN<32>,
+      holder: Address,
+      schema_id: Symbol,
+      token_uri: String,
+      proof_hash: BytesN<32>,
+  ) -> u128;
+
+  fn get_provenance(env: Env, token_id: u128) -> Option<SBTProvenance>;
+
+  fn set_prerequisites(env: Env, schema_id: Symbol, prerequisites: Vec<Symbol>);
+
+  fn prerequisites(env: Env, schema_id: Symbol) -> Vec<Symbol>;
+
+  fn has_valid_credential(env: Env, holder: Address, schema_id: Symbol) -> bool;
+
+  fn credential_summary(env: Env, account: Address) -> Vec<CredentialSummary>;
+
+  fn revoke_by_schema(env: Env, issuer: Address, schema_id: Symbol, start: u32, limit: u32) -> u32;
+
+  fn revoke_by_issuer(env: Env, admin: Address, issuer: Address, start: u32, limit: u32) -> u32;
+
+  fn is_revoked(env: Env, token_id: u128) -> bool;
+
   fn get_sbt_detail(env: Env, token_id: u128) -> SBTDetail;
 
+  fn get_sbt_detail_for(env: Env, reader: Address, token_id: u128) -> SBTDetail;
+
+  fn set_privacy_mode(env: Env, enabled: bool);
+
+  fn privacy_mode(env: Env) -> bool;
+
+  fn set_verifier(env: Env, verifier: Address, allowed: bool);
+
+  fn is_verifier(env: Env, verifier: Address) -> bool;
+
+  fn set_field(env: Env, token_id: u128, name: Symbol, uri: String, public: bool);
+
+  fn set_field_visibility(env: Env, owner: Address, token_id: u128, name: Symbol, public: bool);
+
+  fn get_field(env: Env, token_id: u128, name: Symbol) -> String;
+
+  fn field_names(env: Env, token_id: u128) -> Vec<Symbol>;
+
+  fn set_score(env: Env, token_id: u128, schema_id: Symbol, value: i128, expires_at: u64);
+
+  fn token_score(env: Env, token_id: u128) -> Option<SBTScore>;
+
+  fn get_score(env: Env, account: Address, schema_id: Symbol) -> i128;
+
   fn read_administrator(env: Env) -> Address;
 
   fn has_administrator(env: Env) -> bool;
@@ -70,40 +343,625 @@ impl SBTTrait for SBTContract {
   fn mint_sbt(env: Env, to: Address, token_uri: String) -> u128 {
       to.require_auth();
 
+      mint(&env, to, token_uri)
+  }
+
+  fn offer_sbt(env: Env, to: Address, token_uri: String, expires_at: u64) -> u64 {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
+
       if to == env.current_contract_address() {
-          panic!("Sender can not be contract address")
+          panic!("Receiver can not be contract address")
       } else if token_uri == String::from_slice(&env, "") {
           panic!("SBT URI can not be empty")
+      } else if expires_at <= env.ledger().timestamp() {
+          panic!("Offer must expire in the future")
+      }
+
+      let offer_id: u64 = env.storage().instance().get(&DataKey::OfferCount).unwrap_or(0) + 1;
+      let offer_event: OfferEvent = OfferEvent { offer_id, address: to.clone(), token_id: 0 };
+      let offer: SBTOffer = SBTOffer { to, uri: token_uri, expires_at };
+
+      env.storage().persistent().set(&DataKey::Offer(offer_id), &offer);
+      env.storage().instance().set(&DataKey::OfferCount, &offer_id);
+      env.events().publish((OFFER_EVENT, symbol_short!("offer")), offer_event);
+
+      offer_id
+  }
+
+  fn claim_sbt(env: Env, holder: Address, offer_id: u64) -> u128 {
+      require_holder_auth(&env, &holder);
+
+      let offer = Self::get_offer(env.clone(), offer_id);
+
+      if offer.to != holder {
+          panic!("Offer not addressed to holder")
+      } else if env.ledger().timestamp() > offer.expires_at {
+          panic!("Offer expired")
+      }
+
+      env.storage().persistent().remove(&DataKey::Offer(offer_id));
+
+      let token_id = mint(&env, holder.clone(), offer.uri);
+      let offer_event: OfferEvent = OfferEvent { offer_id, address: holder, token_id };
+
+      env.events().publish((OFFER_EVENT, symbol_short!("claim")), offer_event);
+
+      token_id
+  }
+
+  fn decline_sbt(env: Env, holder: Address, offer_id: u64) {
+      require_holder_auth(&env, &holder);
+
+      let offer = Self::get_offer(env.clone(), offer_id);
+
+      if offer.to != holder {
+          panic!("Offer not addressed to holder")
+      }
+
+      let offer_event: OfferEvent = OfferEvent { offer_id, address: holder, token_id: 0 };
+
+      env.storage().persistent().remove(&DataKey::Offer(offer_id));
+      env.events().publish((OFFER_EVENT, symbol_short!("decline")), offer_event);
+  }
+
+  fn get_offer(env: Env, offer_id: u64) -> SBTOffer {
+      env.storage()
+          .persistent()
+          .get(&DataKey::Offer(offer_id))
+          .expect("Offer not exist")
+  }
+
+  fn set_issuer(env: Env, issuer: Address, allowed: bool) {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
+
+      if allowed {
+          env.storage().persistent().set(&DataKey::Issuer(issuer), &true);
+      } else {
+          env.storage().persistent().remove(&DataKey::Issuer(issuer));
+      }
+  }
+
+  fn is_issuer(env: Env, issuer: Address) -> bool {
+      env.storage().persistent().has(&DataKey::Issuer(issuer))
+  }
+
+  // Mints a credential under a schema on behalf of a registered issuer, with
+  // the holder's consent, indexed so it can be revoked in bulk later
+  fn issue_sbt(env: Env, issuer: Address, to: Address, token_uri: String, schema_id: Symbol) -> u128 {
+      issuer.require_auth();
+      require_holder_auth(&env, &to);
+
+      if !Self::is_issuer(env.clone(), issuer.clone()) {
+          panic!("Issuer not registered")
+      }
+
+      check_prerequisites(&env, &to, &schema_id);
+
+      let token_id = mint(&env, to, token_uri);
+      record_issuance(&env, token_id, issuer, schema_id);
+
+      token_id
+  }
+
+  fn get_issuance(env: Env, token_id: u128) -> Option<SBTIssuance> {
+      env.storage().persistent().get(&DataKey::Issuance(token_id))
+  }
+
+  fn set_relayer(env: Env, relayer: Address, allowed: bool) {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
+
+      if allowed {
+          env.storage().persistent().set(&DataKey::Relayer(relayer), &true);
+      } else {
+          env.storage().persistent().remove(&DataKey::Relayer(relayer));
+      }
+  }
+
+  fn is_relayer(env: Env, relayer: Address) -> bool {
+      env.storage().persistent().has(&DataKey::Relayer(relayer))
+  }
+
+  // Mirrors a credential issued on another chain. The relayer stands in as
+  // the issuer, so its imports can be revoked in bulk with revoke_by_issuer,
+  // and each origin reference can only be imported once
+  fn import_attestation(
+      env: Env,
+      relayer: Address,
+      origin_chain: Symbol,
+      origin_ref: BytesN<32>,
+      holder: Address,
+      schema_id: Symbol,
+      token_uri: String,
+      proof_hash: BytesN<32>,
+  ) -> u128 {
+      relayer.require_auth();
+      require_holder_auth(&env, &holder);
+
+      if relayer != Self::read_administrator(env.clone()) && !Self::is_relayer(env.clone(), relayer.clone()) {
+          panic!("Relayer not registered")
+      }
+
+      let imported_key = DataKey::Imported(origin_chain.clone(), origin_ref.clone());
+
+      if env.storage().persistent().has(&imported_key) {
+          panic!("Attestation already imported")
       }
 
-      let mut token_id: u128 = env.storage().instance().get(&COUNTER).unwrap_or(0);
+      check_prerequisites(&env, &holder, &schema_id);
 
-      token_id += 1;
+      let token_id = mint(&env, holder.clone(), token_uri);
+      record_issuance(&env, token_id, relayer.clone(), schema_id);
 
-      let mint_event: MintEvent = MintEvent { address: to.clone(), token_id };
-      let sbt_detail: SBTDetail = SBTDetail {
-          owner: to,
-          uri: token_uri,
+      let provenance: SBTProvenance = SBTProvenance {
+          origin_chain: origin_chain.clone(),
+          origin_ref: origin_ref.clone(),
+          proof_hash,
+          relayer,
       };
 
-      env.storage().instance().set(&token_id, &sbt_detail);
-      env.storage().instance().set(&COUNTER, &token_id);
-      env.events().publish((MINT_EVENT, symbol_short!("mint")), mint_event);
-      
+      env.storage().persistent().set(&DataKey::Provenance(token_id), &provenance);
+      env.storage().persistent().set(&imported_key, &token_id);
+
+      let import_event: ImportEvent = ImportEvent { token_id, holder, origin_chain, origin_ref };
+      env.events().publish((IMPORT_EVENT, symbol_short!("imported")), import_event);
+
       token_id
   }
 
+  fn get_provenance(env: Env, token_id: u128) -> Option<SBTProvenance> {
+      env.storage().persistent().get(&DataKey::Provenance(token_id))
+  }
+
+  // Credentials of a schema can only be issued to holders that already hold
+  // a valid credential of every prerequisite schema
+  fn set_prerequisites(env: Env, schema_id: Symbol, prerequisites: Vec<Symbol>) {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
+
+      if prerequisites.len() > MAX_PREREQUISITES {
+          panic!("Too many prerequisites")
+      } else if prerequisites.contains(&schema_id) {
+          panic!("Schema can not require itself")
+      }
+
+      if prerequisites.is_empty() {
+          env.storage().persistent().remove(&DataKey::Prerequisites(schema_id));
+      } else {
+          env.storage().persistent().set(&DataKey::Prerequisites(schema_id), &prerequisites);
+      }
+  }
+
+  fn prerequisites(env: Env, schema_id: Symbol) -> Vec<Symbol> {
+      env.storage()
+          .persistent()
+          .get(&DataKey::Prerequisites(schema_id))
+          .unwrap_or(Vec::new(&env))
+  }
+
+  // Whether the holder owns an unrevoked credential issued under the schema
+  fn has_valid_credential(env: Env, holder: Address, schema_id: Symbol) -> bool {
+      let tokens: Vec<u128> = env
+          .storage()
+          .persistent()
+          .get(&DataKey::HolderSchema(holder.clone(), schema_id))
+          .unwrap_or(Vec::new(&env));
+
+      let now = env.ledger().timestamp();
+
+      tokens.iter().any(|token_id| {
+          let expiry = score_expiry(&env, token_id);
+
+          !Self::is_revoked(env.clone(), token_id)
+              && (expiry == 0 || expiry > now)
+              && read_sbt(&env, token_id).owner == holder
+      })
+  }
+
+  // Every credential issued to the account through issue_sbt or an import,
+  // for a compliance check in a single call
+  fn credential_summary(env: Env, account: Address) -> Vec<CredentialSummary> {
+      let tokens: Vec<u128> = env
+          .storage()
+          .persistent()
+          .get(&DataKey::HolderTokens(account.clone()))
+          .unwrap_or(Vec::new(&env));
+      let now = env.ledger().timestamp();
+      let mut summary = Vec::new(&env);
+
+      for token_id in tokens.iter() {
+          if read_sbt(&env, token_id).owner != account {
+              continue;
+          }
+
+          let issuance: SBTIssuance = match Self::get_issuance(env.clone(), token_id) {
+              Some(issuance) => issuance,
+              None => continue,
+          };
+          let expiry = score_expiry(&env, token_id);
+
+          let status = if Self::is_revoked(env.clone(), token_id) {
+              CredentialStatus::Revoked
+          } else if expiry != 0 && expiry <= now {
+              CredentialStatus::Expired
+          } else {
+              CredentialStatus::Active
+          };
+
+          summary.push_back(CredentialSummary {
+              token_id,
+              schema_id: issuance.schema_id,
+              issuer: issuance.issuer,
+              status,
+              expiry
+          });
+      }
+
+      summary
+  }
+
+  // Revokes the issuer's credentials among schema tokens start..start + limit
+  fn revoke_by_schema(env: Env, issuer: Address, schema_id: Symbol, start: u32, limit: u32) -> u32 {
+      issuer.require_auth();
+
+      let tokens: Vec<u128> = env
+          .storage()
+          .persistent()
+          .get(&DataKey::SchemaTokens(schema_id.clone()))
+          .unwrap_or(Vec::new(&env));
+      let revoked = revoke_batch(&env, &tokens, Some(issuer.clone()), start, limit);
+
+      let revoke_event: RevokeEvent = RevokeEvent { issuer, schema_id: Some(schema_id), revoked };
+      env.events().publish((REVOKE_EVENT, symbol_short!("schema")), revoke_event);
+
+      revoked
+  }
+
+  // Incident response for a compromised issuer key, revokes issued tokens
+  // start..start + limit of the issuer
+  fn revoke_by_issuer(env: Env, admin: Address, issuer: Address, start: u32, limit: u32) -> u32 {
+      admin.require_auth();
+
+      if admin != Self::read_administrator(env.clone()) {
+          panic!("Only admin can perform this action")
+      }
+
+      let tokens: Vec<u128> = env
+          .storage()
+          .persistent()
+          .get(&DataKey::IssuedBy(issuer.clone()))
+          .unwrap_or(Vec::new(&env));
+      let revoked = revoke_batch(&env, &tokens, None, start, limit);
+
+      let revoke_event: RevokeEvent = RevokeEvent { issuer, schema_id: None, revoked };
+      env.events().publish((REVOKE_EVENT, symbol_short!("issuer")), revoke_event);
+
+      revoked
+  }
+
+  fn is_revoked(env: Env, token_id: u128) -> bool {
+      env.storage().persistent().has(&DataKey::Revoked(token_id))
+  }
+
   fn get_sbt_detail(env: Env, token_id: u128) -> SBTDetail {
-      let detail: SBTDetail = env
+      if Self::privacy_mode(env.clone()) {
+          panic!("Privacy mode requires an authorized reader")
+      }
+
+      read_sbt(&env, token_id)
+  }
+
+  fn get_sbt_detail_for(env: Env, reader: Address, token_id: u128) -> SBTDetail {
+      let detail = read_sbt(&env, token_id);
+
+      if Self::privacy_mode(env.clone()) {
+          reader.require_auth();
+
+          let issuer = Self::get_issuance(env.clone(), token_id).map(|issuance| issuance.issuer);
+
+          // Holder, issuer or an allowlisted verifier, the admin included
+          // only when it is one of those
+          if reader != detail.owner
+              && Some(reader.clone()) != issuer
+              && !Self::is_verifier(env.clone(), reader)
+          {
+              panic!("Reader not allowed")
+          }
+      }
+
+      detail
+  }
+
+  fn set_privacy_mode(env: Env, enabled: bool) {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
+
+      env.storage().instance().set(&DataKey::PrivacyMode, &enabled);
+  }
+
+  fn privacy_mode(env: Env) -> bool {
+      env.storage().instance().get(&DataKey::PrivacyMode).unwrap_or(false)
+  }
+
+  fn set_verifier(env: Env, verifier: Address, allowed: bool) {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
+
+      if allowed {
+          env.storage().persistent().set(&DataKey::Verifier(verifier), &true);
+      } else {
+          env.storage().persistent().remove(&DataKey::Verifier(verifier));
+      }
+  }
+
+  fn is_verifier(env: Env, verifier: Address) -> bool {
+      env.storage().persistent().has(&DataKey::Verifier(verifier))
+  }
+
+  fn set_field(env: Env, token_id: u128, name: Symbol, uri: String, public: bool) {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
+
+      let sbt_detail = read_sbt(&env, token_id);
+
+      if sbt_detail.owner == env.current_contract_address() {
+          panic!("SBT not exist")
+      } else if uri == String::from_slice(&env, "") {
+          panic!("Field URI can not be empty")
+      }
+
+      let key = DataKey::Field(token_id, name.clone());
+
+      if !env.storage().persistent().has(&key) {
+          let mut names = Self::field_names(env.clone(), token_id);
+          names.push_back(name.clone());
+          env.storage().persistent().set(&DataKey::FieldNames(token_id), &names);
+      }
+
+      let field_event: FieldEvent = FieldEvent { token_id, name, public };
+
+      env.storage().persistent().set(&key, &SBTField { uri, public });
+      env.events().publish((FIELD_EVENT, symbol_short!("set")), field_event);
+  }
+
+  fn set_field_visibility(env: Env, owner: Address, token_id: u128, name: Symbol, public: bool) {
+      require_holder_auth(&env, &owner);
+
+      if Self::has_sbt_owner(env.clone(), owner, token_id) {
+          panic!("Invalid Sender")
+      }
+
+      let key = DataKey::Field(token_id, name.clone());
+      let mut field: SBTField = env.storage().persistent().get(&key).expect("Field not exist");
+
+      field.public = public;
+
+      let field_event: FieldEvent = FieldEvent { token_id, name, public };
+
+      env.storage().persistent().set(&key, &field);
+      env.events().publish((FIELD_EVENT, symbol_short!("visible")), field_event);
+  }
+
+  fn get_field(env: Env, token_id: u128, name: Symbol) -> String {
+      let field: SBTField = env
           .storage()
+          .persistent()
+          .get(&DataKey::Field(token_id, name))
+          .expect("Field not exist");
+
+      if !field.public {
+          panic!("Field not disclosed")
+      }
+
+      field.uri
+  }
+
+  fn field_names(env: Env, token_id: u128) -> Vec<Symbol> {
+      env.storage()
+          .persistent()
+          .get(&DataKey::FieldNames(token_id))
+          .unwrap_or(Vec::new(&env))
+  }
+
+  fn set_score(env: Env, token_id: u128, schema_id: Symbol, value: i128, expires_at: u64) {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
+
+      let sbt_detail = read_sbt(&env, token_id);
+
+      if sbt_detail.owner == env.current_contract_address() {
+          panic!("SBT not exist")
+      }
+
+      // Keep the per-holder index in sync when a token moves to another schema
+      if let Some(previous) = Self::token_score(env.clone(), token_id) {
+          if previous.schema_id != schema_id {
+              let key = DataKey::ScoredTokens(sbt_detail.owner.clone(), previous.schema_id);
+              let mut tokens: Vec<u128> = env.storage().persistent().get(&key).unwrap_or(Vec::new(&env));
+              if let Some(index) = tokens.first_index_of(token_id) {
+                  tokens.remove(index);
+              }
+              env.storage().persistent().set(&key, &tokens);
+          }
+      }
+
+      let key = DataKey::ScoredTokens(sbt_detail.owner, schema_id.clone());
+      let mut tokens: Vec<u128> = env.storage().persistent().get(&key).unwrap_or(Vec::new(&env));
+      if !tokens.contains(token_id) {
+          tokens.push_back(token_id);
+          env.storage().persistent().set(&key, &tokens);
+      }
+
+      let score_event: ScoreEvent = ScoreEvent { token_id, schema_id: schema_id.clone(), value };
+      let score: SBTScore = SBTScore { schema_id, value, expires_at };
+
+      env.storage().persistent().set(&DataKey::Score(token_id), &score);
+      env.events().publish((SCORE_EVENT, symbol_short!("set")), score_event);
+  }
+
+  fn token_score(env: Env, token_id: u128) -> Option<SBTScore> {
+      env.storage().persistent().get(&DataKey::Score(token_id))
+  }
+
+  // Sums the scores of the account's unexpired credentials in the schema so
+  // other contracts can make threshold decisions on-chain
+  fn get_score(env: Env, account: Address, schema_id: Symbol) -> i128 {
+      let tokens: Vec<u128> = env
+          .storage()
+          .persistent()
+          .get(&DataKey::ScoredTokens(account.clone(), schema_id))
+          .unwrap_or(Vec::new(&env));
+      let now = env.ledger().timestamp();
+      let mut total: i128 = 0;
+
+      for token_id in tokens.iter() {
+          if read_sbt(&env, token_id).owner != account || Self::is_revoked(env.clone(), token_id) {
+              continue;
+          }
+
+          if let Some(score) = Self::token_score(env.clone(), token_id) {
+              if score.expires_at == 0 || score.expires_at > now {
+                  total += score.value;
+              }
+          }
+      }
+
+      total
+  }
+
+  fn set_controller(env: Env, account: Address, controller: Option<Address>) {
+      // The admin registers the first controller with the account's consent,
+      // later changes are made by the controller
+      match Self::get_controller(env.clone(), account.clone()) {
+          Some(current) => current.require_auth(),
+          None => {
+              account.require_auth();
+              Self::read_administrator(env.clone()).require_auth();
+          }
+      }
+
+      let key = DataKey::Controller(account.clone());
+      match controller.clone() {
+          Some(controller) => env.storage().persistent().set(&key, &controller),
+          None => env.storage().persistent().remove(&key),
+      }
+
+      let controller_event: ControllerEvent = ControllerEvent { account, controller };
+
+      env.events().publish((CONTROLLER_EVENT, symbol_short!("changed")), controller_event);
+  }
+
+  fn get_controller(env: Env, account: Address) -> Option<Address> {
+      env.storage().persistent().get(&DataKey::Controller(account))
+  }
+
+  fn present_sbt(env: Env, token_id: u128, verifier: Address) {
+      let sbt_detail = read_sbt(&env, token_id);
+
+      if sbt_detail.owner == env.current_contract_address() {
+          panic!("SBT not exist")
+      }
+
+      require_holder_auth(&env, &sbt_detail.owner);
+
+      let present_event: PresentEvent = PresentEvent { token_id, holder: sbt_detail.owner, verifier };
+
+      env.events().publish((PRESENT_EVENT, symbol_short!("present")), present_event);
+  }
+
+  // Links the account to one decentralized identifier, each DID can be bound
+  // to a single account. The account must hold an unrevoked SBT, token_id,
+  // and the admin or an issuer attests that the DID is the account's.
+  fn bind_did(env: Env, account: Address, did: String, token_id: u128, attester: Address) {
+      require_holder_auth(&env, &account);
+      attester.require_auth();
+
+      if did.len() == 0 || did.len() > MAX_DID_LEN {
+          panic!("Invalid DID")
+      } else if Self::account_for_did(env.clone(), did.clone()).is_some() {
+          panic!("DID already bound")
+      } else if read_sbt(&env, token_id).owner != account || Self::is_revoked(env.clone(), token_id) {
+          panic!("Account holds no SBT")
+      } else if attester != Self::read_administrator(env.clone()) && !Self::is_issuer(env.clone(), attester) {
+          panic!("Attester not authorized")
+      }
+
+      if let Some(previous) = Self::resolve_did(env.clone(), account.clone()) {
+          env.storage().persistent().remove(&DataKey::DidAccount(previous));
+      }
+
+      env.storage().persistent().set(&DataKey::Did(account.clone()), &did);
+      env.storage().persistent().set(&DataKey::DidAccount(did.clone()), &account);
+
+      let did_event: DidEvent = DidEvent { account, did };
+      env.events().publish((DID_EVENT, symbol_short!("bind")), did_event);
+  }
+
+  fn unbind_did(env: Env, account: Address) {
+      require_holder_auth(&env, &account);
+
+      let did = Self::resolve_did(env.clone(), account.clone()).expect("DID not bound");
+
+      env.storage().persistent().remove(&DataKey::Did(account.clone()));
+      env.storage().persistent().remove(&DataKey::DidAccount(did.clone()));
+
+      let did_event: DidEvent = DidEvent { account, did };
+      env.events().publish((DID_EVENT, symbol_short!("unbind")), did_event);
+  }
+
+  fn resolve_did(env: Env, account: Address) -> Option<String> {
+      env.storage().persistent().get(&DataKey::Did(account))
+  }
+
+  fn account_for_did(env: Env, did: String) -> Option<Address> {
+      env.storage().persistent().get(&DataKey::DidAccount(did))
+  }
+
+  fn request_renewal(env: Env, token_id: u128) {
+      let sbt_detail = read_sbt(&env, token_id);
+
+      if sbt_detail.owner == env.current_contract_address() {
+          panic!("SBT not exist")
+      }
+
+      require_holder_auth(&env, &sbt_detail.owner);
+
+      let renewal_event: MintEvent = MintEvent { address: sbt_detail.owner, token_id };
+
+      env.events().publish((RENEWAL_EVENT, symbol_short!("request")), renewal_event);
+  }
+
+  fn set_expiry_warning_days(env: Env, days: u64) {
+      let admin = Self::read_administrator(env.clone());
+      admin.require_auth();
+
+      env.storage().instance().set(&DataKey::ExpiryWarningDays, &days);
+  }
+
+  fn expiry_warning_days(env: Env) -> u64 {
+      env.storage()
           .instance()
-          .get(&token_id)
-          .unwrap_or(SBTDetail {
-              owner: env.current_contract_address(),
-              uri: String::from_slice(&env, ""),
-          });
+          .get(&DataKey::ExpiryWarningDays)
+          .unwrap_or(DEFAULT_EXPIRY_WARNING_DAYS)
+  }
+
+  // Permissionless sweep over token ids start..start + limit, returns how
+  // many expiry warnings it emitted. The only place warnings are emitted, so
+  // reads of credentials and scores stay free of storage writes.
+  fn scan_expiring(env: Env, start: u128, limit: u32) -> u32 {
+      let counter: u128 = env.storage().instance().get(&COUNTER).unwrap_or(0);
+      let end = start.saturating_add(limit as u128).min(counter + 1);
+      let mut warned: u32 = 0;
 
-      return detail;
+      for token_id in start.max(1)..end {
+          if warn_if_expiring(&env, token_id) {
+              warned += 1;
+          }
+      }
+
+      warned
   }
 
   fn read_administrator(env: Env) -> Address {
@@ -116,7 +974,7 @@ impl SBTTrait for SBTContract {
   }
 
   fn has_sbt_owner(env: Env, account: Address, token_id: u128) -> bool {
-      let sbt_detail = Self::get_sbt_detail(env.clone(), token_id.clone());
+      let sbt_detail = read_sbt(&env, token_id);
 
       if sbt_detail.owner != account {
           return true;
@@ -138,6 +996,174 @@ impl SBTTrait for SBTContract {
   }
 }
 
+// Reads a token without the privacy checks applied to external readers
+fn read_sbt(env: &Env, token_id: u128) -> SBTDetail {
+  env.storage()
+      .instance()
+      .get(&token_id)
+      .unwrap_or(SBTDetail {
+          owner: env.current_contract_address(),
+          uri: String::from_slice(env, ""),
+      })
+}
+
+// Revokes tokens start..start + limit of the list not revoked yet, only
+// those of issuer when given, and returns how many were revoked
+fn revoke_batch(env: &Env, tokens: &Vec<u128>, issuer: Option<Address>, start: u32, limit: u32) -> u32 {
+  let mut revoked: u32 = 0;
+
+  for token_id in tokens.iter().skip(start as usize).take(limit as usize) {
+      let key = DataKey::Revoked(token_id);
+
+      if env.storage().persistent().has(&key) {
+          continue;
+      }
+
+      if let Some(issuer) = issuer.clone() {
+          let issuance: Option<SBTIssuance> = env.storage().persistent().get(&DataKey::Issuance(token_id));
+
+          if issuance.map(|issuance| issuance.issuer) != Some(issuer) {
+              continue;
+          }
+      }
+
+      env.storage().persistent().set(&key, &true);
+      revoked += 1;
+  }
+
+  revoked
+}
+
+// When the token's score expires, 0 if it has none or it never expires
+fn score_expiry(env: &Env, token_id: u128) -> u64 {
+  env.storage()
+      .persistent()
+      .get::<_, SBTScore>(&DataKey::Score(token_id))
+      .map_or(0, |score| score.expires_at)
+}
+
+// Indexes a credential under its issuer and schema for bulk revocation
+fn record_issuance(env: &Env, token_id: u128, issuer: Address, schema_id: Symbol) {
+  let mut issued: Vec<u128> = env
+      .storage()
+      .persistent()
+      .get(&DataKey::IssuedBy(issuer.clone()))
+      .unwrap_or(Vec::new(env));
+  issued.push_back(token_id);
+
+  let mut schema_tokens: Vec<u128> = env
+      .storage()
+      .persistent()
+      .get(&DataKey::SchemaTokens(schema_id.clone()))
+      .unwrap_or(Vec::new(env));
+  schema_tokens.push_back(token_id);
+
+  let owner = read_sbt(env, token_id).owner;
+  let mut holder_tokens: Vec<u128> = env
+      .storage()
+      .persistent()
+      .get(&DataKey::HolderSchema(owner.clone(), schema_id.clone()))
+      .unwrap_or(Vec::new(env));
+  holder_tokens.push_back(token_id);
+
+  let mut all_holder_tokens: Vec<u128> = env
+      .storage()
+      .persistent()
+      .get(&DataKey::HolderTokens(owner.clone()))
+      .unwrap_or(Vec::new(env));
+  all_holder_tokens.push_back(token_id);
+
+  let issuance: SBTIssuance = SBTIssuance { issuer: issuer.clone(), schema_id: schema_id.clone() };
+
+  env.storage().persistent().set(&DataKey::Issuance(token_id), &issuance);
+  env.storage().persistent().set(&DataKey::HolderSchema(owner.clone(), schema_id.clone()), &holder_tokens);
+  env.storage().persistent().set(&DataKey::HolderTokens(owner), &all_holder_tokens);
+  env.storage().persistent().set(&DataKey::IssuedBy(issuer), &issued);
+  env.storage().persistent().set(&DataKey::SchemaTokens(schema_id), &schema_tokens);
+}
+
+fn check_prerequisites(env: &Env, holder: &Address, schema_id: &Symbol) {
+  for prerequisite in SBTContract::prerequisites(env.clone(), schema_id.clone()).iter() {
+      if !SBTContract::has_valid_credential(env.clone(), holder.clone(), prerequisite) {
+          panic!("Missing prerequisite credential")
+      }
+  }
+}
+
+// Requires the controller's auth for accounts managed by a controller, such
+// as organization contracts, and the holder's own auth otherwise
+fn require_holder_auth(env: &Env, holder: &Address) {
+  let controller: Option<Address> = env.storage().persistent().get(&DataKey::Controller(holder.clone()));
+
+  match controller {
+      Some(controller) => controller.require_auth(),
+      None => holder.require_auth(),
+  }
+}
+
+// Emits a CredentialExpiringEvent for a live scored credential expiring
+// within the warning window, once per expiry date
+fn warn_if_expiring(env: &Env, token_id: u128) -> bool {
+  let score: SBTScore = match env.storage().persistent().get(&DataKey::Score(token_id)) {
+      Some(score) => score,
+      None => return false,
+  };
+
+  let now = env.ledger().timestamp();
+  let window = SBTContract::expiry_warning_days(env.clone()).saturating_mul(DAY_IN_SECONDS);
+
+  if score.expires_at == 0 || score.expires_at <= now || score.expires_at - now > window {
+      return false;
+  }
+
+  let key = DataKey::ExpiryWarned(token_id);
+  if env.storage().persistent().get::<_, u64>(&key) == Some(score.expires_at) {
+      return false;
+  }
+
+  let holder = read_sbt(env, token_id).owner;
+  if holder == env.current_contract_address() || SBTContract::is_revoked(env.clone(), token_id) {
+      return false;
+  }
+
+  let expiring_event: CredentialExpiringEvent = CredentialExpiringEvent {
+      token_id,
+      holder,
+      schema_id: score.schema_id,
+      expires_at: score.expires_at
+  };
+
+  env.storage().persistent().set(&key, &score.expires_at);
+  env.events().publish((EXPIRY_EVENT, symbol_short!("expiring")), expiring_event);
+
+  true
+}
+
+// Issues the next token id to an address that has consented to holding it
+fn mint(env: &Env, to: Address, token_uri: String) -> u128 {
+  if to == env.current_contract_address() {
+      panic!("Sender can not be contract address")
+  } else if token_uri == String::from_slice(env, "") {
+      panic!("SBT URI can not be empty")
+  }
+
+  let mut token_id: u128 = env.storage().instance().get(&COUNTER).unwrap_or(0);
+
+  token_id += 1;
+
+  let mint_event: MintEvent = MintEvent { address: to.clone(), token_id };
+  let sbt_detail: SBTDetail = SBTDetail {
+      owner: to,
+      uri: token_uri,
+  };
+
+  env.storage().instance().set(&token_id, &sbt_detail);
+  env.storage().instance().set(&COUNTER, &token_id);
+  env.events().publish((MINT_EVENT, symbol_short!("mint")), mint_event);
+
+  token_id
+}
+
 #[cfg(test)]
 mod test;
 
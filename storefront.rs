@@ -1,15 +1,73 @@
 #![no_std]
 use soroban_sdk::{
-    contract, contractimpl, contracttype, symbol_short, token, Address, Env, Symbol
+    contract, contractclient, contractimpl, contracttype, events::Topics, symbol_short, token, Address,
+    Env, IntoVal, Symbol, Val, Vec,
 };
 
 mod nft_contract {
     soroban_sdk::contractimport!(file = "nft/nft_soroban.wasm");
 }
 
+mod nft_marketplace;
+mod registry_interface;
+mod token_id;
+
+use nft_marketplace::NFTMarketplaceClient;
+use registry_interface::{FixedListing, ListingRegistryClient, SaleItem};
+use token_id::TokenId;
+
+// SEP-40 price feed, quoting assets in the oracle's base currency (USD)
+#[contractclient(name = "PriceOracleClient")]
+pub trait PriceOracle {
+    fn decimals(env: Env) -> u32;
+
+    fn lastprice(env: Env, asset: OracleAsset) -> Option<PriceData>;
+}
+
+#[contracttype]
+#[derive(Clone, Debug)]
+pub enum OracleAsset {
+    Stellar(Address),
+    Other(Symbol),
+}
+
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct PriceData {
+    pub price: i128,
+    pub timestamp: u64,
+}
+
+// Multi-edition (1155-style) collection whose tokens have balances. The
+// storefront moves editions as an operator the maker approved.
+#[contractclient(name = "EditionTokenClient")]
+pub trait EditionToken {
+    fn balance_of(env: Env, owner: Address, token_id: u128) -> u128;
+
+    fn transfer_from(env: Env, operator: Address, from: Address, to: Address, token_id: u128, amount: u128);
+}
+
 const LISTEVENT: Symbol = symbol_short!("LISTEVENT");
-const SELLEVENT: Symbol = symbol_short!("SELLEVENT");
+const SALE_ACTION: Symbol = symbol_short!("sale");
 const DLEVENT: Symbol = symbol_short!("DLEVENT");
+const OFFEREVENT: Symbol = symbol_short!("OFFEREVNT");
+const PAYOUTEVENT: Symbol = symbol_short!("PAYOUT");
+const ORDEREVENT: Symbol = symbol_short!("ORDEREVNT");
+
+const MAX_SALE_HISTORY: u32 = 50;
+
+// Most sell orders resting in the book of one edition and payment token,
+// which bounds the walk of a purchase
+const MAX_BOOK_DEPTH: u32 = 50;
+
+const HOUR_IN_SECONDS: u64 = 3600;
+const DAY_IN_SECONDS: u64 = 86400;
+const MAX_BPS: u32 = 10_000;
+const MAX_RESALE_LOCK: u64 = 30 * DAY_IN_SECONDS;
+
+// Bumped whenever the topics or data layout of a published event changes,
+// 2 since event data carries the event nonce
+const EVENT_SCHEMA_VERSION: u32 = 2;
 
 #[derive(Clone)]
 #[contracttype]
@@ -23,8 +81,10 @@ pub struct ListEvent {
 #[contracttype]
 pub struct SellEvent {
     token_id: u128,
+    seller: Address,
     buyer: Address,
     price: i128,
+    payment_token: Address,
 }
 
 #[derive(Clone)]
@@ -34,11 +94,204 @@ pub struct DelistEvent {
     owner: Address,
 }
 
+#[derive(Clone)]
+#[contracttype]
+pub struct RejectEvent {
+    token_id: u128,
+    owner: Address,
+    reason_code: u32,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct OfferEvent {
+    offer_id: u64,
+    user: Address,
+    token_id: u128,
+    price: i128,
+    quantity: u32,
+}
+
+// Published with the sale when the seller's proceeds went to a payout
+// address other than the listing owner
+#[derive(Clone)]
+#[contracttype]
+pub struct PayoutEvent {
+    token_id: u128,
+    owner: Address,
+    payout: Address,
+    price: i128,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct OrderEvent {
+    order_id: u64,
+    maker: Address,
+    token_id: u128,
+    price: i128,
+    quantity: u32,
+}
+
+// One fill of a resting edition order, the maker selling to the taker
+#[derive(Clone)]
+#[contracttype]
+pub struct FillEvent {
+    order_id: u64,
+    maker: Address,
+    taker: Address,
+    token_id: u128,
+    price: i128,
+    quantity: u32,
+    payment_token: Address,
+}
+
 #[derive(Clone)]
 #[contracttype]
 pub enum DataKey {
     Admin,
     NFTAddress,
+    OfferCount,
+    Offer(u64),
+    Nonce(Address),
+    SaleHistory(u128),
+    HourlyStats(Address, u64),
+    DailyStats(Address, u64),
+    ListingRegistry,
+    Moderation,
+    Approver(Address),
+    Pending(u128),
+    Decay(u128),
+    Paused,
+    CircuitBreaker,
+    PlatformStats(u64),
+    StartTime(u128),
+    EventNonce,
+    EnforcedRoyalty(Address),
+    EnforcedCollections,
+    ResaleLock(u128),
+    PriceFeed(Address),
+    Payout(u128),
+    OrderCount,
+    Order(u64),
+    OrderBook(Address, u128, Address),
+}
+
+// Oracle quoting a payment token in USD, prices older than max_age seconds
+// are still returned but flagged as stale
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct PriceFeed {
+    oracle: Address,
+    max_age: u64,
+}
+
+// Indicative USD value of a purchase with value_decimals decimals, for
+// display and reporting only, settlement always uses the token price
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct UsdQuote {
+    value: i128,
+    value_decimals: u32,
+    timestamp: u64,
+    stale: bool,
+}
+
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct PurchaseQuote {
+    price: i128,
+    payment_token: Address,
+    usd: Option<UsdQuote>,
+}
+
+// Royalty a collection's creator requires on every storefront sale
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct RoyaltyPolicy {
+    recipient: Address,
+    bps: u32,
+}
+
+// Lifecycle of a listing as seen by buyers, Upcoming listings are scheduled
+// to go live at a later start time
+#[contracttype]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListingState {
+    None,
+    Pending,
+    Upcoming,
+    Active,
+}
+
+// Daily limits across every collection traded in the storefront, purchases
+// halt once a sale would exceed either until the next day
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct CircuitBreaker {
+    max_daily_volume: i128,
+    max_daily_sales: u32,
+}
+
+// Linear Dutch decay from the listing price down to end_price over duration
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct PriceDecay {
+    end_price: i128,
+    start_time: u64,
+    duration: u64,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Default)]
+pub struct SaleStats {
+    volume: i128,
+    sales: u32,
+    min_price: i128,
+}
+
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct CollectionStats {
+    floor_price: i128,
+    volume_24h: i128,
+    volume_7d: i128,
+    sales_24h: u32,
+    sales_7d: u32,
+}
+
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct SaleRecord {
+    price: i128,
+    buyer: Address,
+    timestamp: u64,
+}
+
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct CollectionOffer {
+    offer_id: u64,
+    buyer: Address,
+    collection: Address,
+    payment_token: Address,
+    price: i128,
+    quantity: u32,
+    filled: u32,
+}
+
+// Sell order for quantity editions of token_id at price per edition
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct EditionOrder {
+    order_id: u64,
+    maker: Address,
+    collection: Address,
+    token_id: u128,
+    payment_token: Address,
+    price: i128,
+    quantity: u32,
+    filled: u32,
 }
 
 #[contracttype]
@@ -54,7 +307,7 @@ pub struct NFTStoreFront;
 
 #[contractimpl]
 impl NFTStoreFront {
-    fn initialize(env: Env, nft_contract_address: Address, admin: Address) {
+    pub fn initialize(env: Env, nft_contract_address: Address, admin: Address) {
         if Self::has_administrator(env.clone()) {
             panic!("Contract already initialized")
         }
@@ -68,14 +321,186 @@ impl NFTStoreFront {
     pub fn list_nft(env: Env, from: Address, token_id: u128, price: i128) {
         from.require_auth();
 
+        Self::create_listing(env, from, token_id, price);
+    }
+
+    // Lists at start_price, decaying linearly to end_price over duration seconds
+    pub fn list_nft_decaying(
+        env: Env,
+        from: Address,
+        token_id: u128,
+        start_price: i128,
+        end_price: i128,
+        duration: u64,
+    ) {
+        from.require_auth();
+
+        if end_price < 0 || end_price > start_price {
+            panic!("End price must be between zero and the start price")
+        } else if duration == 0 {
+            panic!("Duration can not be zero")
+        }
+
+        Self::create_listing(env.clone(), from, token_id, start_price);
+
+        let decay = PriceDecay {
+            end_price,
+            start_time: env.ledger().timestamp(),
+            duration,
+        };
+        env.storage().instance().set(&DataKey::Decay(token_id), &decay);
+    }
+
+    // Lists an NFT that can only be purchased from start_time on, so drops can
+    // be announced ahead of time
+    pub fn list_nft_scheduled(env: Env, from: Address, token_id: u128, price: i128, start_time: u64) {
+        from.require_auth();
+
+        if start_time <= env.ledger().timestamp() {
+            panic!("Start time must be in the future")
+        }
+
+        Self::create_listing(env.clone(), from, token_id, price);
+
+        env.storage().instance().set(&DataKey::StartTime(token_id), &start_time);
+        Self::sync_listing_report(env.clone(), token_id);
+    }
+
+    // Lists an NFT whose buyer can not transfer it for lock_duration seconds
+    // after the sale, through a purchase or an accepted collection offer
+    pub fn list_nft_with_resale_lock(env: Env, from: Address, token_id: u128, price: i128, lock_duration: u64) {
+        from.require_auth();
+
+        if lock_duration == 0 || lock_duration > MAX_RESALE_LOCK {
+            panic!("Resale lock must be positive and at most 30 days")
+        }
+
+        // The lock is applied through marketplace_lock, which needs the storefront registered
+        let collection = Self::get_nft_client(env.clone()).address;
+        if !nft_marketplace::is_registered(&env, &collection, &env.current_contract_address()) {
+            panic!("Storefront is not a registered marketplace of the collection")
+        }
+
+        Self::create_listing(env.clone(), from, token_id, price);
+
+        env.storage().instance().set(&DataKey::ResaleLock(token_id), &lock_duration);
+    }
+
+    // Lists an NFT whose sale proceeds go to payout instead of the owner,
+    // e.g. a treasury or split contract
+    pub fn list_nft_with_payout(env: Env, from: Address, token_id: u128, price: i128, payout: Address) {
+        from.require_auth();
+
+        if payout == env.current_contract_address() {
+            panic!("Payout can not be contract address")
+        }
+
+        Self::create_listing(env.clone(), from, token_id, price);
+
+        env.storage().instance().set(&DataKey::Payout(token_id), &payout);
+    }
+
+    pub fn listing_payout(env: Env, token_id: u128) -> Option<Address> {
+        env.storage().instance().get(&DataKey::Payout(token_id))
+    }
+
+    pub fn resale_lock(env: Env, token_id: u128) -> Option<u64> {
+        env.storage().instance().get(&DataKey::ResaleLock(token_id))
+    }
+
+    pub fn listing_start(env: Env, token_id: u128) -> Option<u64> {
+        env.storage().instance().get(&DataKey::StartTime(token_id))
+    }
+
+    // Listing of the token in the shape the listing registry's get_item returns
+    pub fn get_item(env: Env, token_id: u128) -> SaleItem {
+        let listed_nft = Self::get_listed_nft(env.clone(), token_id);
+
+        if listed_nft.token_id == 0 {
+            return SaleItem::None;
+        }
+
+        SaleItem::Listing(FixedListing {
+            marketplace: env.current_contract_address(),
+            owner: listed_nft.owner,
+            price: Self::price_at(env.clone(), token_id, env.ledger().timestamp()),
+        })
+    }
+
+    pub fn listing_state(env: Env, token_id: u128) -> ListingState {
+        if Self::get_listed_nft(env.clone(), token_id).token_id == 0 {
+            return ListingState::None;
+        } else if Self::is_listing_pending(env.clone(), token_id) {
+            return ListingState::Pending;
+        }
+
+        match Self::listing_start(env.clone(), token_id) {
+            Some(start_time) if env.ledger().timestamp() < start_time => ListingState::Upcoming,
+            _ => ListingState::Active,
+        }
+    }
+
+    // Price a purchase of the listing executes at when made at timestamp,
+    // the same math purchase_listed_nft applies
+    pub fn price_at(env: Env, token_id: u128, timestamp: u64) -> i128 {
+        let listed_nft = Self::get_listed_nft(env.clone(), token_id);
+
+        if listed_nft.token_id == 0 {
+            panic!("NFT not listed")
+        }
+
+        let decay: PriceDecay = match env.storage().instance().get(&DataKey::Decay(token_id)) {
+            Some(decay) => decay,
+            None => return listed_nft.price,
+        };
+
+        if timestamp <= decay.start_time {
+            return listed_nft.price;
+        }
+
+        let elapsed = timestamp - decay.start_time;
+        if elapsed >= decay.duration {
+            return decay.end_price;
+        }
+
+        let drop = (listed_nft.price - decay.end_price)
+            .checked_mul(elapsed as i128)
+            .expect("Price decay overflows")
+            / decay.duration as i128;
+
+        listed_nft.price - drop
+    }
+
+    // Lets a relayer submit a listing the seller authorized off-chain. The
+    // signed arguments include the seller's next nonce so it can't be replayed.
+    pub fn list_nft_relayed(env: Env, from: Address, token_id: u128, price: i128, nonce: u64) {
+        from.require_auth_for_args((token_id, price, nonce).into_val(&env));
+
+        Self::consume_nonce(env.clone(), from.clone(), nonce);
+        Self::create_listing(env, from, token_id, price);
+    }
+
+    pub fn get_nonce(env: Env, account: Address) -> u64 {
+        env.storage().instance().get(&DataKey::Nonce(account)).unwrap_or(0)
+    }
+
+    fn consume_nonce(env: Env, account: Address, nonce: u64) {
+        if nonce != Self::get_nonce(env.clone(), account.clone()) {
+            panic!("Invalid nonce")
+        }
+
+        env.storage().instance().set(&DataKey::Nonce(account), &(nonce + 1));
+    }
+
+    fn create_listing(env: Env, from: Address, token_id: u128, price: i128) {
+        TokenId::from_u128(token_id);
+
         let nft_client = Self::get_nft_client(env.clone());
 
         if nft_client.has_nft_owner(&from.clone(), &token_id) {
             panic!("Invalid Sender")
         } else if from == env.current_contract_address() {
             panic!("Sender can not be contract address")
-        } else if token_id == 0 {
-            panic!("Token ID can not be zero")
         }
 
         let list_nft = Self::get_listed_nft(env.clone(), token_id);
@@ -95,9 +520,21 @@ impl NFTStoreFront {
             price,
         };
 
+        // Settings of an earlier listing of the token, possibly by a previous
+        // owner, must not carry over
+        Self::clear_listing_settings(env.clone(), token_id);
+
         env.storage().instance().set(&token_id, &listing); // store list nft at token_id
+        Self::lock_listing(env.clone(), token_id);
 
-        env.events().publish((LISTEVENT, symbol_short!("listed")), list_event);
+        // Under moderation a listing only becomes purchasable once approved
+        if Self::moderation(env.clone()) {
+            env.storage().instance().set(&DataKey::Pending(token_id), &true);
+            Self::publish_event(env.clone(), (LISTEVENT, symbol_short!("pending")), list_event);
+        } else {
+            Self::sync_listing_report(env.clone(), token_id);
+            Self::publish_event(env.clone(), (LISTEVENT, symbol_short!("listed")), list_event);
+        }
     }
 
     pub fn delist_nft(env: Env, from: Address, token_id: u128) {
@@ -114,14 +551,86 @@ impl NFTStoreFront {
             panic!("Only the owner or admin can delist the NFT");
         }
 
-        env.storage().instance().remove(&token_id);
+        Self::close_listing(env.clone(), token_id);
 
         let delist_event = DelistEvent {
             token_id,
             owner: from,
         };
 
-        env.events().publish((DLEVENT, symbol_short!("delisted")), delist_event)
+        Self::publish_event(env.clone(), (DLEVENT, symbol_short!("delisted")), delist_event)
+    }
+
+    pub fn set_moderation(env: Env, admin: Address, enabled: bool) {
+        Self::check_admin(env.clone(), admin);
+
+        env.storage().instance().set(&DataKey::Moderation, &enabled);
+    }
+
+    pub fn moderation(env: Env) -> bool {
+        env.storage().instance().get(&DataKey::Moderation).unwrap_or(false)
+    }
+
+    pub fn set_approver(env: Env, admin: Address, approver: Address, allowed: bool) {
+        Self::check_admin(env.clone(), admin);
+
+        if allowed {
+            env.storage().instance().set(&DataKey::Approver(approver), &true);
+        } else {
+            env.storage().instance().remove(&DataKey::Approver(approver));
+        }
+    }
+
+    pub fn is_approver(env: Env, approver: Address) -> bool {
+        approver == Self::read_administrator(env.clone())
+            || env.storage().instance().has(&DataKey::Approver(approver))
+    }
+
+    pub fn is_listing_pending(env: Env, token_id: u128) -> bool {
+        env.storage().instance().has(&DataKey::Pending(token_id))
+    }
+
+    pub fn approve_listing(env: Env, approver: Address, token_id: u128) {
+        let listed_nft = Self::get_pending_listing(env.clone(), approver, token_id);
+
+        env.storage().instance().remove(&DataKey::Pending(token_id));
+        Self::sync_listing_report(env.clone(), token_id);
+
+        let list_event = ListEvent {
+            token_id,
+            owner: listed_nft.owner,
+            price: listed_nft.price,
+        };
+
+        Self::publish_event(env.clone(), (LISTEVENT, symbol_short!("listed")), list_event);
+    }
+
+    // Drops a pending listing. The storefront never takes custody of listed
+    // NFTs, so the token is already back with its owner once the listing is gone.
+    pub fn reject_listing(env: Env, approver: Address, token_id: u128, reason_code: u32) {
+        let listed_nft = Self::get_pending_listing(env.clone(), approver, token_id);
+
+        Self::close_listing(env.clone(), token_id);
+
+        let reject_event = RejectEvent {
+            token_id,
+            owner: listed_nft.owner,
+            reason_code,
+        };
+
+        Self::publish_event(env.clone(), (LISTEVENT, symbol_short!("rejected")), reject_event);
+    }
+
+    fn get_pending_listing(env: Env, approver: Address, token_id: u128) -> NFTListing {
+        approver.require_auth();
+
+        if !Self::is_approver(env.clone(), approver) {
+            panic!("Only an approver can moderate listings")
+        } else if !Self::is_listing_pending(env.clone(), token_id) {
+            panic!("Listing not pending")
+        }
+
+        Self::get_listed_nft(env, token_id)
     }
 
     fn get_listed_nft(env: Env, token_id: u128) -> NFTListing {
@@ -134,63 +643,936 @@ impl NFTStoreFront {
         return listed_nft;
     }
 
-    pub fn purchase_listed_nft(env: Env, owner: Address, buyer: Address, token_id: u128, xlm_address: Address) {
-        buyer.require_auth();
+    // Ends a listing however it ended, sold, delisted or rejected
+    fn close_listing(env: Env, token_id: u128) {
+        env.storage().instance().remove(&token_id);
+        Self::clear_listing_settings(env.clone(), token_id);
+        Self::report_listing(env.clone(), token_id, None);
+        Self::unlock_listing(env, token_id);
+    }
+
+    fn clear_listing_settings(env: Env, token_id: u128) {
+        env.storage().instance().remove(&DataKey::Pending(token_id));
+        env.storage().instance().remove(&DataKey::Decay(token_id));
+        env.storage().instance().remove(&DataKey::StartTime(token_id));
+        env.storage().instance().remove(&DataKey::ResaleLock(token_id));
+        env.storage().instance().remove(&DataKey::Payout(token_id));
+    }
+
+    // Reverts if the listing price exceeds max_price, protecting the buyer
+    // from a price change between signing and execution. The buyer signs
+    // the token, max price, payment token and their next nonce, so a
+    // captured authorization can't be replayed against another listing.
+    pub fn purchase_listed_nft(
+        env: Env,
+        owner: Address,
+        buyer: Address,
+        token_id: u128,
+        max_price: i128,
+        xlm_address: Address,
+        nonce: u64,
+    ) {
+        buyer.require_auth_for_args((token_id, max_price, xlm_address.clone(), nonce).into_val(&env));
+
+        Self::consume_nonce(env.clone(), buyer.clone(), nonce);
+        TokenId::from_u128(token_id);
         let nft_client = Self::get_nft_client(env.clone());
 
         if nft_client.has_nft_owner(&owner.clone(), &token_id) {
             panic!("Invalid Sender")
         } else if owner == env.current_contract_address() {
             panic!("Sender can not be contract address")
-        } else if token_id == 0 {
-            panic!("Token ID can not be zero")
         }
 
         let mut listed_nft = Self::get_listed_nft(env.clone(), token_id);
 
+        // A listing made by an earlier holder must not sell the token out from
+        // under whoever holds it now
         if listed_nft.token_id == 0 {
             panic!("NFT not listed yet")
+        } else if listed_nft.owner != owner {
+            panic!("Listing was made by a previous owner")
+        } else if Self::is_listing_pending(env.clone(), token_id) {
+            panic!("Listing pending approval")
+        } else if Self::listing_state(env.clone(), token_id) == ListingState::Upcoming {
+            panic!("Listing has not started yet")
         }
 
-        let client = token::Client::new(&env.clone(), &xlm_address);
-        client.transfer(&buyer, &owner, &listed_nft.price);
+        listed_nft.price = Self::price_at(env.clone(), token_id, env.ledger().timestamp());
 
-        nft_client.transfer_from(&owner, &buyer, &token_id);
+        if listed_nft.price > max_price {
+            panic!("Listing price exceeds max price")
+        }
 
-        env.storage().instance().remove(&token_id);
+        Self::check_trading(env.clone(), listed_nft.price);
 
-        let sell_event = SellEvent {
+        let payout = Self::listing_payout(env.clone(), token_id).unwrap_or(owner.clone());
+
+        Self::pay_sale(
+            env.clone(),
+            nft_client.address.clone(),
             token_id,
-            buyer: buyer.clone(),
-            price: listed_nft.price,
-        };
+            buyer.clone(),
+            payout.clone(),
+            listed_nft.price,
+            xlm_address.clone(),
+        );
 
-        env.events().publish((SELLEVENT, symbol_short!("sold")), sell_event)
-    }
+        Self::transfer_sold_nft(env.clone(), &nft_client, &owner, &buyer, token_id);
+        Self::apply_resale_lock(env.clone(), &nft_client.address, token_id);
 
-    fn read_administrator(env: Env) -> Address {
-        env.storage().instance().get(&DataKey::Admin).unwrap()
-    }
+        Self::close_listing(env.clone(), token_id);
+        Self::record_sale(env.clone(), nft_client.address.clone(), token_id, buyer.clone(), listed_nft.price);
 
-    fn has_administrator(env: Env) -> bool {
-        let key = DataKey::Admin;
-        env.storage().instance().has(&key)
-    }
+        if payout != owner {
+            let payout_event = PayoutEvent {
+                token_id,
+                owner: owner.clone(),
+                payout,
+                price: listed_nft.price,
+            };
+            Self::publish_event(env.clone(), (PAYOUTEVENT, symbol_short!("paid")), payout_event);
+        }
 
-    fn get_nft_client(env: Env) -> nft_contract::Client<'static> {
-        let contract = env
-            .storage()
-            .instance()
-            .get::<DataKey, Address>(&DataKey::NFTAddress)
-            .expect("none");
+        Self::publish_sale(
+            env.clone(),
+            nft_client.address.clone(),
+            token_id,
+            owner,
+            buyer,
+            listed_nft.price,
+            xlm_address,
+        );
+    }
 
-        let client = nft_contract::Client::new(&env, &contract);
+    // Escrows price * quantity for a floor bid on any token of the collection
+    pub fn make_collection_offer(
+        env: Env,
+        buyer: Address,
+        collection: Address,
+        price: i128,
+        quantity: u32,
+        xlm_address: Address,
+    ) -> u64 {
+        buyer.require_auth();
 
-        return client;
-    }
-}
+        if buyer == env.current_contract_address() {
+            panic!("Sender can not be contract address")
+        } else if price <= 0 {
+            panic!("Offer price must be positive")
+        } else if quantity == 0 {
+            panic!("Offer quantity can not be zero")
+        }
 
-#[cfg(test)]
+        let offer_id: u64 = env.storage().instance().get(&DataKey::OfferCount).unwrap_or(0) + 1;
+        let offer = CollectionOffer {
+            offer_id,
+            buyer: buyer.clone(),
+            collection,
+            payment_token: xlm_address.clone(),
+            price,
+            quantity,
+            filled: 0,
+        };
+
+        let escrow = price.checked_mul(quantity as i128).expect("Offer escrow overflows");
+
+        let client = token::Client::new(&env.clone(), &xlm_address);
+        client.transfer(&buyer, &env.current_contract_address(), &escrow);
+
+        env.storage().instance().set(&DataKey::Offer(offer_id), &offer);
+        env.storage().instance().set(&DataKey::OfferCount, &offer_id);
+
+        let offer_event = OfferEvent {
+            offer_id,
+            user: buyer,
+            token_id: 0,
+            price,
+            quantity,
+        };
+
+        Self::publish_event(env.clone(), (OFFEREVENT, symbol_short!("offered")), offer_event);
+
+        offer_id
+    }
+
+    // Fills one unit of the offer with a token the seller holds in the collection
+    pub fn accept_collection_offer(env: Env, seller: Address, offer_id: u64, token_id: u128) {
+        seller.require_auth();
+        TokenId::from_u128(token_id);
+
+        let mut offer = Self::get_collection_offer(env.clone(), offer_id);
+        let nft_client = nft_contract::Client::new(&env, &offer.collection);
+
+        if nft_client.has_nft_owner(&seller.clone(), &token_id) {
+            panic!("Invalid Sender")
+        } else if seller == offer.buyer {
+            panic!("Buyer can not accept own offer")
+        }
+
+        Self::check_trading(env.clone(), offer.price);
+
+        offer.filled += 1;
+
+        if offer.filled == offer.quantity {
+            env.storage().instance().remove(&DataKey::Offer(offer_id));
+        } else {
+            env.storage().instance().set(&DataKey::Offer(offer_id), &offer);
+        }
+
+        Self::transfer_sold_nft(env.clone(), &nft_client, &seller, &offer.buyer, token_id);
+
+        // A listed token filling the offer is delisted, honouring the resale
+        // lock disclosed in its listing
+        let listed = offer.collection == Self::get_nft_client(env.clone()).address
+            && Self::get_listed_nft(env.clone(), token_id).token_id != 0;
+
+        if listed {
+            Self::apply_resale_lock(env.clone(), &nft_client.address, token_id);
+            Self::close_listing(env.clone(), token_id);
+
+            let delist_event = DelistEvent {
+                token_id,
+                owner: seller.clone(),
+            };
+            Self::publish_event(env.clone(), (DLEVENT, symbol_short!("filled")), delist_event);
+        }
+
+        Self::record_sale(
+            env.clone(),
+            offer.collection.clone(),
+            token_id,
+            offer.buyer.clone(),
+            offer.price,
+        );
+
+        Self::pay_sale(
+            env.clone(),
+            offer.collection.clone(),
+            token_id,
+            env.current_contract_address(),
+            seller.clone(),
+            offer.price,
+            offer.payment_token.clone(),
+        );
+
+        let offer_event = OfferEvent {
+            offer_id,
+            user: seller.clone(),
+            token_id,
+            price: offer.price,
+            quantity: offer.quantity - offer.filled,
+        };
+
+        Self::publish_event(env.clone(), (OFFEREVENT, symbol_short!("accepted")), offer_event);
+
+        Self::publish_sale(
+            env.clone(),
+            offer.collection,
+            token_id,
+            seller,
+            offer.buyer,
+            offer.price,
+            offer.payment_token,
+        );
+    }
+
+    // Cancels the unfilled remainder of an offer and refunds its escrow
+    pub fn cancel_collection_offer(env: Env, buyer: Address, offer_id: u64) {
+        buyer.require_auth();
+
+        let offer = Self::get_collection_offer(env.clone(), offer_id);
+
+        if offer.buyer != buyer {
+            panic!("Only the buyer can cancel the offer")
+        }
+
+        env.storage().instance().remove(&DataKey::Offer(offer_id));
+
+        let remaining = offer.quantity - offer.filled;
+        let refund = offer.price.checked_mul(remaining as i128).expect("Offer escrow overflows");
+
+        let client = token::Client::new(&env.clone(), &offer.payment_token);
+        client.transfer(&env.current_contract_address(), &buyer, &refund);
+
+        let offer_event = OfferEvent {
+            offer_id,
+            user: buyer,
+            token_id: 0,
+            price: offer.price,
+            quantity: remaining,
+        };
+
+        Self::publish_event(env.clone(), (OFFEREVENT, symbol_short!("cancelled")), offer_event);
+    }
+
+    pub fn get_collection_offer(env: Env, offer_id: u64) -> CollectionOffer {
+        env.storage()
+            .instance()
+            .get(&DataKey::Offer(offer_id))
+            .expect("Offer not found")
+    }
+
+    // Rests a sell order for editions the maker holds. Orders of the same
+    // edition and payment token are kept cheapest first, oldest first at
+    // the same price.
+    pub fn place_edition_order(
+        env: Env,
+        maker: Address,
+        collection: Address,
+        token_id: u128,
+        price: i128,
+        quantity: u32,
+        xlm_address: Address,
+    ) -> u64 {
+        maker.require_auth();
+        TokenId::from_u128(token_id);
+
+        if maker == env.current_contract_address() {
+            panic!("Sender can not be contract address")
+        } else if price <= 0 {
+            panic!("Order price must be positive")
+        } else if quantity == 0 {
+            panic!("Order quantity can not be zero")
+        }
+
+        let edition_client = EditionTokenClient::new(&env, &collection);
+
+        if edition_client.balance_of(&maker, &token_id) < quantity as u128 {
+            panic!("Insufficient editions")
+        }
+
+        let key = DataKey::OrderBook(collection.clone(), token_id, xlm_address.clone());
+        let mut book: Vec<(i128, u64)> = env.storage().instance().get(&key).unwrap_or(Vec::new(&env));
+
+        if book.len() >= MAX_BOOK_DEPTH {
+            panic!("Order book is full")
+        }
+
+        let order_id: u64 = env.storage().instance().get(&DataKey::OrderCount).unwrap_or(0) + 1;
+        let order = EditionOrder {
+            order_id,
+            maker: maker.clone(),
+            collection,
+            token_id,
+            payment_token: xlm_address,
+            price,
+            quantity,
+            filled: 0,
+        };
+
+        let index = book.iter().position(|(resting, _)| resting > price).unwrap_or(book.len() as usize);
+        book.insert(index as u32, (price, order_id));
+
+        env.storage().instance().set(&DataKey::Order(order_id), &order);
+        env.storage().instance().set(&DataKey::OrderCount, &order_id);
+        env.storage().instance().set(&key, &book);
+
+        let order_event = OrderEvent {
+            order_id,
+            maker,
+            token_id,
+            price,
+            quantity,
+        };
+
+        Self::publish_event(env.clone(), (ORDEREVENT, symbol_short!("placed")), order_event);
+
+        order_id
+    }
+
+    // Cancels the unfilled remainder of an edition order
+    pub fn cancel_edition_order(env: Env, maker: Address, order_id: u64) {
+        maker.require_auth();
+
+        let order = Self::get_edition_order(env.clone(), order_id);
+
+        if order.maker != maker {
+            panic!("Only the maker can cancel the order")
+        }
+
+        Self::remove_edition_order(env.clone(), &order);
+
+        let order_event = OrderEvent {
+            order_id,
+            maker,
+            token_id: order.token_id,
+            price: order.price,
+            quantity: order.quantity - order.filled,
+        };
+
+        Self::publish_event(env.clone(), (ORDEREVENT, symbol_short!("cancelled")), order_event);
+    }
+
+    // Buys up to quantity editions from the cheapest orders priced at most
+    // max_price, partially filling the last one, and returns how many were
+    // bought. Orders whose maker no longer holds the editions are dropped.
+    pub fn buy_editions(
+        env: Env,
+        buyer: Address,
+        collection: Address,
+        token_id: u128,
+        quantity: u32,
+        max_price: i128,
+        xlm_address: Address,
+    ) -> u32 {
+        buyer.require_auth();
+        TokenId::from_u128(token_id);
+
+        if quantity == 0 {
+            panic!("Order quantity can not be zero")
+        }
+
+        let edition_client = EditionTokenClient::new(&env, &collection);
+        let key = DataKey::OrderBook(collection.clone(), token_id, xlm_address.clone());
+        let book: Vec<(i128, u64)> = env.storage().instance().get(&key).unwrap_or(Vec::new(&env));
+        let mut resting = Vec::new(&env);
+        let mut remaining = quantity;
+
+        for (price, order_id) in book.iter() {
+            let mut order = Self::get_edition_order(env.clone(), order_id);
+
+            if remaining == 0 || price > max_price || order.maker == buyer {
+                resting.push_back((price, order_id));
+                continue;
+            }
+
+            let held = edition_client.balance_of(&order.maker, &token_id).min(u32::MAX as u128) as u32;
+
+            if held == 0 {
+                env.storage().instance().remove(&DataKey::Order(order_id));
+                continue;
+            }
+
+            let fill = remaining.min(order.quantity - order.filled).min(held);
+            let cost = price.checked_mul(fill as i128).expect("Order cost overflows");
+
+            Self::check_trading(env.clone(), cost);
+
+            Self::pay_sale(
+                env.clone(),
+                collection.clone(),
+                token_id,
+                buyer.clone(),
+                order.maker.clone(),
+                cost,
+                xlm_address.clone(),
+            );
+            edition_client.transfer_from(
+                &env.current_contract_address(),
+                &order.maker,
+                &buyer,
+                &token_id,
+                &(fill as u128),
+            );
+
+            let day = env.ledger().timestamp() / DAY_IN_SECONDS;
+            Self::add_to_stats(env.clone(), DataKey::PlatformStats(day), cost);
+
+            order.filled += fill;
+            remaining -= fill;
+
+            if order.filled == order.quantity {
+                env.storage().instance().remove(&DataKey::Order(order_id));
+            } else {
+                env.storage().instance().set(&DataKey::Order(order_id), &order);
+                resting.push_back((price, order_id));
+            }
+
+            let fill_event = FillEvent {
+                order_id,
+                maker: order.maker,
+                taker: buyer.clone(),
+                token_id,
+                price,
+                quantity: fill,
+                payment_token: xlm_address.clone(),
+            };
+
+            Self::publish_event(env.clone(), (ORDEREVENT, symbol_short!("filled")), fill_event);
+        }
+
+        if remaining == quantity {
+            panic!("No order matches the price")
+        }
+
+        env.storage().instance().set(&key, &resting);
+
+        quantity - remaining
+    }
+
+    pub fn get_edition_order(env: Env, order_id: u64) -> EditionOrder {
+        env.storage()
+            .instance()
+            .get(&DataKey::Order(order_id))
+            .expect("Order not found")
+    }
+
+    // Resting orders of an edition in one payment token, cheapest first
+    pub fn order_book(env: Env, collection: Address, token_id: u128, xlm_address: Address) -> Vec<EditionOrder> {
+        let key = DataKey::OrderBook(collection, token_id, xlm_address);
+        let book: Vec<(i128, u64)> = env.storage().instance().get(&key).unwrap_or(Vec::new(&env));
+        let mut orders = Vec::new(&env);
+
+        for (_, order_id) in book.iter() {
+            orders.push_back(Self::get_edition_order(env.clone(), order_id));
+        }
+
+        orders
+    }
+
+    fn remove_edition_order(env: Env, order: &EditionOrder) {
+        let key = DataKey::OrderBook(order.collection.clone(), order.token_id, order.payment_token.clone());
+        let mut book: Vec<(i128, u64)> = env.storage().instance().get(&key).unwrap_or(Vec::new(&env));
+
+        if let Some(index) = book.first_index_of((order.price, order.order_id)) {
+            book.remove(index);
+        }
+
+        env.storage().instance().set(&key, &book);
+        env.storage().instance().remove(&DataKey::Order(order.order_id));
+    }
+
+    pub fn last_sale(env: Env, token_id: u128) -> Option<SaleRecord> {
+        let history: Vec<SaleRecord> = env
+            .storage()
+            .persistent()
+            .get(&DataKey::SaleHistory(token_id))
+            .unwrap_or(Vec::new(&env));
+
+        history.first()
+    }
+
+    // Returns sales newest first, paginated by start index and limit
+    pub fn sale_history(env: Env, token_id: u128, start: u32, limit: u32) -> Vec<SaleRecord> {
+        let history: Vec<SaleRecord> = env
+            .storage()
+            .persistent()
+            .get(&DataKey::SaleHistory(token_id))
+            .unwrap_or(Vec::new(&env));
+
+        if start >= history.len() {
+            return Vec::new(&env);
+        }
+
+        let end = start.saturating_add(limit).min(history.len());
+
+        history.slice(start..end)
+    }
+
+    // Sale events follow the wallet convention of (action, schema version,
+    // asset contract, token id) topics with amounts and addresses as data
+    fn publish_sale(
+        env: Env,
+        collection: Address,
+        token_id: u128,
+        seller: Address,
+        buyer: Address,
+        price: i128,
+        payment_token: Address,
+    ) {
+        let sell_event = SellEvent {
+            token_id,
+            seller,
+            buyer,
+            price,
+            payment_token,
+        };
+
+        Self::publish_event(
+            env.clone(),
+            (SALE_ACTION, EVENT_SCHEMA_VERSION, collection, token_id),
+            sell_event,
+        )
+    }
+
+    // None removes the feed, quotes in that token then carry no USD value
+    pub fn set_price_feed(env: Env, admin: Address, payment_token: Address, feed: Option<PriceFeed>) {
+        Self::check_admin(env.clone(), admin);
+
+        match feed {
+            Some(feed) => env.storage().instance().set(&DataKey::PriceFeed(payment_token), &feed),
+            None => env.storage().instance().remove(&DataKey::PriceFeed(payment_token)),
+        }
+    }
+
+    pub fn price_feed(env: Env, payment_token: Address) -> Option<PriceFeed> {
+        env.storage().instance().get(&DataKey::PriceFeed(payment_token))
+    }
+
+    // Current price of a listing in payment_token, with its USD value when
+    // the token has a price feed that returns a price
+    pub fn quote_purchase(env: Env, token_id: u128, payment_token: Address) -> PurchaseQuote {
+        if Self::get_listed_nft(env.clone(), token_id).token_id == 0 {
+            panic!("NFT not listed yet")
+        }
+
+        let price = Self::price_at(env.clone(), token_id, env.ledger().timestamp());
+        let usd = Self::price_feed(env.clone(), payment_token.clone())
+            .and_then(|feed| Self::usd_quote(env.clone(), feed, payment_token.clone(), price));
+
+        PurchaseQuote { price, payment_token, usd }
+    }
+
+    fn usd_quote(env: Env, feed: PriceFeed, payment_token: Address, amount: i128) -> Option<UsdQuote> {
+        let oracle = PriceOracleClient::new(&env, &feed.oracle);
+        let data = oracle.lastprice(&OracleAsset::Stellar(payment_token.clone()))?;
+
+        let token_decimals = token::Client::new(&env, &payment_token).decimals();
+        let value = amount
+            .checked_mul(data.price)
+            .and_then(|value| value.checked_div(10i128.checked_pow(token_decimals)?))?;
+
+        Some(UsdQuote {
+            value,
+            value_decimals: oracle.decimals(),
+            timestamp: data.timestamp,
+            stale: env.ledger().timestamp().saturating_sub(data.timestamp) > feed.max_age,
+        })
+    }
+
+    // Aggregates sales of a collection over the last 24 hours (hourly buckets)
+    // and 7 days (daily buckets). The floor is the lowest 24h sale price.
+    // Exposed as a price oracle for other contracts such as NFT lending.
+    pub fn collection_stats(env: Env, collection: Address) -> CollectionStats {
+        let now = env.ledger().timestamp();
+        let hour = now / HOUR_IN_SECONDS;
+        let day = now / DAY_IN_SECONDS;
+        let mut stats = CollectionStats {
+            floor_price: 0,
+            volume_24h: 0,
+            volume_7d: 0,
+            sales_24h: 0,
+            sales_7d: 0,
+        };
+
+        for bucket in (hour.saturating_sub(23))..=hour {
+            let hourly = Self::get_stats(env.clone(), DataKey::HourlyStats(collection.clone(), bucket));
+
+            if hourly.sales > 0 && (stats.sales_24h == 0 || hourly.min_price < stats.floor_price) {
+                stats.floor_price = hourly.min_price;
+            }
+            stats.volume_24h = stats.volume_24h.checked_add(hourly.volume).expect("Volume overflows");
+            stats.sales_24h += hourly.sales;
+        }
+
+        for bucket in (day.saturating_sub(6))..=day {
+            let daily = Self::get_stats(env.clone(), DataKey::DailyStats(collection.clone(), bucket));
+
+            stats.volume_7d = stats.volume_7d.checked_add(daily.volume).expect("Volume overflows");
+            stats.sales_7d += daily.sales;
+        }
+
+        stats
+    }
+
+    fn get_stats(env: Env, key: DataKey) -> SaleStats {
+        env.storage().persistent().get(&key).unwrap_or_default()
+    }
+
+    fn add_to_stats(env: Env, key: DataKey, price: i128) {
+        let mut stats = Self::get_stats(env.clone(), key.clone());
+
+        if stats.sales == 0 || price < stats.min_price {
+            stats.min_price = price;
+        }
+        stats.volume = stats.volume.checked_add(price).expect("Volume overflows");
+        stats.sales += 1;
+
+        env.storage().persistent().set(&key, &stats);
+    }
+
+    fn record_sale(env: Env, collection: Address, token_id: u128, buyer: Address, price: i128) {
+        let now = env.ledger().timestamp();
+
+        Self::add_to_stats(env.clone(), DataKey::HourlyStats(collection.clone(), now / HOUR_IN_SECONDS), price);
+        Self::add_to_stats(env.clone(), DataKey::DailyStats(collection, now / DAY_IN_SECONDS), price);
+        Self::add_to_stats(env.clone(), DataKey::PlatformStats(now / DAY_IN_SECONDS), price);
+
+        let key = DataKey::SaleHistory(token_id);
+        let mut history: Vec<SaleRecord> = env.storage().persistent().get(&key).unwrap_or(Vec::new(&env));
+
+        history.push_front(SaleRecord {
+            price,
+            buyer,
+            timestamp: env.ledger().timestamp(),
+        });
+
+        if history.len() > MAX_SALE_HISTORY {
+            history.pop_back();
+        }
+
+        env.storage().persistent().set(&key, &history);
+    }
+
+    // Lets the admin of an NFT collection require a royalty on its sales, or
+    // lift the requirement with None
+    pub fn set_royalty_enforcement(env: Env, collection: Address, policy: Option<RoyaltyPolicy>) {
+        let nft_client = nft_contract::Client::new(&env, &collection);
+        nft_client.read_administrator().require_auth();
+
+        let mut collections = Self::enforced_collections(env.clone());
+        let index = collections.first_index_of(collection.clone());
+
+        match policy {
+            Some(policy) => {
+                if policy.bps == 0 || policy.bps > MAX_BPS {
+                    panic!("Invalid royalty")
+                }
+
+                env.storage().instance().set(&DataKey::EnforcedRoyalty(collection.clone()), &policy);
+                if index.is_none() {
+                    collections.push_back(collection);
+                }
+            }
+            None => {
+                env.storage().instance().remove(&DataKey::EnforcedRoyalty(collection));
+                if let Some(index) = index {
+                    collections.remove(index);
+                }
+            }
+        }
+
+        env.storage().instance().set(&DataKey::EnforcedCollections, &collections);
+    }
+
+    pub fn enforced_royalty(env: Env, collection: Address) -> Option<RoyaltyPolicy> {
+        env.storage().instance().get(&DataKey::EnforcedRoyalty(collection))
+    }
+
+    pub fn enforced_collections(env: Env) -> Vec<Address> {
+        env.storage().instance().get(&DataKey::EnforcedCollections).unwrap_or(Vec::new(&env))
+    }
+
+    // Pays the seller from the payer, taking the royalty leg first when the
+    // collection enforces one. A sale whose royalty rounds down to nothing
+    // is refused rather than settled without it.
+    fn pay_sale(
+        env: Env,
+        collection: Address,
+        token_id: u128,
+        payer: Address,
+        seller: Address,
+        price: i128,
+        payment_token: Address,
+    ) {
+        let client = token::Client::new(&env, &payment_token);
+        let mut seller_amount = price;
+
+        if let Some(policy) = Self::enforced_royalty(env.clone(), collection.clone()) {
+            let royalty = price.checked_mul(policy.bps as i128).expect("Royalty overflows") / MAX_BPS as i128;
+
+            if royalty <= 0 {
+                panic!("Royalty leg can not execute")
+            }
+
+            client.transfer(&payer, &policy.recipient, &royalty);
+            seller_amount -= royalty;
+
+            let marketplace_client = NFTMarketplaceClient::new(&env, &collection);
+            if nft_marketplace::is_registered(&env, &collection, &env.current_contract_address()) {
+                marketplace_client.record_royalty(
+                    &env.current_contract_address(),
+                    &token_id,
+                    &policy.recipient,
+                    &payment_token,
+                    &royalty,
+                );
+            }
+        }
+
+        client.transfer(&payer, &seller, &seller_amount);
+    }
+
+    pub fn set_paused(env: Env, admin: Address, paused: bool) {
+        Self::check_admin(env.clone(), admin);
+
+        env.storage().instance().set(&DataKey::Paused, &paused);
+    }
+
+    pub fn paused(env: Env) -> bool {
+        env.storage().instance().get(&DataKey::Paused).unwrap_or(false)
+    }
+
+    pub fn set_circuit_breaker(env: Env, admin: Address, max_daily_volume: i128, max_daily_sales: u32) {
+        Self::check_admin(env.clone(), admin);
+
+        if max_daily_volume <= 0 || max_daily_sales == 0 {
+            panic!("Limits must be positive")
+        }
+
+        let breaker = CircuitBreaker {
+            max_daily_volume,
+            max_daily_sales,
+        };
+        env.storage().instance().set(&DataKey::CircuitBreaker, &breaker);
+    }
+
+    pub fn clear_circuit_breaker(env: Env, admin: Address) {
+        Self::check_admin(env.clone(), admin);
+
+        env.storage().instance().remove(&DataKey::CircuitBreaker);
+    }
+
+    pub fn get_circuit_breaker(env: Env) -> Option<CircuitBreaker> {
+        env.storage().instance().get(&DataKey::CircuitBreaker)
+    }
+
+    // Volume and sales count of today across every collection
+    pub fn platform_stats(env: Env) -> SaleStats {
+        let day = env.ledger().timestamp() / DAY_IN_SECONDS;
+
+        Self::get_stats(env, DataKey::PlatformStats(day))
+    }
+
+    fn check_trading(env: Env, price: i128) {
+        if Self::paused(env.clone()) {
+            panic!("Storefront is paused")
+        }
+
+        if let Some(breaker) = Self::get_circuit_breaker(env.clone()) {
+            let stats = Self::platform_stats(env);
+
+            let volume = stats.volume.checked_add(price).expect("Volume overflows");
+
+            if stats.sales >= breaker.max_daily_sales || volume > breaker.max_daily_volume {
+                panic!("Daily sales limit reached")
+            }
+        }
+    }
+
+    pub fn set_listing_registry(env: Env, admin: Address, registry: Address) {
+        Self::check_admin(env.clone(), admin);
+
+        env.storage().instance().set(&DataKey::ListingRegistry, &registry);
+    }
+
+    fn lock_listing(env: Env, token_id: u128) {
+        if let Some(registry) = env.storage().instance().get::<DataKey, Address>(&DataKey::ListingRegistry) {
+            let registry_client = ListingRegistryClient::new(&env, &registry);
+            let collection = Self::get_nft_client(env.clone()).address;
+            registry_client.lock(&env.current_contract_address(), &collection, &token_id);
+        }
+    }
+
+    fn unlock_listing(env: Env, token_id: u128) {
+        if let Some(registry) = env.storage().instance().get::<DataKey, Address>(&DataKey::ListingRegistry) {
+            let registry_client = ListingRegistryClient::new(&env, &registry);
+            let collection = Self::get_nft_client(env.clone()).address;
+            registry_client.unlock(&env.current_contract_address(), &collection, &token_id);
+        }
+    }
+
+    // Prefixes the event data with the storefront's running event nonce
+    fn publish_event<T: Topics, D: IntoVal<Env, Val>>(env: Env, topics: T, data: D) {
+        let event_nonce = Self::event_nonce(env.clone()) + 1;
+
+        env.storage().instance().set(&DataKey::EventNonce, &event_nonce);
+        env.events().publish(topics, (event_nonce, data));
+    }
+
+    // Nonce of the last published event
+    pub fn event_nonce(env: Env) -> u64 {
+        env.storage().instance().get(&DataKey::EventNonce).unwrap_or(0)
+    }
+
+    fn apply_resale_lock(env: Env, collection: &Address, token_id: u128) {
+        if let Some(lock_duration) = Self::resale_lock(env.clone(), token_id) {
+            env.storage().instance().remove(&DataKey::ResaleLock(token_id));
+
+            let until = env.ledger().timestamp() + lock_duration;
+            NFTMarketplaceClient::new(&env, collection).marketplace_lock(&env.current_contract_address(), &token_id, &until);
+        }
+    }
+
+    // Re-reports a listing to the collection's floor price view. Anyone can
+    // call it, decaying prices and scheduled starts change without a
+    // transaction of their own.
+    pub fn refresh_listing_report(env: Env, token_id: u128) {
+        if Self::listing_state(env.clone(), token_id) == ListingState::None {
+            panic!("NFT not listed")
+        }
+
+        Self::sync_listing_report(env, token_id);
+    }
+
+    // Reports the current price of a listing that can be bought now, and
+    // takes pending and upcoming listings off the floor
+    fn sync_listing_report(env: Env, token_id: u128) {
+        let price = match Self::listing_state(env.clone(), token_id) {
+            ListingState::Active => Some(Self::price_at(env.clone(), token_id, env.ledger().timestamp())),
+            _ => None,
+        };
+
+        Self::report_listing(env, token_id, price);
+    }
+
+    // Feeds the collection's floor price view once the storefront is
+    // registered as a marketplace with the NFT contract
+    fn report_listing(env: Env, token_id: u128, price: Option<i128>) {
+        let collection = Self::get_nft_client(env.clone()).address;
+        let marketplace_client = NFTMarketplaceClient::new(&env, &collection);
+
+        if nft_marketplace::is_registered(&env, &collection, &env.current_contract_address()) {
+            marketplace_client.report_listing(&env.current_contract_address(), &token_id, &price);
+        }
+    }
+
+    fn check_admin(env: Env, admin: Address) {
+        admin.require_auth();
+
+        if admin != Self::read_administrator(env) {
+            panic!("Only admin can perform this action")
+        }
+    }
+
+    fn read_administrator(env: Env) -> Address {
+        env.storage().instance().get(&DataKey::Admin).unwrap()
+    }
+
+    fn has_administrator(env: Env) -> bool {
+        let key = DataKey::Admin;
+        env.storage().instance().has(&key)
+    }
+
+    // Hands a sold token to the buyer, as a marketplace sale when the
+    // collection registered this contract so its cooldown exemption applies
+    fn transfer_sold_nft(env: Env, nft_client: &nft_contract::Client, from: &Address, to: &Address, token_id: u128) {
+        let marketplace_client = NFTMarketplaceClient::new(&env, &nft_client.address);
+
+        if nft_marketplace::is_registered(&env, &nft_client.address, &env.current_contract_address()) {
+            marketplace_client.marketplace_transfer(&env.current_contract_address(), from, to, &token_id);
+        } else {
+            nft_client.transfer_from(from, to, &token_id);
+        }
+    }
+
+    fn get_nft_client(env: Env) -> nft_contract::Client<'static> {
+        let contract = env
+            .storage()
+            .instance()
+            .get::<DataKey, Address>(&DataKey::NFTAddress)
+            .expect("none");
+
+        let client = nft_contract::Client::new(&env, &contract);
+
+        return client;
+    }
+}
+
+#[cfg(test)]
 mod test;
 
+#[cfg(test)]
+mod nft_mock;
+
+#[cfg(test)]
+mod marketplace_testutils;
+
+#[cfg(test)]
+mod storefront_lifecycle;
+
+#[cfg(test)]
+mod storefront_budget;
+
 mod testutils;
@@ -0,0 +1,42 @@
+use soroban_sdk::{testutils::Address as _, token, Address, Env};
+
+use crate::nft_mock::{MockNft, MockNftClient};
+use crate::{NFTStoreFront, NFTStoreFrontClient};
+
+// Host budget one purchase may use. A change that needs more should raise
+// the ceiling in the same commit rather than slip past unnoticed.
+const PURCHASE_CPU_LIMIT: u64 = 10_000_000;
+const PURCHASE_MEMORY_LIMIT: u64 = 1_500_000;
+
+#[test]
+fn purchase_stays_within_budget() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let seller = Address::generate(&env);
+    let buyer = Address::generate(&env);
+
+    let nft = MockNftClient::new(&env, &env.register_contract(None, MockNft));
+    nft.initialize(&admin);
+    let token_id = nft.mint(&seller);
+
+    let token = env.register_stellar_asset_contract(admin.clone());
+    token::StellarAssetClient::new(&env, &token).mint(&buyer, &1_000);
+
+    let storefront = NFTStoreFrontClient::new(&env, &env.register_contract(None, NFTStoreFront));
+    storefront.initialize(&nft.address, &admin);
+    storefront.list_nft(&seller, &token_id, &500);
+
+    env.budget().reset_default();
+    storefront.purchase_listed_nft(&seller, &buyer, &token_id, &500, &token, &0);
+
+    let cpu = env.budget().cpu_instruction_cost();
+    let memory = env.budget().memory_bytes_cost();
+
+    assert!(cpu <= PURCHASE_CPU_LIMIT, "cpu cost {} exceeds {}", cpu, PURCHASE_CPU_LIMIT);
+    assert!(memory <= PURCHASE_MEMORY_LIMIT, "memory cost {} exceeds {}", memory, PURCHASE_MEMORY_LIMIT);
+    assert_eq!(nft.owner_of(&token_id), buyer);
+    assert_eq!(token::Client::new(&env, &token).balance(&seller), 500);
+}
@@ -0,0 +1,81 @@
+use crate::marketplace_testutils::Marketplace;
+use crate::{NFTStoreFront, NFTStoreFrontClient, RoyaltyPolicy};
+
+const PRICE: i128 = 1_000;
+const ROYALTY_BPS: u32 = 500;
+
+fn storefront<'a>(marketplace: &Marketplace) -> NFTStoreFrontClient<'a> {
+    let env = &marketplace.env;
+    let storefront = NFTStoreFrontClient::new(env, &env.register_contract(None, NFTStoreFront));
+    storefront.initialize(&marketplace.nft.address, &marketplace.admin);
+
+    let policy = RoyaltyPolicy {
+        recipient: marketplace.creator.clone(),
+        bps: ROYALTY_BPS,
+    };
+    storefront.set_royalty_enforcement(&marketplace.nft.address, &Some(policy));
+
+    storefront
+}
+
+// Mint, list and buy, with the enforced royalty taken out of the sale
+#[test]
+fn listed_sale_pays_seller_and_creator() {
+    let marketplace = Marketplace::setup();
+    let storefront = storefront(&marketplace);
+    let seller = marketplace.account(0);
+    let buyer = marketplace.account(PRICE);
+    let token_id = marketplace.mint(&seller);
+
+    storefront.list_nft(&seller, &token_id, &PRICE);
+    storefront.purchase_listed_nft(&seller, &buyer, &token_id, &PRICE, &marketplace.token.address, &0);
+
+    assert_eq!(marketplace.owner_of(token_id), buyer);
+    assert_eq!(marketplace.balance(&buyer), 0);
+    assert_eq!(marketplace.balance(&seller), 950);
+    assert_eq!(marketplace.balance(&marketplace.creator), 50);
+    assert_eq!(marketplace.balance(&storefront.address), 0);
+}
+
+// The buyer relists and the royalty is owed again on the resale
+#[test]
+fn resale_pays_royalty_again() {
+    let marketplace = Marketplace::setup();
+    let storefront = storefront(&marketplace);
+    let seller = marketplace.account(0);
+    let buyer = marketplace.account(PRICE);
+    let collector = marketplace.account(2 * PRICE);
+    let token_id = marketplace.mint(&seller);
+
+    storefront.list_nft(&seller, &token_id, &PRICE);
+    storefront.purchase_listed_nft(&seller, &buyer, &token_id, &PRICE, &marketplace.token.address, &0);
+
+    storefront.list_nft(&buyer, &token_id, &(2 * PRICE));
+    storefront.purchase_listed_nft(&buyer, &collector, &token_id, &(2 * PRICE), &marketplace.token.address, &0);
+
+    assert_eq!(marketplace.owner_of(token_id), collector);
+    assert_eq!(marketplace.balance(&buyer), 1_900);
+    assert_eq!(marketplace.balance(&marketplace.creator), 150);
+}
+
+// A listing left behind by a holder who moved the token elsewhere cannot
+// be bought from the new holder
+#[test]
+fn listing_by_previous_owner_cannot_be_bought() {
+    let marketplace = Marketplace::setup();
+    let storefront = storefront(&marketplace);
+    let seller = marketplace.account(0);
+    let holder = marketplace.account(0);
+    let buyer = marketplace.account(PRICE);
+    let token_id = marketplace.mint(&seller);
+
+    storefront.list_nft(&seller, &token_id, &PRICE);
+    marketplace.nft.transfer_from(&seller, &holder, &token_id);
+
+    let result =
+        storefront.try_purchase_listed_nft(&holder, &buyer, &token_id, &PRICE, &marketplace.token.address, &0);
+
+    assert!(result.is_err());
+    assert_eq!(marketplace.owner_of(token_id), holder);
+    assert_eq!(marketplace.balance(&buyer), PRICE);
+}
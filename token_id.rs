@@ -0,0 +1,22 @@
+// Token ids are issued from 1 and never exceed u64. The contract ABIs and
+// storage keys keep u128 for existing integrations, so this only validates
+// the ids coming in by converting them.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord)]
+pub struct TokenId(pub u64);
+
+impl TokenId {
+    pub fn new(raw: u128) -> Option<TokenId> {
+        if raw == 0 || raw > u64::MAX as u128 {
+            None
+        } else {
+            Some(TokenId(raw as u64))
+        }
+    }
+
+    pub fn from_u128(raw: u128) -> TokenId {
+        match TokenId::new(raw) {
+            Some(token_id) => token_id,
+            None => panic!("Invalid token id"),
+        }
+    }
+}